@@ -0,0 +1,7 @@
+// Material lighting up models with the band under them, and flashing on beats
+void main() {
+    float level = band(int(gl_FragCoord.x / audio.resolution.x * BAND_COUNT));
+    vec3 glow = vec3(level * level, 0.3 * level, 0.0) + 0.3 * audio.beat;
+    color = vec4(in_color + glow, 1.0);
+    normal = in_normal;
+}
@@ -0,0 +1,7 @@
+// Effect darkening the edges of the frame, less so when the sound gets loud
+void main() {
+    vec2 uv = gl_FragCoord.xy / audio.resolution;
+    float edge = length(uv - 0.5) * 1.4;
+    float strength = smoothstep(0.4, 1.0, edge) * (1.0 - 0.5 * audio.loudness);
+    f_color = vec4(0.0, 0.0, 0.0, strength);
+}
@@ -0,0 +1,20 @@
+// A Shadertoy-style scene: the spectrum as glowing bars over the waveform,
+// which can be pasted into Shadertoy as is with a microphone or music input
+void mainImage(out vec4 fragColor, in vec2 fragCoord) {
+    vec2 uv = fragCoord / iResolution.xy;
+
+    // 64 bars, each showing the level of the spectrum at its left edge
+    float bars = 64.0;
+    float bar = floor(uv.x * bars) / bars;
+    float level = texture(iChannel0, vec2(bar, 0.25)).x;
+    float inside = step(uv.y, level) * step(0.1, fract(uv.x * bars));
+    vec3 color = inside * mix(vec3(0.1, 0.3, 1.0), vec3(1.0, 0.2, 0.4), uv.y);
+
+    // The waveform as a thin line around the middle of the frame
+    float wave = texture(iChannel0, vec2(uv.x, 0.75)).x;
+    color += vec3(1.0) * smoothstep(0.01, 0.0, abs(wave - uv.y));
+
+    // Slowly moving background
+    color += 0.05 * vec3(0.5 + 0.5 * sin(iTime + uv.xyx * 3.0));
+    fragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,308 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::audio::loudness::{self, LoudnessBlocks, BLOCK_STEP};
+use crate::audio::signal_processing::{DECIBEL_FLOOR, OnsetDetector, Spectrum, SpectrumAnalyzer, TempoEstimator};
+use crate::Sound;
+
+/// Bumped whenever the analysis changes, so that outdated cached ones are ignored
+const ANALYSIS_VERSION: u32 = 1;
+/// Length of the chunks features are averaged over when looking for sections
+const SECTION_STEP: Duration = Duration::from_secs(1);
+/// Number of section steps compared on each side of a potential boundary,
+/// which is also the shortest a section can be
+const SECTION_CONTEXT: usize = 8;
+/// Range of frequencies pitches are detected in, in Hz
+const PITCH_RANGE: (f32, f32) = (55.0, 5000.0);
+
+/// Krumhansl-Kessler key profiles, how much each pitch class starting from the
+/// tonic fits in a major and in a minor key
+const MAJOR_PROFILE: [f32; 12] = [6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88];
+const MINOR_PROFILE: [f32; 12] = [6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17];
+const PITCH_CLASSES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// A musical key
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Key {
+    /// Pitch class of the tonic, 0 being C and 11 being B
+    pub tonic: u8,
+    pub minor: bool
+}
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = if self.minor { "minor" } else { "major" };
+        write!(f, "{} {}", PITCH_CLASSES[self.tonic as usize % 12], mode)
+    }
+}
+
+/// Attributes of a whole track, too costly to compute while it plays
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct TrackAnalysis {
+    /// Loudness in LUFS of each 400ms block, a new block starting every
+    /// `BLOCK_STEP`
+    pub loudness_curve: Vec<f32>,
+    /// Highest absolute sample value
+    pub peak: f32,
+    /// Root mean square of all samples
+    pub rms: f32,
+    /// Integrated loudness in LUFS, as defined by ITU-R BS.1770
+    pub integrated_loudness: f32,
+    pub key: Option<Key>,
+    /// Estimated tempo, or 0 if unknown
+    pub bpm: f32,
+    /// Times at which a new section starts, such as a chorus or a drop
+    pub sections: Vec<Duration>
+}
+impl TrackAnalysis {
+    /// Decodes and analyses the whole sound, which can take a while
+    pub fn analyse(sound: &Sound) -> Result<Self, String> {
+        let mut spectrum = SpectrumAnalyzer::new(sound.sample_rate(), sound.channel_count())
+            .fft_size(4096)
+            .hop_size(1024);
+        let spectrum_rate = spectrum.spectrum_rate();
+        let mut onsets = OnsetDetector::new(spectrum_rate);
+        let mut tempo = TempoEstimator::new(spectrum_rate);
+        let mut blocks = LoudnessBlocks::new(sound.sample_rate(), sound.channel_count());
+
+        let mut block_mean_squares = vec![];
+        let mut peak = 0.0f32;
+        let mut sum_of_squares = 0.0f64;
+        let mut sample_count = 0usize;
+
+        let mut tempos = vec![];
+        let mut pitch_classes = vec![];
+        let mut chromas = vec![[0.0f32; 12]];
+        let spectrums_per_step = (spectrum_rate * SECTION_STEP.as_secs_f32()).round().max(1.0) as usize;
+        let mut step_spectrums = 0;
+
+        sound.decode(|samples| {
+            for &sample in samples {
+                peak = peak.max(sample.abs());
+                sum_of_squares += (sample * sample) as f64;
+            }
+            sample_count += samples.len();
+
+            blocks.push(samples, |mean_square| block_mean_squares.push(mean_square));
+            spectrum.push(samples, |s| {
+                let onset = onsets.process(s);
+                tempo.process(onsets.flux(), onset);
+
+                if pitch_classes.is_empty() {
+                    pitch_classes = Self::pitch_classes(s);
+                }
+                let chroma = chromas.last_mut().unwrap();
+                for (pitch_class, magnitude) in pitch_classes.iter().zip(&s.magnitudes) {
+                    if let Some(p) = pitch_class {
+                        chroma[*p] += magnitude;
+                    }
+                }
+
+                step_spectrums += 1;
+                if step_spectrums == spectrums_per_step {
+                    step_spectrums = 0;
+                    chromas.push([0.0; 12]);
+                    if tempo.bpm() > 0.0 {
+                        tempos.push(tempo.bpm());
+                    }
+                }
+            });
+        })?;
+
+        // The estimated tempo drifts while the estimator settles and during
+        // breaks, the median gets rid of those moments
+        tempos.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let bpm = tempos.get(tempos.len() / 2).copied().unwrap_or_default();
+
+        let loudness_curve: Vec<f32> = block_mean_squares.iter().map(|&b| loudness::loudness(b)).collect();
+        let sections = Self::sections(&chromas, &loudness_curve);
+
+        Ok(TrackAnalysis {
+            peak,
+            rms: (sum_of_squares / sample_count.max(1) as f64).sqrt() as f32,
+            integrated_loudness: loudness::integrated_loudness(&block_mean_squares),
+            key: Self::key(&chromas),
+            bpm,
+            sections,
+            loudness_curve
+        })
+    }
+
+    /// Returns the analysis of a sound cached on disk, or analyses the sound
+    /// and caches the result
+    pub fn load_or_analyse(sound: &Sound) -> Result<Self, String> {
+        let cache_path = cache_directory()
+            .zip(file_hash(sound.path()).ok())
+            .map(|(directory, hash)| directory.join(format!("{:016x}.ron", hash)));
+
+        if let Some(path) = &cache_path {
+            let cached = fs::read_to_string(path).ok()
+                .and_then(|content| ron::from_str::<CachedAnalysis>(&content).ok())
+                .filter(|cached| cached.version == ANALYSIS_VERSION);
+            if let Some(cached) = cached {
+                return Ok(cached.analysis)
+            }
+        }
+
+        let analysis = Self::analyse(sound)?;
+
+        // Failing to cache only means we will have to analyse again next time
+        if let Some(path) = &cache_path {
+            let cached = CachedAnalysis { version: ANALYSIS_VERSION, analysis };
+            let written = ron::to_string(&cached).map_err(|e| e.to_string())
+                .and_then(|content| {
+                    fs::create_dir_all(path.parent().unwrap()).map_err(|e| e.to_string())?;
+                    fs::write(path, content).map_err(|e| e.to_string())
+                });
+            if let Err(e) = written {
+                eprintln!("could not cache the analysis of {}: {}", sound.path().display(), e);
+            }
+            return Ok(cached.analysis)
+        }
+
+        Ok(analysis)
+    }
+
+    /// Returns the loudness in LUFS around the given time
+    pub fn loudness_at(&self, time: Duration) -> f32 {
+        // Each block ends 4 steps after it starts, but is most representative of its center
+        let index = (time.as_secs_f32() / BLOCK_STEP.as_secs_f32()) as usize;
+        let index = index.saturating_sub(2).min(self.loudness_curve.len().saturating_sub(1));
+        self.loudness_curve.get(index).copied().unwrap_or(DECIBEL_FLOOR)
+    }
+
+    /// Returns the index of the section playing at the given time
+    pub fn section_at(&self, time: Duration) -> usize {
+        self.sections.partition_point(|&start| start <= time)
+    }
+
+    /// Returns when the section following the given time starts, if any
+    pub fn next_section(&self, time: Duration) -> Option<Duration> {
+        self.sections.get(self.section_at(time)).copied()
+    }
+
+    /// Computes the pitch class of each bin of a spectrum, if it lies in a range
+    /// where pitches make sense
+    fn pitch_classes(spectrum: &Spectrum) -> Vec<Option<usize>> {
+        spectrum.frequencies.iter().map(|&f| {
+            if f < PITCH_RANGE.0 || f > PITCH_RANGE.1 { return None }
+            // A4 is 440Hz, and A is the 9th pitch class
+            let semitones = (12.0 * (f / 440.0).log2()).round() as i32;
+            Some((semitones + 9).rem_euclid(12) as usize)
+        }).collect()
+    }
+
+    /// Finds the key whose profile best correlates with the chroma of the track
+    fn key(chromas: &[[f32; 12]]) -> Option<Key> {
+        let mut chroma = [0.0; 12];
+        for c in chromas {
+            chroma.iter_mut().zip(c).for_each(|(total, value)| *total += value);
+        }
+        if chroma.iter().all(|&c| c == 0.0) { return None }
+
+        let mut best = None;
+        for (minor, profile) in [(false, MAJOR_PROFILE), (true, MINOR_PROFILE)] {
+            for tonic in 0..12 {
+                let rotated: Vec<f32> = (0..12).map(|p| profile[(p + 12 - tonic) % 12]).collect();
+                let score = correlation(&chroma, &rotated);
+                if !matches!(best, Some((_, s)) if s >= score) {
+                    best = Some((Key { tonic: tonic as u8, minor }, score));
+                }
+            }
+        }
+        best.map(|(key, _)| key)
+    }
+
+    /// Looks for the moments where the harmony or the loudness of the track
+    /// changes the most, comparing what comes before and after each step
+    fn sections(chromas: &[[f32; 12]], loudness_curve: &[f32]) -> Vec<Duration> {
+        let blocks_per_step = (SECTION_STEP.as_secs_f32() / BLOCK_STEP.as_secs_f32()).round() as usize;
+        let features: Vec<Vec<f32>> = chromas.iter().enumerate().map(|(i, chroma)| {
+            let norm = chroma.iter().map(|c| c * c).sum::<f32>().sqrt().max(f32::EPSILON);
+            let mut feature: Vec<f32> = chroma.iter().map(|c| c / norm).collect();
+
+            let blocks = loudness_curve.iter().skip(i * blocks_per_step).take(blocks_per_step);
+            let loudness = blocks.clone().sum::<f32>() / blocks.count().max(1) as f32;
+            // Loudness goes from silence to full scale in about the same range as chromas
+            feature.push((loudness + 70.0).max(0.0) / 35.0);
+            feature
+        }).collect();
+
+        if features.len() < 2 * SECTION_CONTEXT { return vec![] }
+
+        let mean_of = |range: &[Vec<f32>]| -> Vec<f32> {
+            let mut mean = vec![0.0; range[0].len()];
+            range.iter().for_each(|f| mean.iter_mut().zip(f).for_each(|(m, v)| *m += v / range.len() as f32));
+            mean
+        };
+        let novelty: Vec<f32> = (SECTION_CONTEXT..=features.len() - SECTION_CONTEXT).map(|i| {
+            let before = mean_of(&features[i - SECTION_CONTEXT..i]);
+            let after = mean_of(&features[i..i + SECTION_CONTEXT]);
+            before.iter().zip(&after).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt()
+        }).collect();
+
+        let mean = novelty.iter().sum::<f32>() / novelty.len() as f32;
+        let deviation = (novelty.iter().map(|n| (n - mean) * (n - mean)).sum::<f32>() / novelty.len() as f32).sqrt();
+
+        // Keep the most novel steps first, as long as they are far enough from
+        // the boundaries we already have
+        let mut candidates: Vec<usize> = (0..novelty.len()).filter(|&i| novelty[i] > mean + deviation).collect();
+        candidates.sort_by(|&a, &b| novelty[b].partial_cmp(&novelty[a]).unwrap());
+        let mut boundaries: Vec<usize> = vec![];
+        for candidate in candidates {
+            if boundaries.iter().all(|&b| b.abs_diff(candidate) >= SECTION_CONTEXT) {
+                boundaries.push(candidate);
+            }
+        }
+        boundaries.sort_unstable();
+
+        boundaries.into_iter()
+            .map(|i| SECTION_STEP * (i + SECTION_CONTEXT) as u32)
+            .collect()
+    }
+}
+
+/// What is written to the cache, so that outdated analyses can be told apart
+#[derive(Serialize, Deserialize)]
+struct CachedAnalysis {
+    version: u32,
+    analysis: TrackAnalysis
+}
+
+/// Directory analyses are cached in, following the XDG base directory specification
+fn cache_directory() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|directory| directory.join("synesthesia"))
+}
+
+/// Hashes the content of a file using 64 bits FNV-1a, so that cached analyses
+/// follow files around and are invalidated when they change
+fn file_hash(path: &Path) -> Result<u64, String> {
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let mut buffer = vec![0; 1 << 16];
+    let mut hash = 0xcbf29ce484222325u64;
+    loop {
+        let read = file.read(&mut buffer).map_err(|e| e.to_string())?;
+        if read == 0 { return Ok(hash) }
+        for &byte in &buffer[..read] {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// Pearson correlation coefficient of two sequences of the same length
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let mean_a = a.iter().sum::<f32>() / a.len() as f32;
+    let mean_b = b.iter().sum::<f32>() / b.len() as f32;
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        variance_a += (x - mean_a) * (x - mean_a);
+        variance_b += (y - mean_b) * (y - mean_b);
+    }
+    covariance / (variance_a * variance_b).sqrt().max(f32::EPSILON)
+}
@@ -0,0 +1,162 @@
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use cpal::{Sample, SampleFormat, Stream};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crate::audio::{AnalysisControls, Analyzer, RealtimeAttributes};
+use crate::Sound;
+
+/// Number of frames handed to the analysis at once by synthetic sources
+const SYNTHETIC_CHUNK_FRAMES: usize = 1024;
+/// Time it takes generated clicks to fade by about two thirds
+const CLICK_DECAY: Duration = Duration::from_millis(5);
+
+/// Where captured samples come from
+pub enum CaptureSource {
+    /// The default input device of the host, usually a microphone or line-in
+    Device,
+    /// A mono sine wave of the given frequency, in Hz
+    Sine { frequency: f32, sample_rate: u32 },
+    /// Short bursts of noise at the given tempo, in BPM
+    Clicks { bpm: f32, sample_rate: u32 },
+    /// The samples of a sound file, delivered at the pace they would be captured
+    /// at without ever being played back
+    File(Sound)
+}
+
+/// A running capture, stopped when dropped
+pub enum Capture {
+    Device(Stream),
+    Synthetic(Arc<AtomicBool>)
+}
+impl Capture {
+    /// Starts capturing samples from the given source, feeding them to the
+    /// realtime attributes
+    pub fn start(
+        source: CaptureSource,
+        rta: Arc<Mutex<RealtimeAttributes>>,
+        controls: Arc<AnalysisControls>
+    ) -> Result<Self, String> {
+        match source {
+            CaptureSource::Device => Self::start_device(rta, controls),
+            CaptureSource::Sine { frequency, sample_rate } => {
+                let mut phase = 0.0f32;
+                let step = 2.0 * PI * frequency / sample_rate as f32;
+                Ok(Self::start_synthetic(sample_rate, 1, rta, controls, move |data| {
+                    for sample in data.iter_mut() {
+                        *sample = phase.sin();
+                        phase = (phase + step) % (2.0 * PI);
+                    }
+                    true
+                }))
+            },
+            CaptureSource::Clicks { bpm, sample_rate } => {
+                let period = (60.0 * sample_rate as f32 / bpm.max(1.0)) as usize;
+                let decay = (-1.0 / (CLICK_DECAY.as_secs_f32() * sample_rate as f32)).exp();
+                let mut until_click = 0;
+                let mut envelope = 0.0f32;
+                Ok(Self::start_synthetic(sample_rate, 1, rta, controls, move |data| {
+                    for sample in data.iter_mut() {
+                        if until_click == 0 {
+                            envelope = 1.0;
+                            until_click = period.max(1);
+                        }
+                        until_click -= 1;
+                        *sample = envelope * (rand::random::<f32>() * 2.0 - 1.0);
+                        envelope *= decay;
+                    }
+                    true
+                }))
+            },
+            CaptureSource::File(sound) => {
+                let mut samples = sound.stream()?;
+                let stream_handle = samples.handle();
+                Ok(Self::start_synthetic(sound.sample_rate(), sound.channel_count(), rta, controls, move |data| {
+                    let written = samples.read(data);
+                    data[written..].iter_mut().for_each(|s| *s = 0.0);
+                    !stream_handle.finished()
+                }))
+            }
+        }
+    }
+
+    fn start_device(rta: Arc<Mutex<RealtimeAttributes>>, controls: Arc<AnalysisControls>) -> Result<Self, String> {
+        let host = cpal::default_host();
+        let device = host.default_input_device().ok_or("no input device available")?;
+        let supported_config = device.default_input_config().map_err(|e| e.to_string())?;
+        let config = supported_config.config();
+
+        let stream = match supported_config.sample_format() {
+            SampleFormat::F32 => Self::build_device_stream::<f32>(&device, &config, rta, controls),
+            SampleFormat::I16 => Self::build_device_stream::<i16>(&device, &config, rta, controls),
+            SampleFormat::U16 => Self::build_device_stream::<u16>(&device, &config, rta, controls)
+        }?;
+        stream.play().map_err(|e| e.to_string())?;
+
+        Ok(Capture::Device(stream))
+    }
+
+    fn build_device_stream<T: Sample>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        rta: Arc<Mutex<RealtimeAttributes>>,
+        controls: Arc<AnalysisControls>
+    ) -> Result<Stream, String> {
+        let samples_per_second = config.sample_rate.0 as f64 * config.channels as f64;
+        let mut captured = 0usize;
+        let mut buffer = vec![];
+        let mut analyzer = Analyzer::new(config.sample_rate.0, config.channels as usize, controls);
+        device.build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                buffer.clear();
+                buffer.extend(data.iter().map(|s| s.to_f32()));
+                captured += data.len();
+                analyzer.analyse(&buffer, Duration::from_secs_f64(captured as f64 / samples_per_second), &rta);
+            },
+            move |e| eprintln!("audio input error: {:?}", e)
+        ).map_err(|e| e.to_string())
+    }
+
+    /// Spawns a thread which periodically asks the generator for a new chunk
+    /// of interleaved samples, until it returns false
+    fn start_synthetic<G: FnMut(&mut [f32]) -> bool + Send + 'static>(
+        sample_rate: u32,
+        channels: usize,
+        rta: Arc<Mutex<RealtimeAttributes>>,
+        controls: Arc<AnalysisControls>,
+        mut generator: G
+    ) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let inner_stopped = stopped.clone();
+        thread::spawn(move || {
+            let chunk_duration = Duration::from_secs_f64(SYNTHETIC_CHUNK_FRAMES as f64 / sample_rate as f64);
+            let mut buffer = vec![0.0; SYNTHETIC_CHUNK_FRAMES * channels];
+            let mut analyzer = Analyzer::new(sample_rate, channels, controls);
+            let start = Instant::now();
+            let mut timestamp = Duration::ZERO;
+            while !inner_stopped.load(Ordering::Acquire) {
+                let more = generator(&mut buffer);
+                timestamp += chunk_duration;
+                analyzer.analyse(&buffer, timestamp, &rta);
+                if !more { break }
+
+                // Deliver chunks at the pace a real device would
+                if let Some(wait) = (start + timestamp).checked_duration_since(Instant::now()) {
+                    thread::sleep(wait);
+                }
+            }
+        });
+
+        Capture::Synthetic(stopped)
+    }
+}
+impl Drop for Capture {
+    fn drop(&mut self) {
+        if let Capture::Synthetic(stopped) = self {
+            stopped.store(true, Ordering::Release);
+        }
+    }
+}
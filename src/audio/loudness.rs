@@ -0,0 +1,365 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::time::Duration;
+use crate::audio::signal_processing::DECIBEL_FLOOR;
+
+/// Time between two consecutive blocks
+pub const BLOCK_STEP: Duration = Duration::from_millis(100);
+/// Number of steps a block lasts, blocks being 400ms long
+const BLOCK_STEPS: usize = 4;
+/// Number of steps the short-term loudness is measured over, 3s
+const SHORT_TERM_STEPS: usize = 30;
+/// Oversampling factor used to find peaks between samples
+const OVERSAMPLING: usize = 4;
+/// Number of input samples each interpolated sample depends on
+const INTERPOLATION_TAPS: usize = 12;
+/// Lowest and highest loudness kept track of by the integrated loudness, in LUFS
+const HISTOGRAM_RANGE: (f32, f32) = (-70.0, 10.0);
+/// Resolution of the integrated loudness, in LU
+const HISTOGRAM_RESOLUTION: f32 = 0.1;
+/// Blocks quieter than this are ignored by the integrated loudness, in LUFS
+const ABSOLUTE_GATE: f32 = -70.0;
+/// Blocks this much quieter than the ungated loudness are ignored by the
+/// integrated loudness, in LU
+const RELATIVE_GATE: f32 = -10.0;
+
+/// Second order IIR filter, in transposed direct form II
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2]
+}
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.state[0];
+        self.state[0] = self.b[1] * x - self.a[0] * y + self.state[1];
+        self.state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The K-weighting filter of ITU-R BS.1770, roughly modelling how loud each
+/// frequency sounds to us, for a single channel
+#[derive(Clone, Copy)]
+pub struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad
+}
+impl KWeighting {
+    /// Adapts the filters of the standard, given at 48kHz, to any sample rate
+    pub fn new(sample_rate: u32) -> Self {
+        let rate = sample_rate as f64;
+
+        // High shelf boosting frequencies above ~1.5kHz by 4dB, modelling the head
+        let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad {
+            b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            state: [0.0; 2]
+        };
+
+        // High pass removing frequencies below ~40Hz, which we barely hear
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            state: [0.0; 2]
+        };
+
+        KWeighting { shelf, high_pass }
+    }
+
+    pub fn process(&mut self, sample: f32) -> f64 {
+        self.high_pass.process(self.shelf.process(sample as f64))
+    }
+}
+
+/// Splits a stream of interleaved samples into overlapping 400ms blocks, and
+/// computes the mean square of their K-weighted samples
+pub struct LoudnessBlocks {
+    filters: Vec<KWeighting>,
+    step_frames: usize,
+
+    /// Sum of the squares of each of the last steps, enough of them for a
+    /// short-term measurement
+    steps: VecDeque<f64>,
+    current_step: f64,
+    frames: usize,
+    /// Samples of an incomplete frame left over from the last push
+    partial_frame: Vec<f32>
+}
+impl LoudnessBlocks {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        LoudnessBlocks {
+            filters: vec![KWeighting::new(sample_rate); channels.max(1)],
+            step_frames: ((sample_rate as f64 * BLOCK_STEP.as_secs_f64()) as usize).max(1),
+
+            steps: VecDeque::new(),
+            current_step: 0.0,
+            frames: 0,
+            partial_frame: vec![]
+        }
+    }
+
+    /// Feeds interleaved samples, calling `on_block` with the mean square of
+    /// every block completed in the process
+    pub fn push<F: FnMut(f64)>(&mut self, samples: &[f32], mut on_block: F) {
+        for &sample in samples {
+            self.partial_frame.push(sample);
+            if self.partial_frame.len() < self.filters.len() { continue }
+
+            // Every channel is weighted the same, which is right for anything
+            // but the surround channels of 5.1 sound
+            for (filter, &sample) in self.filters.iter_mut().zip(&self.partial_frame) {
+                let weighted = filter.process(sample);
+                self.current_step += weighted * weighted;
+            }
+            self.partial_frame.clear();
+
+            self.frames += 1;
+            if self.frames == self.step_frames {
+                if self.steps.len() == SHORT_TERM_STEPS {
+                    self.steps.pop_front();
+                }
+                self.steps.push_back(self.current_step);
+                self.current_step = 0.0;
+                self.frames = 0;
+
+                if let Some(block) = self.mean_square(BLOCK_STEPS) {
+                    on_block(block);
+                }
+            }
+        }
+    }
+
+    /// Mean square of the last `steps` steps, if there are that many
+    fn mean_square(&self, steps: usize) -> Option<f64> {
+        if self.steps.len() < steps { return None }
+        Some(self.steps.iter().rev().take(steps).sum::<f64>() / (steps * self.step_frames) as f64)
+    }
+
+    /// Returns the mean square of the last 3 seconds, once there are that many
+    pub fn short_term(&self) -> Option<f64> {
+        self.mean_square(SHORT_TERM_STEPS)
+    }
+}
+
+/// Measures loudness as defined by EBU R128, from a stream of interleaved samples
+pub struct LoudnessMeter {
+    blocks: LoudnessBlocks,
+    channels: usize,
+
+    /// Number of blocks and sum of their mean squares for each loudness
+    /// interval, so that the integrated loudness can be gated without
+    /// keeping every block around
+    histogram: Vec<(usize, f64)>,
+    /// Last samples of each channel, most recent first
+    history: Vec<VecDeque<f32>>,
+    /// Interpolation filter of each oversampling phase
+    phases: Vec<[f32; INTERPOLATION_TAPS]>,
+    /// Samples of an incomplete frame left over from the last push
+    partial_frame: Vec<f32>,
+
+    momentary: f32,
+    short_term: f32,
+    integrated: f32,
+    /// Highest absolute value of the oversampled signal
+    peak: f32
+}
+impl LoudnessMeter {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let channels = channels.max(1);
+        let bins = ((HISTOGRAM_RANGE.1 - HISTOGRAM_RANGE.0) / HISTOGRAM_RESOLUTION) as usize;
+        LoudnessMeter {
+            blocks: LoudnessBlocks::new(sample_rate, channels),
+            channels,
+
+            histogram: vec![(0, 0.0); bins],
+            history: vec![VecDeque::from(vec![0.0; INTERPOLATION_TAPS]); channels],
+            phases: Self::interpolation_phases(),
+            partial_frame: vec![],
+
+            momentary: DECIBEL_FLOOR,
+            short_term: DECIBEL_FLOOR,
+            integrated: DECIBEL_FLOOR,
+            peak: 0.0
+        }
+    }
+
+    /// Splits a Hann windowed sinc into one low pass filter per phase, each
+    /// interpolating the signal at a fraction of a sample
+    fn interpolation_phases() -> Vec<[f32; INTERPOLATION_TAPS]> {
+        // Centering the filter on a sample puts the phases at whole fractions
+        // of a sample, the first one giving back the samples themselves
+        let length = OVERSAMPLING * INTERPOLATION_TAPS;
+        let center = (length / 2) as f64;
+        (0..OVERSAMPLING).map(|phase| {
+            let mut taps = [0.0; INTERPOLATION_TAPS];
+            for (k, tap) in taps.iter_mut().enumerate() {
+                let n = (phase + OVERSAMPLING * k) as f64;
+                let x = (n - center) / OVERSAMPLING as f64;
+                let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+                let window = 0.5 + 0.5 * (2.0 * PI * (n - center) / length as f64).cos();
+                *tap = (sinc * window) as f32;
+            }
+            // Each phase should leave a constant signal untouched
+            let sum: f32 = taps.iter().sum();
+            taps.iter_mut().for_each(|t| *t /= sum);
+            taps
+        }).collect()
+    }
+
+    /// Feeds interleaved samples to the meter
+    pub fn push(&mut self, samples: &[f32]) {
+        let (histogram, integrated) = (&mut self.histogram, &mut self.integrated);
+        let mut momentary = None;
+        self.blocks.push(samples, |block| {
+            momentary = Some(block);
+            let bin = (loudness(block) - HISTOGRAM_RANGE.0) / HISTOGRAM_RESOLUTION;
+            if bin >= 0.0 {
+                let last = histogram.len() - 1;
+                let bin = &mut histogram[(bin as usize).min(last)];
+                bin.0 += 1;
+                bin.1 += block;
+                *integrated = Self::gate(histogram);
+            }
+        });
+        if let Some(block) = momentary {
+            self.momentary = loudness(block);
+        }
+        if let Some(short_term) = self.blocks.short_term() {
+            self.short_term = loudness(short_term);
+        }
+
+        for &sample in samples {
+            self.partial_frame.push(sample);
+            if self.partial_frame.len() < self.channels { continue }
+
+            for (history, &sample) in self.history.iter_mut().zip(&self.partial_frame) {
+                history.pop_back();
+                history.push_front(sample);
+                for taps in &self.phases {
+                    let interpolated: f32 = history.iter().zip(taps).map(|(s, t)| s * t).sum();
+                    self.peak = self.peak.max(interpolated.abs());
+                }
+            }
+            self.partial_frame.clear();
+        }
+    }
+
+    /// Computes the integrated loudness from the histogram of block loudness
+    fn gate(histogram: &[(usize, f64)]) -> f32 {
+        // Blocks under the absolute gate never make it into the histogram
+        let (count, sum) = histogram.iter().fold((0, 0.0), |(c, s), b| (c + b.0, s + b.1));
+        if count == 0 { return DECIBEL_FLOOR }
+
+        let threshold = loudness(sum / count as f64) + RELATIVE_GATE;
+        let first = ((threshold - HISTOGRAM_RANGE.0) / HISTOGRAM_RESOLUTION).max(0.0) as usize;
+        let (count, sum) = histogram.iter().skip(first).fold((0, 0.0), |(c, s), b| (c + b.0, s + b.1));
+        if count == 0 { DECIBEL_FLOOR } else { loudness(sum / count as f64) }
+    }
+
+    /// Loudness of the last 400ms, in LUFS
+    pub fn momentary(&self) -> f32 { self.momentary }
+
+    /// Loudness of the last 3s, in LUFS
+    pub fn short_term(&self) -> f32 { self.short_term }
+
+    /// Loudness of everything measured so far, in LUFS
+    pub fn integrated(&self) -> f32 { self.integrated }
+
+    /// Highest peak of the signal measured so far, including between samples, in dBTP
+    pub fn true_peak(&self) -> f32 {
+        (20.0 * self.peak.log10()).max(DECIBEL_FLOOR)
+    }
+}
+
+/// Converts the mean square of K-weighted samples to LUFS
+pub fn loudness(mean_square: f64) -> f32 {
+    ((-0.691 + 10.0 * mean_square.log10()) as f32).max(DECIBEL_FLOOR)
+}
+
+/// Computes the integrated loudness of a sequence of blocks given by their
+/// mean square, ignoring silent blocks and those much quieter than the rest
+pub fn integrated_loudness(blocks: &[f64]) -> f32 {
+    let gated_mean = |gate: f32| {
+        let kept: Vec<f64> = blocks.iter().copied().filter(|&b| loudness(b) > gate).collect();
+        if kept.is_empty() { None } else { Some(kept.iter().sum::<f64>() / kept.len() as f64) }
+    };
+
+    gated_mean(ABSOLUTE_GATE)
+        .and_then(|mean| gated_mean(ABSOLUTE_GATE.max(loudness(mean) + RELATIVE_GATE)))
+        .map(loudness)
+        .unwrap_or(DECIBEL_FLOOR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// Interleaved samples of a sine played on both channels of a stereo signal
+    fn stereo_sine(frequency: f64, decibels: f64, phase: f64, duration: f64) -> Vec<f32> {
+        let amplitude = 10f64.powf(decibels / 20.0);
+        let frames = (duration * SAMPLE_RATE as f64) as usize;
+        (0..frames).flat_map(|i| {
+            let sample = (amplitude * (2.0 * PI * frequency * i as f64 / SAMPLE_RATE as f64 + phase).sin()) as f32;
+            [sample, sample]
+        }).collect()
+    }
+
+    #[test]
+    fn reference_sines() {
+        // Cases 1 and 2 of EBU Tech 3341: a 997Hz stereo sine reads as many
+        // LUFS as it has dBFS
+        for level in [-23.0, -33.0] {
+            let mut meter = LoudnessMeter::new(SAMPLE_RATE, 2);
+            for chunk in stereo_sine(997.0, level, 0.0, 5.0).chunks(1000) {
+                meter.push(chunk);
+            }
+            for (name, value) in [("momentary", meter.momentary()), ("short-term", meter.short_term()), ("integrated", meter.integrated())] {
+                assert!((value - level as f32).abs() <= 0.1, "{} loudness of {} LUFS at {} dBFS", name, value, level);
+            }
+        }
+    }
+
+    #[test]
+    fn silence_is_gated_out() {
+        // -80 LUFS, quieter than the absolute gate
+        let mut meter = LoudnessMeter::new(SAMPLE_RATE, 2);
+        meter.push(&stereo_sine(997.0, -80.0, 0.0, 2.0));
+        assert!((meter.momentary() + 80.0).abs() <= 0.1);
+        assert_eq!(meter.integrated(), DECIBEL_FLOOR);
+
+        let mut meter = LoudnessMeter::new(SAMPLE_RATE, 2);
+        meter.push(&stereo_sine(997.0, -23.0, 0.0, 5.0));
+        meter.push(&vec![0.0; 10 * 2 * SAMPLE_RATE as usize]);
+        assert_eq!(meter.momentary(), DECIBEL_FLOOR);
+        // Only the 47 blocks of the sine and the 3 overlapping its end, with
+        // 3/4, 1/2 and 1/4 of its power, make it through the gates
+        let expected = -23.0 + 10.0 * (48.5f32 / 50.0).log10();
+        assert!((meter.integrated() - expected).abs() <= 0.1, "integrated loudness of {} LUFS", meter.integrated());
+    }
+
+    #[test]
+    fn true_peak_between_samples() {
+        // Sampled at 45 degrees, a sine at a quarter of the sample rate never
+        // gets sampled higher than its amplitude divided by sqrt(2)
+        let samples = stereo_sine(SAMPLE_RATE as f64 / 4.0, -6.0, PI / 4.0, 1.0);
+        let mut meter = LoudnessMeter::new(SAMPLE_RATE, 2);
+        meter.push(&samples);
+
+        let sample_peak = 20.0 * samples.iter().fold(0.0f32, |p, s| p.max(s.abs())).log10();
+        assert!((sample_peak + 9.01).abs() < 0.01, "sample peak of {} dBFS", sample_peak);
+        let above = meter.true_peak() - sample_peak;
+        assert!((above - 3.01).abs() < 0.2, "true peak {} dB above the sample peak", above);
+    }
+}
@@ -1,28 +1,116 @@
-mod signal_processing;
+pub mod signal_processing;
+pub mod capture;
+pub mod playlist;
+pub mod loudness;
+pub mod analysis;
 
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
 use cpal::{Device, Stream};
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use rustfft::num_complex::Complex;
-use crate::audio::signal_processing::fft;
+use crate::audio::analysis::TrackAnalysis;
+use crate::audio::capture::{Capture, CaptureSource};
+use crate::audio::loudness::LoudnessMeter;
+use crate::audio::playlist::{Playlist, Repeat};
+use crate::audio::signal_processing::{DECIBEL_FLOOR, OnsetDetector, Spectrum, SpectrumAnalyzer, TempoEstimator};
+use crate::resource_pool::sound_loader::{SoundMetadata, SoundStreamHandle};
 use crate::Sound;
 
-#[derive(Default, Clone)]
+/// Highest gain the auto-gain applies, in dB
+const AUTO_GAIN_MAX: f32 = 24.0;
+/// Lowest gain the auto-gain applies, in dB
+const AUTO_GAIN_MIN: f32 = -12.0;
+/// The auto-gain keeps its current gain while the input is quieter than this,
+/// instead of boosting silence, in LUFS
+const AUTO_GAIN_GATE: f32 = -50.0;
+/// Time it takes the auto-gain to get about two thirds of the way to its target
+const AUTO_GAIN_TIME: Duration = Duration::from_secs(1);
+/// Number of samples in the waveform of realtime attributes
+pub const WAVEFORM_LENGTH: usize = 512;
+
+#[derive(Clone)]
 pub struct RealtimeAttributes {
-    pub fft: Vec<Complex<f32>>,
-    pub timestamp: Duration
+    pub spectrum: Spectrum,
+    /// The last mono samples analysed, oldest first
+    pub waveform: Vec<f32>,
+    pub timestamp: Duration,
+    /// Whether a beat occurred since these attributes were last retrieved
+    pub beat: bool,
+    /// Strength of the last onset, in [0, 1]
+    pub onset_strength: f32,
+    /// Estimated tempo, or 0 if unknown
+    pub bpm: f32,
+    /// How far we are between two beats, in [0, 1)
+    pub beat_phase: f32,
+    /// Loudness of the last 400ms, in LUFS
+    pub momentary_loudness: f32,
+    /// Loudness of the last 3s, in LUFS
+    pub short_term_loudness: f32,
+    /// Loudness since the track started, in LUFS
+    pub integrated_loudness: f32,
+    /// Highest peak since the track started, including between samples, in dBTP
+    pub true_peak: f32,
+    /// Gain applied to the samples before analysing them, in dB
+    pub gain: f32
+}
+
+impl Default for RealtimeAttributes {
+    fn default() -> Self {
+        RealtimeAttributes {
+            spectrum: Default::default(),
+            waveform: vec![],
+            timestamp: Duration::ZERO,
+            beat: false,
+            onset_strength: 0.0,
+            bpm: 0.0,
+            beat_phase: 0.0,
+            momentary_loudness: DECIBEL_FLOOR,
+            short_term_loudness: DECIBEL_FLOOR,
+            integrated_loudness: DECIBEL_FLOOR,
+            true_peak: DECIBEL_FLOOR,
+            gain: 0.0
+        }
+    }
 }
 
 #[derive(Default, Clone)]
 pub struct GeneralAttributes {
-    pub duration: Duration
+    pub duration: Duration,
+    /// Index of the track being played in the playlist
+    pub track_index: usize,
+    /// Whether the track changed since these attributes were last retrieved
+    pub track_changed: bool,
+    /// Title of the track, or the name of its file if it is not tagged
+    pub title: String,
+    /// Tags and cover art of the track
+    pub metadata: SoundMetadata,
+    /// Analysis of the whole track, available a while after it started
+    pub analysis: Option<Arc<TrackAnalysis>>
 }
 
 pub struct AudioPlayer {
-    device: Device,
+    device: Option<Device>,
     stream: Option<Stream>,
+    stream_handle: Option<SoundStreamHandle>,
     paused: bool,
+    capture: Option<Capture>,
+    analysis_controls: Arc<AnalysisControls>,
+
+    playlist: Playlist,
+    /// Number of queued tracks of the current stream that we know started
+    tracks_started: u64,
+    /// Number of tracks that could not be opened since one last played
+    tracks_skipped: usize,
+
+    /// Analyses of the tracks we came across, `None` while being computed
+    analyses: HashMap<PathBuf, Option<Arc<TrackAnalysis>>>,
+    analysis_sender: Sender<(PathBuf, Option<Arc<TrackAnalysis>>)>,
+    analysis_receiver: Receiver<(PathBuf, Option<Arc<TrackAnalysis>>)>,
 
     realtime_attributes: Arc<Mutex<RealtimeAttributes>>,
     general_attributes: GeneralAttributes
@@ -30,72 +118,292 @@ pub struct AudioPlayer {
 impl AudioPlayer {
     pub fn new() -> Self {
         let host = cpal::default_host();
-        let device = host.default_output_device().expect("no output device available");
+        // Capturing does not require any output device, so we only complain
+        // about its absence once something is played
+        let device = host.default_output_device();
+        let (analysis_sender, analysis_receiver) = mpsc::channel();
 
         AudioPlayer {
             device,
             stream: None,
+            stream_handle: None,
             paused: true,
+            capture: None,
+            analysis_controls: Default::default(),
+
+            playlist: Default::default(),
+            tracks_started: 0,
+            tracks_skipped: 0,
+
+            analyses: HashMap::new(),
+            analysis_sender,
+            analysis_receiver,
 
             realtime_attributes: Default::default(),
             general_attributes: Default::default(),
         }
     }
 
+    /// Plays a single sound, replacing the playlist
     pub fn play(&mut self, sound: &Sound) {
+        self.play_playlist(Playlist::from(vec![sound.clone()]))
+    }
+
+    /// Replaces the playlist and starts playing its current track
+    pub fn play_playlist(&mut self, playlist: Playlist) {
+        self.playlist = playlist;
+        if let Some(sound) = self.playlist.current().cloned() {
+            self.start(&sound);
+        }
+    }
+
+    /// Adds a sound at the end of the playlist
+    pub fn enqueue(&mut self, sound: &Sound) {
+        self.playlist.push(sound.clone());
+        self.queue_next();
+    }
+
+    /// Stops playback and analyses samples from the given source instead, without
+    /// playing them back
+    pub fn capture(&mut self, source: CaptureSource) {
+        self.stream = None;
+        self.stream_handle = None;
+        self.playlist = Default::default();
+        self.general_attributes = Default::default();
+
+        self.realtime_attributes = Default::default();
+        match Capture::start(source, self.realtime_attributes.clone(), self.analysis_controls.clone()) {
+            Ok(c) => self.capture = Some(c),
+            Err(e) => eprintln!("could not start capturing: {}", e)
+        }
+    }
+
+    /// Opens a new output stream for the given sound
+    fn start(&mut self, sound: &Sound) {
+        let device = match &self.device {
+            Some(d) => d,
+            None => {
+                eprintln!("no output device available");
+                return
+            }
+        };
+        self.capture = None;
+
         let config = cpal::StreamConfig {
             channels: sound.channel_count() as cpal::ChannelCount,
             sample_rate: cpal::SampleRate(sound.sample_rate()),
             buffer_size: cpal::BufferSize::Default
         };
 
-        let mut samples = sound.samples().into_iter();
-
-        // ------------
-        // Here we would calculate the general attributes,
-        // tedious operations which should never be done
-        // in real time
-        // -------------
-        self.general_attributes = Default::default();
-        self.general_attributes.duration = sound.duration();
+        let mut samples = match sound.stream() {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("could not play {}: {}", sound.path().display(), e);
+                self.skip_unplayable();
+                return
+            }
+        };
+        self.tracks_skipped = 0;
+        let stream_handle = samples.handle();
 
         self.realtime_attributes = Default::default();
         let inner_rta = self.realtime_attributes.clone();
-        let play_start = Instant::now();
-        let stream = self.device.build_output_stream(
+        let inner_stream_handle = stream_handle.clone();
+        let mut analyzer = Analyzer::new(sound.sample_rate(), sound.channel_count(), self.analysis_controls.clone());
+        let stream = device.build_output_stream(
             &config,
             move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
                 // Fill the buffer with as much samples as we can
-                let written = std::cmp::min(data.len(), samples.len());
-                for sample in data[..written].iter_mut() {
-                    *sample = samples.next().unwrap();
-                }
-
+                let written = samples.read(data);
                 data[written..].iter_mut().for_each(|s| *s = 0.0);
 
-                let mut rta = inner_rta.lock().unwrap();
-                (*rta).fft = fft(&data[..written]);
-                (*rta).timestamp = play_start.elapsed();
-                drop(rta)
+                analyzer.analyse(&data[..written], inner_stream_handle.position(), &inner_rta);
             },
             move |e| eprintln!("audio output error: {:?}", e)
         );
 
-        if let Ok(s) = stream {
-            self.stream = Some(s);
+        match stream {
+            Ok(s) => {
+                self.stream = Some(s);
+                self.stream_handle = Some(stream_handle);
+                self.tracks_started = 0;
+            },
+            Err(e) => {
+                // Keeping the previous stream would carry on with the last track
+                eprintln!("could not open the output stream: {}", e);
+                self.stream = None;
+                self.stream_handle = None;
+                self.paused = true;
+                return
+            }
         }
 
+        self.track_changed(sound);
+        self.queue_next();
         self.resume();
         self.paused = false;
     }
 
+    /// Moves on from a track that could not be opened, giving up once as
+    /// many tracks as the playlist holds failed in a row
+    fn skip_unplayable(&mut self) {
+        self.stream = None;
+        self.stream_handle = None;
+        self.paused = true;
+
+        self.tracks_skipped += 1;
+        if self.tracks_skipped >= self.playlist.len() {
+            self.tracks_skipped = 0;
+            return
+        }
+        match self.playlist.advance().cloned() {
+            Some(sound) => self.start(&sound),
+            None => self.tracks_skipped = 0
+        }
+    }
+
+    /// Lets the stream know which track it should play once the current one ends
+    fn queue_next(&mut self) {
+        if let Some(next) = self.playlist.peek_next().cloned() {
+            if let Some(h) = &self.stream_handle {
+                h.queue(&next);
+            }
+            // Getting a head start on the analysis of the next track
+            self.analyse(&next);
+        }
+    }
+
+    fn track_changed(&mut self, sound: &Sound) {
+        self.analyse(sound);
+        self.analysis_controls.restart_measurements();
+        self.general_attributes = GeneralAttributes {
+            duration: sound.duration(),
+            track_index: self.playlist.current_index().unwrap_or_default(),
+            track_changed: true,
+            title: sound.title(),
+            metadata: sound.metadata().clone(),
+            analysis: None
+        };
+    }
+
+    /// Analyses the whole sound in a background thread, unless already done
+    fn analyse(&mut self, sound: &Sound) {
+        if self.analyses.contains_key(sound.path()) { return }
+        self.analyses.insert(sound.path().to_path_buf(), None);
+
+        let sound = sound.clone();
+        let sender = self.analysis_sender.clone();
+        thread::spawn(move || {
+            let analysis = match TrackAnalysis::load_or_analyse(&sound) {
+                Ok(a) => Some(Arc::new(a)),
+                Err(e) => {
+                    eprintln!("could not analyse {}: {}", sound.path().display(), e);
+                    None
+                }
+            };
+            // The player may be gone by now, in which case nobody cares
+            let _ = sender.send((sound.path().to_path_buf(), analysis));
+        });
+    }
+
+    /// Keeps the playlist in sync with the output stream, should be called
+    /// regularly
+    pub fn update(&mut self) {
+        while let Ok((path, analysis)) = self.analysis_receiver.try_recv() {
+            self.analyses.insert(path, analysis);
+        }
+
+        let (started, finished) = match &self.stream_handle {
+            Some(h) => (h.sounds_started(), h.finished()),
+            None => return
+        };
+
+        // The stream seamlessly moved on to the tracks we queued
+        if self.tracks_started < started {
+            while self.tracks_started < started {
+                self.tracks_started += 1;
+                self.playlist.advance();
+            }
+            if let Some(sound) = self.playlist.current().cloned() {
+                self.track_changed(&sound);
+            }
+            self.queue_next();
+        }
+
+        // The stream could not play the next track, either because it needs
+        // another output configuration or because there is none
+        if finished && !self.paused {
+            match self.playlist.advance().cloned() {
+                Some(sound) => self.start(&sound),
+                None => self.pause()
+            }
+        }
+    }
+
+    /// Skips to the next track of the playlist
+    pub fn next(&mut self) {
+        self.update();
+        if let Some(sound) = self.playlist.next_track().cloned() {
+            self.skip_to(&sound);
+        }
+    }
+
+    /// Goes back to the previous track of the playlist
+    pub fn previous(&mut self) {
+        self.update();
+        if let Some(sound) = self.playlist.previous_track().cloned() {
+            self.skip_to(&sound);
+        }
+    }
+
+    fn skip_to(&mut self, sound: &Sound) {
+        match &self.stream_handle {
+            Some(h) if h.accepts(sound) => {
+                h.play(sound);
+                self.track_changed(sound);
+                self.queue_next();
+            },
+            _ => self.start(sound)
+        }
+    }
+
+    /// Shuffles the upcoming tracks, or restores their original order
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        self.playlist.set_shuffle(shuffle);
+        self.queue_next();
+    }
+
+    pub fn set_repeat(&mut self, repeat: Repeat) {
+        self.playlist.set_repeat(repeat);
+        self.queue_next();
+    }
+
+    pub fn playlist(&self) -> &Playlist {
+        &self.playlist
+    }
+
+    /// Brings the analysed samples to the given loudness in LUFS, or analyses
+    /// them as they are if `None`. Playback is left untouched.
+    pub fn set_auto_gain(&self, target: Option<f32>) {
+        *self.analysis_controls.auto_gain.lock().unwrap() = target;
+    }
+
+    pub fn auto_gain(&self) -> Option<f32> {
+        *self.analysis_controls.auto_gain.lock().unwrap()
+    }
+
     pub fn get_realtime_attributes(&self) -> RealtimeAttributes {
-        let rta = self.realtime_attributes.lock().unwrap();
-        (*rta).clone()
+        take_realtime_attributes(&self.realtime_attributes)
     }
 
-    pub fn get_general_attributes(&self) -> GeneralAttributes {
-        self.general_attributes.clone()
+    pub fn get_general_attributes(&mut self) -> GeneralAttributes {
+        if self.general_attributes.analysis.is_none() {
+            self.general_attributes.analysis = self.playlist.current()
+                .and_then(|s| self.analyses.get(s.path()).cloned().flatten());
+        }
+        let attributes = self.general_attributes.clone();
+        self.general_attributes.track_changed = false;
+        attributes
     }
 
     pub fn resume(&mut self) {
@@ -112,6 +420,23 @@ impl AudioPlayer {
         }
     }
 
+    /// Moves playback to the given time, clamped to the duration of the sound
+    pub fn seek(&mut self, time: Duration) {
+        if let Some(h) = &self.stream_handle {
+            let time = time.min(self.general_attributes.duration);
+            h.seek(time);
+
+            // The callback will not update the timestamp while paused
+            let mut rta = self.realtime_attributes.lock().unwrap();
+            rta.timestamp = time;
+        }
+    }
+
+    /// Time of the last sample sent to the output device
+    pub fn position(&self) -> Duration {
+        self.stream_handle.as_ref().map(|h| h.position()).unwrap_or_default()
+    }
+
     pub fn paused(&self) -> bool {
         self.paused
     }
@@ -121,4 +446,126 @@ impl Default for AudioPlayer {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+/// Retrieves the attributes written by an analyzer, resetting those that only
+/// describe the moment they were retrieved at
+pub fn take_realtime_attributes(rta: &Mutex<RealtimeAttributes>) -> RealtimeAttributes {
+    let mut rta = rta.lock().unwrap();
+    let attributes = rta.clone();
+    rta.beat = false;
+    attributes
+}
+
+/// Lets the player steer analyzers running in audio threads
+#[derive(Default)]
+pub struct AnalysisControls {
+    /// Loudness the analysed samples are brought to, in LUFS
+    auto_gain: Mutex<Option<f32>>,
+    /// Bumped whenever measurements spanning a whole track should start over
+    generation: AtomicU64
+}
+impl AnalysisControls {
+    pub fn restart_measurements(&self) {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+/// Turns samples that were just played or captured into realtime attributes
+pub struct Analyzer {
+    sample_rate: u32,
+    channels: usize,
+    controls: Arc<AnalysisControls>,
+    generation: u64,
+
+    loudness: LoudnessMeter,
+    /// Current auto-gain, in dB
+    gain: f32,
+    buffer: Vec<f32>,
+
+    spectrum: SpectrumAnalyzer,
+    onsets: OnsetDetector,
+    tempo: TempoEstimator
+}
+impl Analyzer {
+    pub fn new(sample_rate: u32, channels: usize, controls: Arc<AnalysisControls>) -> Self {
+        let spectrum = SpectrumAnalyzer::new(sample_rate, channels);
+        let spectrum_rate = spectrum.spectrum_rate();
+        Analyzer {
+            sample_rate,
+            channels,
+            generation: controls.generation.load(Ordering::Acquire),
+            controls,
+
+            loudness: LoudnessMeter::new(sample_rate, channels),
+            gain: 0.0,
+            buffer: vec![],
+
+            spectrum,
+            onsets: OnsetDetector::new(spectrum_rate),
+            tempo: TempoEstimator::new(spectrum_rate)
+        }
+    }
+
+    pub fn analyse(&mut self, samples: &[f32], timestamp: Duration, rta: &Mutex<RealtimeAttributes>) {
+        let generation = self.controls.generation.load(Ordering::Acquire);
+        if generation != self.generation {
+            self.generation = generation;
+            self.loudness = LoudnessMeter::new(self.sample_rate, self.channels);
+        }
+
+        // Loudness is measured before the auto-gain, which is based on it
+        self.loudness.push(samples);
+        let target = *self.controls.auto_gain.lock().unwrap();
+        let samples = match target {
+            Some(target) => {
+                self.update_gain(target, samples.len());
+                let gain = 10f32.powf(self.gain / 20.0);
+                self.buffer.clear();
+                self.buffer.extend(samples.iter().map(|s| s * gain));
+                &self.buffer[..]
+            },
+            None => {
+                self.gain = 0.0;
+                samples
+            }
+        };
+
+        let mut updated = false;
+        let mut beat = false;
+        let (onsets, tempo) = (&mut self.onsets, &mut self.tempo);
+        self.spectrum.push(samples, |spectrum| {
+            updated = true;
+            let onset = onsets.process(spectrum);
+            beat |= tempo.process(onsets.flux(), onset);
+        });
+
+        let mut rta = rta.lock().unwrap();
+        if updated {
+            rta.spectrum.clone_from(self.spectrum.spectrum());
+            rta.onset_strength = self.onsets.strength();
+            rta.bpm = self.tempo.bpm();
+            rta.beat_phase = self.tempo.phase();
+        }
+        rta.waveform.clear();
+        rta.waveform.extend(self.spectrum.waveform(WAVEFORM_LENGTH));
+        // Beats are kept until someone gets to see them
+        rta.beat |= beat;
+        rta.momentary_loudness = self.loudness.momentary();
+        rta.short_term_loudness = self.loudness.short_term();
+        rta.integrated_loudness = self.loudness.integrated();
+        rta.true_peak = self.loudness.true_peak();
+        rta.gain = self.gain;
+        rta.timestamp = timestamp;
+    }
+
+    /// Moves the gain towards the one bringing the short-term loudness to the target
+    fn update_gain(&mut self, target: f32, sample_count: usize) {
+        let short_term = self.loudness.short_term();
+        if short_term < AUTO_GAIN_GATE { return }
+
+        let wanted = (target - short_term).clamp(AUTO_GAIN_MIN, AUTO_GAIN_MAX);
+        let elapsed = sample_count as f32 / (self.sample_rate as f32 * self.channels as f32);
+        self.gain += (wanted - self.gain) * (1.0 - (-elapsed / AUTO_GAIN_TIME.as_secs_f32()).exp());
+    }
+}
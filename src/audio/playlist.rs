@@ -0,0 +1,298 @@
+use rand::seq::SliceRandom;
+use crate::Sound;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repeat {
+    /// Stops once every track has been played
+    Off,
+    /// Plays the current track over and over
+    One,
+    /// Starts over from the first track once every track has been played
+    All
+}
+
+/// An ordered queue of sounds
+#[derive(Clone)]
+pub struct Playlist {
+    tracks: Vec<Sound>,
+    /// Order in which the tracks are played, as indices into `tracks`
+    order: Vec<usize>,
+    /// Position of the current track in `order`
+    position: usize,
+
+    shuffle: bool,
+    repeat: Repeat
+}
+impl Playlist {
+    pub fn new() -> Self {
+        Playlist {
+            tracks: vec![],
+            order: vec![],
+            position: 0,
+
+            shuffle: false,
+            repeat: Repeat::Off
+        }
+    }
+
+    /// Adds a sound at the end of the playlist, or anywhere after the current
+    /// track if shuffling
+    pub fn push(&mut self, sound: Sound) {
+        self.tracks.push(sound);
+        let index = self.tracks.len() - 1;
+        if self.shuffle && self.order.len() > self.position + 1 {
+            let slot = rand::random::<usize>() % (self.order.len() - self.position) + self.position + 1;
+            self.order.insert(slot, index);
+        } else {
+            self.order.push(index);
+        }
+    }
+
+    /// Returns the track being played
+    pub fn current(&self) -> Option<&Sound> {
+        self.current_index().map(|i| &self.tracks[i])
+    }
+
+    /// Returns the index of the track being played, in insertion order
+    pub fn current_index(&self) -> Option<usize> {
+        self.order.get(self.position).copied()
+    }
+
+    /// Returns the track that will be played once the current one ends
+    pub fn peek_next(&self) -> Option<&Sound> {
+        self.following(false).map(|p| &self.tracks[self.order[p]])
+    }
+
+    /// Moves on to the track that should be played once the current one ends
+    pub fn advance(&mut self) -> Option<&Sound> {
+        self.position = self.following(false)?;
+        self.current()
+    }
+
+    /// Skips to the next track, even when repeating the current one
+    pub fn next_track(&mut self) -> Option<&Sound> {
+        self.position = self.following(true)?;
+        self.current()
+    }
+
+    /// Goes back to the previous track, or to the start of the current one if
+    /// there is none
+    pub fn previous_track(&mut self) -> Option<&Sound> {
+        if self.position > 0 {
+            self.position -= 1;
+        } else if self.repeat == Repeat::All && !self.order.is_empty() {
+            self.position = self.order.len() - 1;
+        }
+        self.current()
+    }
+
+    fn following(&self, skip: bool) -> Option<usize> {
+        if self.order.is_empty() { return None }
+
+        match self.repeat {
+            Repeat::One if !skip => Some(self.position),
+            _ if self.position + 1 < self.order.len() => Some(self.position + 1),
+            Repeat::Off => None,
+            _ => Some(0)
+        }
+    }
+
+    /// Shuffles the tracks following the current one, or restores the
+    /// insertion order
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        self.shuffle = shuffle;
+        let current = match self.current_index() {
+            Some(i) => i,
+            None => return
+        };
+
+        if shuffle {
+            // The current track goes first so that it keeps playing
+            self.order.retain(|&i| i != current);
+            self.order.shuffle(&mut rand::thread_rng());
+            self.order.insert(0, current);
+            self.position = 0;
+        } else {
+            self.order = (0..self.tracks.len()).collect();
+            self.position = current;
+        }
+    }
+
+    pub fn shuffle(&self) -> bool { self.shuffle }
+
+    pub fn set_repeat(&mut self, repeat: Repeat) { self.repeat = repeat }
+
+    pub fn repeat(&self) -> Repeat { self.repeat }
+
+    pub fn len(&self) -> usize { self.tracks.len() }
+
+    pub fn is_empty(&self) -> bool { self.tracks.is_empty() }
+}
+impl Default for Playlist {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl From<Vec<Sound>> for Playlist {
+    fn from(tracks: Vec<Sound>) -> Self {
+        let mut playlist = Playlist::new();
+        tracks.into_iter().for_each(|t| playlist.push(t));
+        playlist
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(len: usize, repeat: Repeat) -> Playlist {
+        let mut playlist = Playlist::from(vec![Sound::placeholder(); len]);
+        playlist.set_repeat(repeat);
+        playlist
+    }
+
+    fn at_end(len: usize, repeat: Repeat) -> Playlist {
+        let mut playlist = playlist(len, repeat);
+        for _ in 1..len {
+            playlist.next_track();
+        }
+        assert_eq!(playlist.current_index(), Some(len - 1));
+        playlist
+    }
+
+    #[test]
+    fn empty() {
+        for repeat in [Repeat::Off, Repeat::One, Repeat::All] {
+            let mut playlist = playlist(0, repeat);
+            assert!(playlist.current().is_none());
+            assert!(playlist.peek_next().is_none());
+            assert!(playlist.advance().is_none());
+            assert!(playlist.next_track().is_none());
+            assert!(playlist.previous_track().is_none());
+            playlist.set_shuffle(true);
+            assert!(playlist.current().is_none());
+        }
+    }
+
+    #[test]
+    fn repeat_off() {
+        let mut playlist = playlist(3, Repeat::Off);
+        assert_eq!(playlist.current_index(), Some(0));
+        playlist.previous_track();
+        assert_eq!(playlist.current_index(), Some(0));
+        playlist.advance();
+        assert_eq!(playlist.current_index(), Some(1));
+
+        let mut playlist = at_end(3, Repeat::Off);
+        assert!(playlist.peek_next().is_none());
+        assert!(playlist.advance().is_none());
+        assert!(playlist.next_track().is_none());
+        // Stopping leaves the last track current
+        assert_eq!(playlist.current_index(), Some(2));
+        playlist.previous_track();
+        assert_eq!(playlist.current_index(), Some(1));
+    }
+
+    #[test]
+    fn repeat_one() {
+        let mut playlist = playlist(3, Repeat::One);
+        playlist.previous_track();
+        assert_eq!(playlist.current_index(), Some(0));
+        playlist.advance();
+        assert_eq!(playlist.current_index(), Some(0));
+        playlist.next_track();
+        assert_eq!(playlist.current_index(), Some(1));
+
+        let mut playlist = at_end(3, Repeat::One);
+        assert!(playlist.peek_next().is_some());
+        playlist.advance();
+        assert_eq!(playlist.current_index(), Some(2));
+        playlist.next_track();
+        assert_eq!(playlist.current_index(), Some(0));
+    }
+
+    #[test]
+    fn repeat_all() {
+        let mut playlist = playlist(3, Repeat::All);
+        playlist.previous_track();
+        assert_eq!(playlist.current_index(), Some(2));
+        playlist.advance();
+        assert_eq!(playlist.current_index(), Some(0));
+
+        let mut playlist = at_end(3, Repeat::All);
+        playlist.advance();
+        assert_eq!(playlist.current_index(), Some(0));
+        let mut playlist = at_end(3, Repeat::All);
+        playlist.next_track();
+        assert_eq!(playlist.current_index(), Some(0));
+    }
+
+    #[test]
+    fn single_track() {
+        for repeat in [Repeat::Off, Repeat::One, Repeat::All] {
+            let mut playlist = playlist(1, repeat);
+            playlist.previous_track();
+            assert_eq!(playlist.current_index(), Some(0));
+            let next = playlist.next_track().is_some();
+            assert_eq!(next, repeat != Repeat::Off);
+            assert_eq!(playlist.current_index(), Some(0));
+        }
+    }
+
+    #[test]
+    fn shuffled_pushes_come_after_the_current_track() {
+        let mut playlist = playlist(3, Repeat::Off);
+        playlist.next_track();
+        playlist.set_shuffle(true);
+        let played = playlist.order[..=playlist.position].to_vec();
+        for _ in 0..100 {
+            playlist.push(Sound::placeholder());
+        }
+        assert_eq!(playlist.current_index(), Some(1));
+        assert_eq!(&playlist.order[..=playlist.position], &played[..]);
+
+        let mut order = playlist.order.clone();
+        order.sort_unstable();
+        assert_eq!(order, (0..103).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn shuffled_push_on_the_last_track() {
+        // With no slot after the current track, the sound is the next one
+        let mut playlist = at_end(3, Repeat::Off);
+        playlist.set_shuffle(true);
+        while playlist.next_track().is_some() {}
+        playlist.push(Sound::placeholder());
+        assert_eq!(playlist.order.last(), Some(&3));
+        playlist.advance();
+        assert_eq!(playlist.current_index(), Some(3));
+    }
+
+    #[test]
+    fn shuffle_wraps_around() {
+        let mut playlist = playlist(10, Repeat::All);
+        playlist.next_track();
+        playlist.next_track();
+        playlist.set_shuffle(true);
+        assert_eq!(playlist.current_index(), Some(2));
+
+        // Every track is played once before the shuffled order starts over
+        let mut played = vec![];
+        for _ in 0..10 {
+            played.push(playlist.current_index().unwrap());
+            playlist.advance();
+        }
+        assert_eq!(playlist.current_index(), Some(2));
+        played.sort_unstable();
+        assert_eq!(played, (0..10).collect::<Vec<_>>());
+
+        playlist.previous_track();
+        assert_eq!(playlist.current_index(), playlist.order.last().copied());
+
+        // The insertion order comes back, keeping the current track
+        let current = playlist.current_index();
+        playlist.set_shuffle(false);
+        assert_eq!(playlist.current_index(), current);
+        assert_eq!(playlist.order, (0..10).collect::<Vec<_>>());
+    }
+}
@@ -1,11 +1,798 @@
-use rustfft::FftPlanner;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+use rustfft::{Fft, FftPlanner};
 use rustfft::num_complex::Complex;
+use serde::{Deserialize, Serialize};
 
-pub fn fft(data: &[f32]) -> Vec<Complex<f32>> {
-    let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(data.len());
+/// Lowest value reported in decibels, anything quieter is clamped to it
+pub const DECIBEL_FLOOR: f32 = -120.0;
 
-    let mut result: Vec<Complex<f32>> = data.iter().map(Complex::from).collect();
-    fft.process(&mut result);
-    result
-}
\ No newline at end of file
+/// Window functions applied to the samples before transforming them, trading
+/// frequency resolution for less spectral leakage
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+    BlackmanHarris
+}
+impl Window {
+    pub fn coefficients(&self, size: usize) -> Vec<f32> {
+        let phase = |n: usize| 2.0 * PI * n as f32 / (size - 1).max(1) as f32;
+        (0..size).map(|n| match self {
+            Window::Rectangular => 1.0,
+            Window::Hann => 0.5 - 0.5 * phase(n).cos(),
+            Window::Hamming => 0.54 - 0.46 * phase(n).cos(),
+            Window::BlackmanHarris => {
+                0.35875
+                    - 0.48829 * phase(n).cos()
+                    + 0.14128 * (2.0 * phase(n)).cos()
+                    - 0.01168 * (3.0 * phase(n)).cos()
+            }
+        }).collect()
+    }
+}
+
+/// One-sided spectrum of a mono signal
+#[derive(Default, Clone, Debug)]
+pub struct Spectrum {
+    /// Magnitude of each bin, scaled so that a full scale sine peaks at 1
+    pub magnitudes: Vec<f32>,
+    /// Magnitude of each bin in decibels relative to full scale
+    pub decibels: Vec<f32>,
+    /// Center frequency of each bin, in Hz
+    pub frequencies: Vec<f32>
+}
+
+/// Computes the spectrum of a stream of interleaved samples.
+///
+/// Channels are mixed down to mono, and a new spectrum is computed every
+/// `hop_size` samples over the last `fft_size` ones.
+pub struct SpectrumAnalyzer {
+    planner: FftPlanner<f32>,
+    fft: Arc<dyn Fft<f32>>,
+    sample_rate: u32,
+    channels: usize,
+
+    fft_size: usize,
+    hop_size: usize,
+    window: Window,
+    coefficients: Vec<f32>,
+
+    /// The last `fft_size` mono samples
+    history: VecDeque<f32>,
+    /// Mono samples received since the last spectrum was computed
+    pending: usize,
+    /// Samples of an incomplete frame left over from the last push
+    partial_frame: Vec<f32>,
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+
+    spectrum: Spectrum
+}
+impl SpectrumAnalyzer {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(2048);
+        SpectrumAnalyzer {
+            planner,
+            fft,
+            sample_rate,
+            channels: channels.max(1),
+
+            fft_size: 2048,
+            hop_size: 512,
+            window: Window::Hann,
+            coefficients: vec![],
+
+            history: VecDeque::new(),
+            pending: 0,
+            partial_frame: vec![],
+            buffer: vec![],
+            scratch: vec![],
+
+            spectrum: Default::default()
+        }.fft_size(2048)
+    }
+
+    /// Sets the number of samples transformed at once, the resulting spectrum
+    /// has `fft_size / 2 + 1` bins
+    pub fn fft_size(mut self, fft_size: usize) -> Self {
+        self.fft_size = fft_size.max(2);
+        self.fft = self.planner.plan_fft_forward(self.fft_size);
+        self.coefficients = self.window.coefficients(self.fft_size);
+        self.scratch = vec![Complex::default(); self.fft.get_inplace_scratch_len()];
+        self.history = VecDeque::from(vec![0.0; self.fft_size]);
+        self.hop_size = self.hop_size.min(self.fft_size);
+
+        let bins = self.fft_size / 2 + 1;
+        let resolution = self.sample_rate as f32 / self.fft_size as f32;
+        self.spectrum = Spectrum {
+            magnitudes: vec![0.0; bins],
+            decibels: vec![DECIBEL_FLOOR; bins],
+            frequencies: (0..bins).map(|k| k as f32 * resolution).collect()
+        };
+        self
+    }
+
+    /// Sets the number of samples between two spectrums
+    pub fn hop_size(mut self, hop_size: usize) -> Self {
+        self.hop_size = hop_size.clamp(1, self.fft_size);
+        self
+    }
+
+    pub fn window(mut self, window: Window) -> Self {
+        self.window = window;
+        self.coefficients = window.coefficients(self.fft_size);
+        self
+    }
+
+    /// Feeds interleaved samples to the analyzer, calling `on_spectrum` every
+    /// time a new spectrum is computed
+    pub fn push<F: FnMut(&Spectrum)>(&mut self, samples: &[f32], mut on_spectrum: F) {
+        for &sample in samples {
+            self.partial_frame.push(sample);
+            if self.partial_frame.len() < self.channels { continue }
+
+            let mono = self.partial_frame.iter().sum::<f32>() / self.channels as f32;
+            self.partial_frame.clear();
+            self.history.pop_front();
+            self.history.push_back(mono);
+
+            self.pending += 1;
+            if self.pending >= self.hop_size {
+                self.pending = 0;
+                self.transform();
+                on_spectrum(&self.spectrum);
+            }
+        }
+    }
+
+    fn transform(&mut self) {
+        self.buffer.clear();
+        self.buffer.extend(
+            self.history.iter()
+                .zip(&self.coefficients)
+                .map(|(s, w)| Complex::new(s * w, 0.0))
+        );
+        self.fft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        // Scaling by the window's gain makes magnitudes independent from both
+        // the window and the size of the transform, and since we only keep half
+        // of the bins their energy is doubled, except for the DC and Nyquist ones
+        let gain: f32 = self.coefficients.iter().sum();
+        let last = self.spectrum.magnitudes.len() - 1;
+        for (k, value) in self.buffer.iter().take(last + 1).enumerate() {
+            let scale = if k == 0 || k == last { 1.0 } else { 2.0 };
+            let magnitude = value.norm() * scale / gain;
+            self.spectrum.magnitudes[k] = magnitude;
+            self.spectrum.decibels[k] = (20.0 * magnitude.log10()).max(DECIBEL_FLOOR);
+        }
+    }
+
+    /// Returns the last computed spectrum
+    pub fn spectrum(&self) -> &Spectrum {
+        &self.spectrum
+    }
+
+    /// Returns up to the last `length` mono samples, oldest first
+    pub fn waveform(&self, length: usize) -> impl Iterator<Item = f32> + '_ {
+        self.history.iter().skip(self.history.len().saturating_sub(length)).copied()
+    }
+
+    pub fn sample_rate(&self) -> u32 { self.sample_rate }
+
+    /// Returns the number of spectrums computed per second
+    pub fn spectrum_rate(&self) -> f32 {
+        self.sample_rate as f32 / self.hop_size as f32
+    }
+}
+
+/// Scales on which spectrum bins can be grouped into bands
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BandScale {
+    /// The given number of bands, evenly spaced in log-frequency
+    Logarithmic(usize),
+    /// The given number of bands, evenly spaced on the mel scale
+    Mel(usize),
+    /// The given number of bands, evenly spaced on the bark scale
+    Bark(usize),
+    /// Standard 1/3 octave bands whose center lies within the frequency range
+    ThirdOctave
+}
+impl BandScale {
+    /// Computes the frequencies delimiting each band, in Hz
+    fn edges(&self, min_frequency: f32, max_frequency: f32) -> Vec<f32> {
+        let evenly_spaced = |count: usize, to_scale: fn(f32) -> f32, from_scale: fn(f32) -> f32| {
+            let (low, high) = (to_scale(min_frequency), to_scale(max_frequency));
+            (0..=count)
+                .map(|i| from_scale(low + (high - low) * i as f32 / count.max(1) as f32))
+                .collect()
+        };
+
+        match *self {
+            BandScale::Logarithmic(count) => evenly_spaced(count, f32::ln, f32::exp),
+            BandScale::Mel(count) => evenly_spaced(
+                count,
+                |f| 2595.0 * (1.0 + f / 700.0).log10(),
+                |m| 700.0 * (10f32.powf(m / 2595.0) - 1.0)
+            ),
+            BandScale::Bark(count) => evenly_spaced(
+                count,
+                |f| 26.81 * f / (1960.0 + f) - 0.53,
+                |z| 1960.0 * (z + 0.53) / (26.28 - z)
+            ),
+            BandScale::ThirdOctave => {
+                // Centers are 1/3 octave apart from 1kHz, and each band
+                // stretches 1/6 octave on both sides of its center
+                let first = (3.0 * (min_frequency / 1000.0).log2()).ceil() as i32;
+                let last = (3.0 * (max_frequency / 1000.0).log2()).floor() as i32;
+                (first..=last + 1)
+                    .map(|n| 1000.0 * 2f32.powf((n as f32 - 0.5) / 3.0))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// How the bins making up a band are combined
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Aggregation {
+    /// Keeps the loudest bin
+    Peak,
+    /// Keeps the root mean square of the bins
+    Rms
+}
+
+/// Groups the bins of a spectrum into perceptually meaningful bands.
+///
+/// Bands are made of the magnitudes of the bins whose frequency falls within
+/// them, bands too narrow to contain any bin use the one closest to their center.
+#[derive(Clone)]
+pub struct BandMapper {
+    scale: BandScale,
+    min_frequency: f32,
+    max_frequency: f32,
+    aggregation: Aggregation,
+    attack: f32,
+    release: f32,
+
+    edges: Vec<f32>,
+    /// Bins making up each band, for the spectrum layout they were computed for
+    bins: Vec<Range<usize>>,
+    layout: (usize, f32),
+    bands: Vec<f32>
+}
+impl BandMapper {
+    pub fn new(scale: BandScale) -> Self {
+        BandMapper {
+            scale,
+            min_frequency: 20.0,
+            max_frequency: 20000.0,
+            aggregation: Aggregation::Peak,
+            attack: 0.0,
+            release: 0.0,
+
+            edges: vec![],
+            bins: vec![],
+            layout: (0, 0.0),
+            bands: vec![]
+        }.frequency_range(20.0, 20000.0)
+    }
+
+    /// Restricts the bands to the given frequencies, in Hz
+    pub fn frequency_range(mut self, min_frequency: f32, max_frequency: f32) -> Self {
+        self.min_frequency = min_frequency.max(1.0);
+        self.max_frequency = max_frequency.max(self.min_frequency);
+        self.edges = self.scale.edges(self.min_frequency, self.max_frequency);
+        self.bands = vec![0.0; self.edges.len().saturating_sub(1)];
+        self.layout = (0, 0.0);
+        self
+    }
+
+    pub fn aggregation(mut self, aggregation: Aggregation) -> Self {
+        self.aggregation = aggregation;
+        self
+    }
+
+    /// Smooths bands over time, each coefficient in [0, 1) being the share of
+    /// the previous value kept when a band respectively rises or falls
+    pub fn smoothing(mut self, attack: f32, release: f32) -> Self {
+        self.attack = attack.clamp(0.0, 0.99);
+        self.release = release.clamp(0.0, 0.99);
+        self
+    }
+
+    /// Updates the bands from a new spectrum and returns them
+    pub fn map(&mut self, spectrum: &Spectrum) -> &[f32] {
+        let resolution = spectrum.frequencies.get(1).copied().unwrap_or_default();
+        if self.layout != (spectrum.frequencies.len(), resolution) {
+            self.layout = (spectrum.frequencies.len(), resolution);
+            self.bins = Self::bins_of(&self.edges, &spectrum.frequencies);
+        }
+
+        for (band, bins) in self.bands.iter_mut().zip(&self.bins) {
+            let magnitudes = &spectrum.magnitudes[bins.clone()];
+            let value = match self.aggregation {
+                Aggregation::Peak => magnitudes.iter().fold(0.0, |a: f32, &m| a.max(m)),
+                Aggregation::Rms => {
+                    let power = magnitudes.iter().map(|m| m * m).sum::<f32>() / magnitudes.len().max(1) as f32;
+                    power.sqrt()
+                }
+            };
+            let kept = if value > *band { self.attack } else { self.release };
+            *band = *band * kept + value * (1.0 - kept);
+        }
+
+        &self.bands
+    }
+
+    fn bins_of(edges: &[f32], frequencies: &[f32]) -> Vec<Range<usize>> {
+        if frequencies.is_empty() { return vec![0..0; edges.len().saturating_sub(1)] }
+
+        edges.windows(2).map(|edge| {
+            // Frequencies are sorted, so we only need to find where the band starts and ends
+            let start = frequencies.partition_point(|&f| f < edge[0]);
+            let end = frequencies.partition_point(|&f| f < edge[1]);
+            if start < end {
+                start..end
+            } else {
+                let center = (edge[0] * edge[1]).sqrt();
+                let closest = frequencies.partition_point(|&f| f < center).min(frequencies.len() - 1);
+                closest..closest + 1
+            }
+        }).collect()
+    }
+
+    /// Returns the bands computed by the last call to `map`
+    pub fn bands(&self) -> &[f32] {
+        &self.bands
+    }
+
+    /// Returns the bands in [0, 1], 0 being any magnitude quieter than
+    /// `floor` decibels and 1 a full scale one
+    pub fn levels(&self, floor: f32) -> Vec<f32> {
+        self.bands.iter()
+            .map(|b| (1.0 - 20.0 * b.max(f32::MIN_POSITIVE).log10() / floor).clamp(0.0, 1.0))
+            .collect()
+    }
+
+    /// Returns the frequencies delimiting each band, in Hz
+    pub fn edges(&self) -> &[f32] {
+        &self.edges
+    }
+}
+
+/// Detects sudden increases of energy in a stream of spectrums, such as drum hits.
+///
+/// The onset strength is the spectral flux, the sum of the increases of each
+/// bin since the previous spectrum, and an onset is reported whenever it rises
+/// above a threshold adapted to its recent history.
+pub struct OnsetDetector {
+    /// Number of spectrums per second
+    spectrum_rate: f32,
+    sensitivity: f32,
+    min_interval: usize,
+
+    previous: Vec<f32>,
+    /// Recent spectral flux values the threshold is computed from
+    history: VecDeque<f32>,
+    /// Number of spectrums since the last onset
+    since_onset: usize,
+    /// Slowly decaying maximum of the flux, used to normalise it
+    peak: f32,
+
+    flux: f32,
+    strength: f32
+}
+impl OnsetDetector {
+    /// Creates a detector for the given number of spectrums per second
+    pub fn new(spectrum_rate: f32) -> Self {
+        OnsetDetector {
+            spectrum_rate,
+            sensitivity: 1.5,
+            min_interval: 0,
+
+            previous: vec![],
+            history: VecDeque::from(vec![0.0; (spectrum_rate * 0.5).ceil() as usize]),
+            since_onset: usize::MAX,
+            peak: 0.0,
+
+            flux: 0.0,
+            strength: 0.0
+        }.min_interval(Duration::from_millis(100))
+    }
+
+    /// Sets how many standard deviations above its recent mean the flux has
+    /// to be for an onset to be reported
+    pub fn sensitivity(mut self, sensitivity: f32) -> Self {
+        self.sensitivity = sensitivity;
+        self
+    }
+
+    /// Sets the shortest time between two onsets
+    pub fn min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = (min_interval.as_secs_f32() * self.spectrum_rate).round() as usize;
+        self
+    }
+
+    /// Processes the next spectrum, returning whether it starts an onset
+    pub fn process(&mut self, spectrum: &Spectrum) -> bool {
+        // Log compression keeps quiet bins from being swamped by loud ones
+        let mut flux = 0.0;
+        self.previous.resize(spectrum.magnitudes.len(), 0.0);
+        for (previous, magnitude) in self.previous.iter_mut().zip(&spectrum.magnitudes) {
+            let compressed = (1.0 + 1000.0 * magnitude).ln();
+            flux += (compressed - *previous).max(0.0);
+            *previous = compressed;
+        }
+        self.flux = flux / spectrum.magnitudes.len().max(1) as f32;
+
+        let count = self.history.len() as f32;
+        let mean = self.history.iter().sum::<f32>() / count;
+        let variance = self.history.iter().map(|f| (f - mean) * (f - mean)).sum::<f32>() / count;
+        let threshold = mean + self.sensitivity * variance.sqrt();
+        self.history.pop_front();
+        self.history.push_back(self.flux);
+
+        self.peak = (self.peak * 0.999).max(self.flux);
+        self.strength = if self.peak > 0.0 { self.flux / self.peak } else { 0.0 };
+
+        self.since_onset = self.since_onset.saturating_add(1);
+        let onset = self.flux > threshold && self.flux > 1e-3 && self.since_onset > self.min_interval;
+        if onset {
+            self.since_onset = 0;
+        }
+        onset
+    }
+
+    /// Returns the spectral flux of the last spectrum
+    pub fn flux(&self) -> f32 { self.flux }
+
+    /// Returns the spectral flux of the last spectrum relative to the recent
+    /// loudest one, in [0, 1]
+    pub fn strength(&self) -> f32 { self.strength }
+}
+
+/// Estimates the tempo of a stream from its onset strength, and follows the
+/// phase of its beats.
+///
+/// The tempo is the period at which the recent onset strength best correlates
+/// with itself, favouring tempos around 120 BPM to avoid picking a multiple of
+/// the actual one.
+pub struct TempoEstimator {
+    spectrum_rate: f32,
+    min_bpm: f32,
+    max_bpm: f32,
+
+    /// Recent onset strength, one value per spectrum
+    envelope: VecDeque<f32>,
+    /// Number of spectrums since the tempo was last estimated
+    since_estimate: usize,
+
+    bpm: f32,
+    phase: f32,
+    /// Whether the phase was aligned to an onset since the tempo last changed
+    locked: bool
+}
+impl TempoEstimator {
+    /// Creates an estimator for the given number of spectrums per second
+    pub fn new(spectrum_rate: f32) -> Self {
+        TempoEstimator {
+            spectrum_rate,
+            min_bpm: 60.0,
+            max_bpm: 200.0,
+
+            envelope: VecDeque::from(vec![0.0; (spectrum_rate * 6.0).ceil() as usize]),
+            since_estimate: 0,
+
+            bpm: 0.0,
+            phase: 0.0,
+            locked: false
+        }
+    }
+
+    /// Restricts the estimated tempo to the given range, in BPM
+    pub fn bpm_range(mut self, min_bpm: f32, max_bpm: f32) -> Self {
+        self.min_bpm = min_bpm.max(1.0);
+        self.max_bpm = max_bpm.max(self.min_bpm);
+        self
+    }
+
+    /// Processes the onset strength of the next spectrum and whether it starts
+    /// an onset, returning whether a beat falls on it
+    pub fn process(&mut self, flux: f32, onset: bool) -> bool {
+        self.envelope.pop_front();
+        self.envelope.push_back(flux);
+
+        // Re-estimating the tempo a few times per second is plenty
+        self.since_estimate += 1;
+        if self.since_estimate as f32 >= self.spectrum_rate / 4.0 {
+            self.since_estimate = 0;
+            self.estimate();
+        }
+
+        if self.bpm <= 0.0 { return onset }
+
+        self.phase += self.bpm / 60.0 / self.spectrum_rate;
+        let beat = self.phase >= 1.0;
+        self.phase %= 1.0;
+
+        // The first onset at a new tempo sets the phase, following ones close
+        // to where we expect a beat pull it towards them
+        if onset && !self.locked {
+            self.phase = 0.0;
+            self.locked = true;
+        } else if onset {
+            let error = if self.phase < 0.5 { self.phase } else { self.phase - 1.0 };
+            if error.abs() < 0.25 {
+                self.phase = (self.phase - error * 0.5).rem_euclid(1.0);
+            }
+        }
+
+        beat
+    }
+
+    fn estimate(&mut self) {
+        let count = self.envelope.len();
+        let mean = self.envelope.iter().sum::<f32>() / count as f32;
+        let envelope: Vec<f32> = self.envelope.iter().map(|f| f - mean).collect();
+
+        let min_lag = ((60.0 * self.spectrum_rate / self.max_bpm).floor() as usize).max(1);
+        let max_lag = ((60.0 * self.spectrum_rate / self.min_bpm).ceil() as usize).min(count / 2);
+        if min_lag + 2 > max_lag { return }
+
+        let correlations: Vec<f32> = (min_lag - 1..=max_lag + 1).map(|lag| {
+            envelope.iter().zip(&envelope[lag..]).map(|(a, b)| a * b).sum::<f32>() / (count - lag) as f32
+        }).collect();
+
+        // Peaks are compared at the lag a parabola through their neighbours
+        // puts them, as beats rarely last a whole number of spectrums and the
+        // correlation at the closest lags would favour their multiples
+        let mut best = None;
+        for i in 1..correlations.len() - 1 {
+            let (a, b, c) = (correlations[i - 1], correlations[i], correlations[i + 1]);
+            if b <= 0.0 || b < a || b < c { continue }
+
+            let denominator = a - 2.0 * b + c;
+            let offset = if denominator.abs() > f32::EPSILON { 0.5 * (a - c) / denominator } else { 0.0 };
+            let offset = offset.clamp(-0.5, 0.5);
+            let peak = b - 0.25 * (a - c) * offset;
+            let bpm = 60.0 * self.spectrum_rate / ((min_lag + i - 1) as f32 + offset);
+            let weight = (-0.5 * (bpm / 120.0).log2().powi(2)).exp();
+            let score = peak * weight;
+            if !matches!(best, Some((_, s)) if s >= score) {
+                best = Some((bpm, score));
+            }
+        }
+
+        if let Some((bpm, _)) = best {
+            // Small variations are smoothed, while large ones are tempo changes
+            if (bpm - self.bpm).abs() < self.bpm * 0.05 {
+                self.bpm = self.bpm * 0.8 + bpm * 0.2;
+            } else {
+                self.bpm = bpm;
+                self.locked = false;
+            }
+        }
+    }
+
+    /// Returns the estimated tempo in BPM, or 0 if unknown
+    pub fn bpm(&self) -> f32 { self.bpm }
+
+    /// Returns how far we are between two beats, in [0, 1)
+    pub fn phase(&self) -> f32 { self.phase }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44100;
+
+    /// Stereo clicks at the given tempo, each a short decaying burst of a few
+    /// tones over silence
+    fn click_track(bpm: f32, duration: f32) -> Vec<f32> {
+        let period = (60.0 / bpm * SAMPLE_RATE as f32).round() as usize;
+        let click = (0.02 * SAMPLE_RATE as f32) as usize;
+        let length = (duration * SAMPLE_RATE as f32) as usize;
+        (0..length).flat_map(|i| {
+            let t = (i % period) as f32 / SAMPLE_RATE as f32;
+            let sample = if i % period < click {
+                let tones = [800.0, 2500.0, 6000.0].iter().map(|f| (2.0 * PI * f * t).sin()).sum::<f32>();
+                0.3 * tones * (-t * 300.0).exp()
+            } else {
+                0.0
+            };
+            [sample, sample]
+        }).collect()
+    }
+
+    /// Runs a click track through the analysis chain, returning the times of
+    /// the onsets, the times of the beats and the final tempo estimate
+    fn analyse(bpm: f32, duration: f32) -> (Vec<f32>, Vec<f32>, f32) {
+        let mut analyzer = SpectrumAnalyzer::new(SAMPLE_RATE, 2);
+        let rate = analyzer.spectrum_rate();
+        let mut detector = OnsetDetector::new(rate);
+        let mut estimator = TempoEstimator::new(rate);
+
+        let (mut onsets, mut beats) = (vec![], vec![]);
+        let mut count = 0;
+        // Pushed in chunks like the audio callback does
+        for chunk in click_track(bpm, duration).chunks(1024) {
+            analyzer.push(chunk, |spectrum| {
+                let time = count as f32 / rate;
+                count += 1;
+                let onset = detector.process(spectrum);
+                if onset {
+                    onsets.push(time);
+                }
+                if estimator.process(detector.flux(), onset) {
+                    beats.push(time);
+                }
+            });
+        }
+        (onsets, beats, estimator.bpm())
+    }
+
+    /// Distance from a time to the closest click, in seconds
+    fn distance_to_click(time: f32, bpm: f32) -> f32 {
+        let period = 60.0 / bpm;
+        let offset = time.rem_euclid(period);
+        offset.min(period - offset)
+    }
+
+    #[test]
+    fn onsets_fall_on_clicks() {
+        let bpm = 120.0;
+        let (onsets, _, _) = analyse(bpm, 10.0);
+        // The transform looks at the last 2048 samples, so an onset shows up
+        // within that long after its click
+        let latency = 2048.0 / SAMPLE_RATE as f32;
+        assert_eq!(onsets.len(), 20);
+        for onset in onsets {
+            assert!(distance_to_click(onset - latency / 2.0, bpm) <= latency, "onset at {}s", onset);
+        }
+    }
+
+    #[test]
+    fn tempo_of_click_tracks() {
+        for bpm in [90.0, 120.0, 150.0] {
+            let (onsets, beats, estimate) = analyse(bpm, 16.0);
+            assert!((estimate - bpm).abs() < 2.0, "{} BPM estimated as {}", bpm, estimate);
+
+            // Once the tempo is known, beats fall on the onsets of the clicks
+            let settled: Vec<f32> = beats.into_iter().filter(|&b| b > 10.0).collect();
+            let expected = ((16.0 - 10.0) * bpm / 60.0) as usize;
+            assert!(settled.len().abs_diff(expected) <= 1, "{} beats for {} clicks", settled.len(), expected);
+            let hop = 512.0 / SAMPLE_RATE as f32;
+            for beat in settled {
+                let closest = onsets.iter().map(|o| (o - beat).abs()).fold(f32::MAX, f32::min);
+                assert!(closest <= 2.0 * hop, "{} BPM beat at {}s, {}s from an onset", bpm, beat, closest);
+            }
+        }
+    }
+
+    /// Interleaved samples of a sine played on every channel
+    fn sine(frequency: f32, amplitude: f32, frames: usize, channels: usize) -> Vec<f32> {
+        (0..frames).flat_map(|i| {
+            let sample = amplitude * (2.0 * PI * frequency * i as f32 / SAMPLE_RATE as f32).sin();
+            vec![sample; channels]
+        }).collect()
+    }
+
+    #[test]
+    fn bin_centered_sine_peaks_in_its_bin() {
+        let bin = 100;
+        let frequency = bin as f32 * SAMPLE_RATE as f32 / 2048.0;
+        for amplitude in [1.0, 0.5] {
+            let mut analyzer = SpectrumAnalyzer::new(SAMPLE_RATE, 2);
+            analyzer.push(&sine(frequency, amplitude, 2048, 2), |_| {});
+
+            let magnitudes = &analyzer.spectrum().magnitudes;
+            let peak = (0..magnitudes.len()).max_by(|&a, &b| magnitudes[a].total_cmp(&magnitudes[b])).unwrap();
+            assert_eq!(peak, bin);
+            assert!((magnitudes[bin] - amplitude).abs() < 0.01, "{} read as {}", amplitude, magnitudes[bin]);
+            let decibels = 20.0 * amplitude.log10();
+            assert!((analyzer.spectrum().decibels[bin] - decibels).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn bin_frequencies() {
+        for fft_size in [256, 1024, 4096] {
+            let analyzer = SpectrumAnalyzer::new(SAMPLE_RATE, 1).fft_size(fft_size);
+            let frequencies = &analyzer.spectrum().frequencies;
+            assert_eq!(frequencies.len(), fft_size / 2 + 1);
+            for (k, &frequency) in frequencies.iter().enumerate() {
+                let expected = k as f32 * SAMPLE_RATE as f32 / fft_size as f32;
+                assert!((frequency - expected).abs() < 1e-3, "bin {} of {} at {}Hz", k, fft_size, frequency);
+            }
+        }
+    }
+
+    #[test]
+    fn spectrums_follow_the_hop_size() {
+        for hop_size in [128, 512, 2048] {
+            let mut analyzer = SpectrumAnalyzer::new(SAMPLE_RATE, 2).hop_size(hop_size);
+            assert_eq!(analyzer.spectrum_rate(), SAMPLE_RATE as f32 / hop_size as f32);
+
+            // Frames split across pushes still count once complete
+            let mut count = 0;
+            for chunk in sine(440.0, 0.5, 10000, 2).chunks(333) {
+                analyzer.push(chunk, |_| count += 1);
+            }
+            assert_eq!(count, 10000 / hop_size);
+        }
+    }
+
+    #[test]
+    fn band_edges_span_the_frequency_range() {
+        let (min, max) = (50.0, 16000.0);
+        for scale in [BandScale::Logarithmic(24), BandScale::Mel(24), BandScale::Bark(24), BandScale::ThirdOctave] {
+            let mapper = BandMapper::new(scale).frequency_range(min, max);
+            let edges = mapper.edges();
+            assert!(edges.windows(2).all(|e| e[0] < e[1]), "{:?} edges {:?}", scale, edges);
+            if let BandScale::ThirdOctave = scale {
+                // Standard bands stretch past the range, only their centers lie within it
+                for edge in edges.windows(2) {
+                    let center = (edge[0] * edge[1]).sqrt();
+                    assert!(center >= min && center <= max, "{:?} center at {}Hz", scale, center);
+                }
+            } else {
+                assert!((edges[0] - min).abs() < min * 1e-3, "{:?} starts at {}Hz", scale, edges[0]);
+                assert!((edges[edges.len() - 1] - max).abs() < max * 1e-3, "{:?} ends at {}Hz", scale, edges[edges.len() - 1]);
+            }
+        }
+    }
+
+    #[test]
+    fn band_counts() {
+        for count in [1, 8, 64] {
+            for scale in [BandScale::Logarithmic(count), BandScale::Mel(count), BandScale::Bark(count)] {
+                let mapper = BandMapper::new(scale);
+                assert_eq!(mapper.bands().len(), count, "{:?}", scale);
+                assert_eq!(mapper.edges().len(), count + 1, "{:?}", scale);
+            }
+        }
+    }
+
+    #[test]
+    fn third_octave_centers() {
+        let nominal = [
+            25.0, 31.5, 40.0, 50.0, 63.0, 80.0, 100.0, 125.0, 160.0, 200.0, 250.0, 315.0, 400.0, 500.0, 630.0,
+            800.0, 1000.0, 1250.0, 1600.0, 2000.0, 2500.0, 3150.0, 4000.0, 5000.0, 6300.0, 8000.0, 10000.0,
+            12500.0, 16000.0
+        ];
+        let mapper = BandMapper::new(BandScale::ThirdOctave);
+        let centers: Vec<f32> = mapper.edges().windows(2).map(|e| (e[0] * e[1]).sqrt()).collect();
+        assert_eq!(centers.len(), nominal.len());
+        // Exact centers are powers of 2^(1/3), nominal ones are rounded
+        for (center, nominal) in centers.iter().zip(nominal) {
+            assert!((center - nominal).abs() < nominal * 0.02, "{}Hz band centered at {}Hz", nominal, center);
+        }
+    }
+
+    #[test]
+    fn peak_and_rms_aggregation() {
+        // Bins every 100Hz, only 200, 300 and 400Hz fall within the band
+        let magnitudes = vec![0.9, 0.9, 0.1, 0.7, 0.1, 0.9, 0.9];
+        let spectrum = Spectrum {
+            decibels: magnitudes.iter().map(|m: &f32| 20.0 * m.log10()).collect(),
+            frequencies: (0..magnitudes.len()).map(|k| k as f32 * 100.0).collect(),
+            magnitudes
+        };
+
+        let mut peak = BandMapper::new(BandScale::Logarithmic(1)).frequency_range(150.0, 450.0);
+        assert_eq!(peak.map(&spectrum), [0.7]);
+
+        let mut rms = BandMapper::new(BandScale::Logarithmic(1))
+            .frequency_range(150.0, 450.0)
+            .aggregation(Aggregation::Rms);
+        let expected = ((0.1f32 * 0.1 + 0.7 * 0.7 + 0.1 * 0.1) / 3.0).sqrt();
+        assert!((rms.map(&spectrum)[0] - expected).abs() < 1e-6);
+    }
+}
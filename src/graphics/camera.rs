@@ -0,0 +1,108 @@
+use std::f32::consts::PI;
+use glm::{look_at, ortho, perspective, rotate_vec3, TMat4, TVec3};
+use serde::{Deserialize, Serialize};
+
+/// How far from the up axis orbiting cameras stay, in radians
+const POLE_MARGIN: f32 = 0.01;
+/// Field of view of the default camera, in degrees, 2 * atan(1 / 0.5) for
+/// [-1, 1] to fit half a unit away
+const DEFAULT_FOV: f32 = 126.869_9;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Projection {
+    /// Vertical field of view, in degrees
+    Perspective(f32),
+    /// Height of what is seen, in world units
+    Orthographic(f32)
+}
+
+/// Where a scene is seen from, and how
+///
+/// Projections follow the conventions of OpenGL while the y axis of Vulkan
+/// points down, so with the default up vector the y axis of scenes points
+/// towards the bottom of the frame
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Camera {
+    pub projection: Projection,
+    pub near: f32,
+    pub far: f32,
+    pub position: [f32; 3],
+    /// Point the camera looks at, and orbits around
+    pub target: [f32; 3],
+    pub up: [f32; 3]
+}
+impl Default for Camera {
+    /// Looks down the z axis from the origin, wide enough to see scenes laid
+    /// out in [-1, 1] half a unit away, as the demo is
+    fn default() -> Self {
+        Camera {
+            projection: Projection::Perspective(DEFAULT_FOV),
+            near: 0.01,
+            far: 100.0,
+            position: [0.0, 0.0, 0.01],
+            target: [0.0, 0.0, 0.0],
+            up: [0.0, 1.0, 0.0]
+        }
+    }
+}
+impl Camera {
+    pub fn perspective(fov: f32) -> Self {
+        Camera { projection: Projection::Perspective(fov), ..Default::default() }
+    }
+
+    pub fn orthographic(height: f32) -> Self {
+        Camera { projection: Projection::Orthographic(height), ..Default::default() }
+    }
+
+    pub fn look_at(&mut self, position: TVec3<f32>, target: TVec3<f32>) -> &mut Self {
+        self.position = position.into();
+        self.target = target.into();
+        self
+    }
+
+    /// Turns the camera around its target, by `yaw` radians around the up
+    /// axis and `pitch` radians towards it, without going over the top
+    pub fn orbit(&mut self, yaw: f32, pitch: f32) -> &mut Self {
+        let (target, up) = (TVec3::from(self.target), TVec3::from(self.up).normalize());
+        let offset = rotate_vec3(&(TVec3::from(self.position) - target), yaw, &up);
+
+        let angle = offset.angle(&up);
+        let pitch = angle - (angle - pitch).clamp(POLE_MARGIN, PI - POLE_MARGIN);
+        let axis = offset.cross(&up);
+        let offset = if axis.norm() > 0.0 { rotate_vec3(&offset, pitch, &axis.normalize()) } else { offset };
+
+        self.position = (target + offset).into();
+        self
+    }
+
+    /// Distance between the camera and its target
+    pub fn distance(&self) -> f32 {
+        (TVec3::from(self.position) - TVec3::from(self.target)).norm()
+    }
+
+    /// Moves the camera towards or away from its target
+    pub fn set_distance(&mut self, distance: f32) -> &mut Self {
+        let target = TVec3::from(self.target);
+        let direction = TVec3::from(self.position) - target;
+        if direction.norm() > 0.0 {
+            self.position = (target + direction.normalize() * distance).into();
+        }
+        self
+    }
+
+    pub fn view(&self) -> TMat4<f32> {
+        look_at(&self.position.into(), &self.target.into(), &self.up.into())
+    }
+
+    /// Returns the projection for frames of the given width over height
+    pub fn projection(&self, aspect: f32) -> TMat4<f32> {
+        match self.projection {
+            Projection::Perspective(fov) => perspective(aspect, fov.to_radians(), self.near, self.far),
+            Projection::Orthographic(height) => {
+                let (width, height) = (height * aspect, height);
+                ortho(-width / 2.0, width / 2.0, -height / 2.0, height / 2.0, self.near, self.far)
+            }
+        }
+    }
+}
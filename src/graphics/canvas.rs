@@ -0,0 +1,391 @@
+use glm::{mix, TVec2, TVec3, TVec4, vec2, vec4};
+use lyon::math::{Angle, point, Point, vector};
+use lyon::path::Path;
+use lyon::tessellation::{
+    BuffersBuilder, FillOptions, FillTessellator, FillVertex, LineCap, LineJoin, StrokeOptions,
+    StrokeTessellator, StrokeVertex, VertexBuffers
+};
+use super::OverlayVertex;
+
+/// Largest distance between curves and the segments approximating them, as a
+/// share of the height of the frame
+const TOLERANCE: f32 = 0.0005;
+/// Number of triangles gradients are split into along their length, at least
+const GRADIENT_STEPS: f32 = 8.0;
+/// Most times the edges of triangles are split, to bound the vertices of shapes
+/// much larger than their gradient
+const MAX_GRADIENT_SPLITS: u32 = 16;
+
+/// Shapes drawn over the frame, tessellated as soon as they are filled or
+/// stroked
+///
+/// Like the frame overlays are drawn on, the canvas spans [-1, 1] vertically
+/// with the y axis pointing down, and as far as the aspect ratio goes
+/// horizontally. Shapes are kept until the canvas is cleared, so that scenes
+/// drawing every frame clear it first.
+#[derive(Clone)]
+pub struct Canvas {
+    geometry: VertexBuffers<OverlayVertex, u32>,
+    path: Vec<Segment>,
+    paint: Paint,
+    opacity: f32,
+    stroke_width: f32
+}
+
+#[derive(Clone, Copy)]
+enum Segment {
+    MoveTo(Point),
+    LineTo(Point),
+    QuadraticTo(Point, Point),
+    CubicTo(Point, Point, Point),
+    /// Center, radius, start and end angles
+    Arc(Point, f32, f32, f32),
+    Close
+}
+
+/// Colors of shapes, gradients being evaluated at each vertex
+#[derive(Clone)]
+pub(crate) enum Paint {
+    Solid(TVec4<f32>),
+    /// Goes from the first stop at `start` to the last one at `end`
+    Linear { start: TVec2<f32>, end: TVec2<f32>, stops: Vec<Stop>, spread: Spread },
+    /// Goes from the first stop at `focal` to the last one on the circle of
+    /// `radius` around `center`
+    Radial { center: TVec2<f32>, focal: TVec2<f32>, radius: f32, stops: Vec<Stop>, spread: Spread }
+}
+
+/// A color of a gradient, and where it is along it in [0, 1]
+#[derive(Clone, Copy)]
+pub(crate) struct Stop {
+    pub offset: f32,
+    pub color: TVec4<f32>
+}
+
+/// How gradients go on past their ends
+#[derive(Clone, Copy)]
+pub(crate) enum Spread {
+    Pad,
+    Reflect,
+    Repeat
+}
+
+impl Paint {
+    fn at(&self, position: TVec2<f32>) -> TVec4<f32> {
+        let (t, stops, spread) = match self {
+            Paint::Solid(color) => return *color,
+            Paint::Linear { start, end, stops, spread } => {
+                let direction = end - start;
+                let length = direction.norm_squared();
+                let t = if length > 0.0 { (position - start).dot(&direction) / length } else { 0.0 };
+                (t, stops, spread)
+            },
+            Paint::Radial { center, focal, radius, stops, spread } => {
+                (radial_offset(position - focal, center - focal, *radius), stops, spread)
+            }
+        };
+        let t = match spread {
+            Spread::Pad => t.clamp(0.0, 1.0),
+            Spread::Reflect => 1.0 - (t.rem_euclid(2.0) - 1.0).abs(),
+            Spread::Repeat => t.rem_euclid(1.0)
+        };
+
+        let next = stops.iter().position(|s| s.offset > t).unwrap_or(stops.len());
+        match (next.checked_sub(1).map(|i| stops[i]), stops.get(next).copied()) {
+            (Some(previous), Some(next)) => {
+                let mix_factor = (t - previous.offset) / (next.offset - previous.offset);
+                mix(&previous.color, &next.color, mix_factor)
+            },
+            (Some(stop), None) | (None, Some(stop)) => stop.color,
+            (None, None) => vec4(0.0, 0.0, 0.0, 0.0)
+        }
+    }
+
+    /// Returns the distance over which a gradient goes from its first stop to
+    /// its last one, `None` for solid colors
+    fn extent(&self) -> Option<f32> {
+        match self {
+            Paint::Solid(_) => None,
+            Paint::Linear { start, end, .. } => Some((end - start).norm()),
+            Paint::Radial { radius, .. } => Some(*radius)
+        }
+    }
+}
+
+/// Returns how far along a radial gradient a point is, relative to its focal
+/// point, the gradient being made of circles growing from the focal point to
+/// the outer circle
+fn radial_offset(position: TVec2<f32>, center: TVec2<f32>, radius: f32) -> f32 {
+    if radius <= 0.0 {
+        return 0.0
+    }
+    // Solves |position - t * center| = t * radius for t
+    let a = center.norm_squared() - radius * radius;
+    let b = position.dot(&center);
+    let c = position.norm_squared();
+    if a.abs() < f32::EPSILON {
+        return if b > 0.0 { c / (2.0 * b) } else { 0.0 }
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return 0.0
+    }
+    (b - discriminant.sqrt()) / a
+}
+
+/// Adds triangles to `geometry`, painted where `to_paint` takes their
+/// vertices. Triangles painted with a gradient are first split into smaller
+/// ones, as colors are only interpolated in between vertices
+pub(crate) fn add_painted<F>(
+    geometry: &mut VertexBuffers<OverlayVertex, u32>,
+    shape: &VertexBuffers<Point, u32>,
+    paint: &Paint,
+    opacity: f32,
+    to_paint: F
+) where F: Fn(Point) -> TVec2<f32> {
+    let vertex = |position: Point| {
+        let color = paint.at(to_paint(position));
+        OverlayVertex { position: position.to_array(), color: [color.x, color.y, color.z, color.w * opacity] }
+    };
+
+    let first = geometry.vertices.len() as u32;
+    let extent = match paint.extent() {
+        Some(extent) if extent > 0.0 => extent,
+        _ => {
+            geometry.vertices.extend(shape.vertices.iter().map(|&p| vertex(p)));
+            geometry.indices.extend(shape.indices.iter().map(|i| first + i));
+            return
+        }
+    };
+
+    // Every triangle is split as many times, so that the vertices of shared
+    // edges match
+    let longest = shape.indices.chunks_exact(3)
+        .flat_map(|t| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+        .map(|(a, b)| {
+            let (a, b) = (shape.vertices[a as usize], shape.vertices[b as usize]);
+            (to_paint(a) - to_paint(b)).norm()
+        })
+        .fold(0.0, f32::max);
+    let splits = ((longest / extent * GRADIENT_STEPS).ceil() as u32).clamp(1, MAX_GRADIENT_SPLITS);
+
+    let row_start = |i: u32| i * (2 * splits + 3 - i) / 2;
+    for triangle in shape.indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| shape.vertices[triangle[i] as usize]);
+        let first = geometry.vertices.len() as u32;
+        for i in 0..=splits {
+            for j in 0..=splits - i {
+                let (u, v) = (i as f32 / splits as f32, j as f32 / splits as f32);
+                geometry.vertices.push(vertex(a + (b - a) * u + (c - a) * v));
+            }
+        }
+        let index = |i: u32, j: u32| first + row_start(i) + j;
+        for i in 0..splits {
+            for j in 0..splits - i {
+                geometry.indices.extend([index(i, j), index(i + 1, j), index(i, j + 1)]);
+                if i + j + 1 < splits {
+                    geometry.indices.extend([index(i + 1, j), index(i + 1, j + 1), index(i, j + 1)]);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Canvas {
+            geometry: VertexBuffers::new(),
+            path: vec![],
+            paint: Paint::Solid(vec4(1.0, 1.0, 1.0, 1.0)),
+            opacity: 1.0,
+            stroke_width: 0.01
+        }
+    }
+}
+
+impl Canvas {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Removes every shape, keeping the current path, paint and stroke width
+    pub fn clear(&mut self) {
+        self.geometry.vertices.clear();
+        self.geometry.indices.clear();
+    }
+
+    pub fn vertices(&self) -> &[OverlayVertex] {
+        &self.geometry.vertices
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.geometry.indices
+    }
+
+    pub fn set_color(&mut self, color: TVec3<f32>) -> &mut Self {
+        self.paint = Paint::Solid(vec4(color.x, color.y, color.z, 1.0));
+        self
+    }
+
+    /// Paints shapes from `start_color` at `start` to `end_color` at `end`,
+    /// the colors being interpolated between the vertices of shapes
+    pub fn linear_gradient(
+        &mut self,
+        start: TVec2<f32>,
+        end: TVec2<f32>,
+        start_color: TVec3<f32>,
+        end_color: TVec3<f32>
+    ) -> &mut Self {
+        self.paint = Paint::Linear { start, end, stops: gradient_stops(start_color, end_color), spread: Spread::Pad };
+        self
+    }
+
+    /// Paints shapes from `inner_color` at `center` to `outer_color` at
+    /// `radius` from it and beyond, the colors being interpolated between the
+    /// vertices of shapes
+    pub fn radial_gradient(
+        &mut self,
+        center: TVec2<f32>,
+        radius: f32,
+        inner_color: TVec3<f32>,
+        outer_color: TVec3<f32>
+    ) -> &mut Self {
+        self.paint = Paint::Radial { center, focal: center, radius, stops: gradient_stops(inner_color, outer_color), spread: Spread::Pad };
+        self
+    }
+
+    /// Sets how opaque the next shapes are, in [0, 1]
+    pub fn set_opacity(&mut self, opacity: f32) -> &mut Self {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn set_stroke_width(&mut self, width: f32) -> &mut Self {
+        self.stroke_width = width.max(0.0);
+        self
+    }
+
+    /// Forgets the current path, to start a new one
+    pub fn begin_path(&mut self) -> &mut Self {
+        self.path.clear();
+        self
+    }
+
+    /// Starts a new subpath at `to`
+    pub fn move_to(&mut self, to: TVec2<f32>) -> &mut Self {
+        self.path.push(Segment::MoveTo(point(to.x, to.y)));
+        self
+    }
+
+    pub fn line_to(&mut self, to: TVec2<f32>) -> &mut Self {
+        self.path.push(Segment::LineTo(point(to.x, to.y)));
+        self
+    }
+
+    pub fn quadratic_to(&mut self, control: TVec2<f32>, to: TVec2<f32>) -> &mut Self {
+        self.path.push(Segment::QuadraticTo(point(control.x, control.y), point(to.x, to.y)));
+        self
+    }
+
+    pub fn cubic_to(&mut self, control1: TVec2<f32>, control2: TVec2<f32>, to: TVec2<f32>) -> &mut Self {
+        self.path.push(Segment::CubicTo(
+            point(control1.x, control1.y),
+            point(control2.x, control2.y),
+            point(to.x, to.y)
+        ));
+        self
+    }
+
+    /// Adds an arc of a circle going from `start` to `end` radians, clockwise
+    /// on screen when `end` is greater. It is joined to the current subpath by
+    /// a line, if there is one
+    pub fn arc(&mut self, center: TVec2<f32>, radius: f32, start: f32, end: f32) -> &mut Self {
+        self.path.push(Segment::Arc(point(center.x, center.y), radius, start, end));
+        self
+    }
+
+    /// Joins the end of the current subpath to its start
+    pub fn close_path(&mut self) -> &mut Self {
+        self.path.push(Segment::Close);
+        self
+    }
+
+    /// Fills the current path, where it winds around at least once
+    pub fn fill(&mut self) -> &mut Self {
+        let path = self.build_path();
+        let mut shape = VertexBuffers::new();
+        // Paths that cannot be tessellated are left out
+        let _ = FillTessellator::new().tessellate_path(
+            &path,
+            &FillOptions::tolerance(TOLERANCE),
+            &mut BuffersBuilder::new(&mut shape, |v: FillVertex| v.position())
+        );
+        add_painted(&mut self.geometry, &shape, &self.paint, self.opacity, |p| vec2(p.x, p.y));
+        self
+    }
+
+    /// Draws the outline of the current path, `stroke_width` wide
+    pub fn stroke(&mut self) -> &mut Self {
+        let path = self.build_path();
+        let options = StrokeOptions::tolerance(TOLERANCE)
+            .with_line_width(self.stroke_width)
+            .with_line_cap(LineCap::Round)
+            .with_line_join(LineJoin::Round);
+        let mut shape = VertexBuffers::new();
+        let _ = StrokeTessellator::new().tessellate_path(
+            &path,
+            &options,
+            &mut BuffersBuilder::new(&mut shape, |v: StrokeVertex| v.position())
+        );
+        add_painted(&mut self.geometry, &shape, &self.paint, self.opacity, |p| vec2(p.x, p.y));
+        self
+    }
+
+    /// Strokes lines going through `points`, as a new path
+    pub fn polyline(&mut self, points: &[TVec2<f32>]) -> &mut Self {
+        self.begin_path().add_points(points).stroke()
+    }
+
+    /// Fills the polygon whose corners are `points`, as a new path
+    pub fn polygon(&mut self, points: &[TVec2<f32>]) -> &mut Self {
+        self.begin_path().add_points(points).close_path().fill()
+    }
+
+    fn add_points(&mut self, points: &[TVec2<f32>]) -> &mut Self {
+        if let Some((first, rest)) = points.split_first() {
+            self.move_to(*first);
+            for p in rest {
+                self.line_to(*p);
+            }
+        }
+        self
+    }
+
+    fn build_path(&self) -> Path {
+        let mut builder = Path::svg_builder();
+        let mut open = false;
+        for segment in &self.path {
+            match *segment {
+                Segment::MoveTo(to) => { builder.move_to(to); },
+                Segment::LineTo(to) => { builder.line_to(to); },
+                Segment::QuadraticTo(control, to) => { builder.quadratic_bezier_to(control, to); },
+                Segment::CubicTo(control1, control2, to) => { builder.cubic_bezier_to(control1, control2, to); },
+                Segment::Arc(center, radius, start, end) => {
+                    let from = center + vector(start.cos(), start.sin()) * radius;
+                    if open { builder.line_to(from); } else { builder.move_to(from); }
+                    builder.arc(center, vector(radius, radius), Angle::radians(end - start), Angle::zero());
+                },
+                Segment::Close => builder.close()
+            }
+            open = !matches!(segment, Segment::Close);
+        }
+        builder.build()
+    }
+}
+
+/// Opaque stops at both ends of a gradient
+fn gradient_stops(start: TVec3<f32>, end: TVec3<f32>) -> Vec<Stop> {
+    vec![
+        Stop { offset: 0.0, color: vec4(start.x, start.y, start.z, 1.0) },
+        Stop { offset: 1.0, color: vec4(end.x, end.y, end.z, 1.0) }
+    ]
+}
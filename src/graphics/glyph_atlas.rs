@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use ttf_parser::{GlyphId, OutlineBuilder};
+use crate::resource_pool::font_loader::Font;
+
+/// Width and height of the atlas, in texels
+pub const ATLAS_SIZE: u32 = 1024;
+/// Largest size glyphs are rasterized at, in pixels per em, bigger text being
+/// scaled up from it
+pub const MAX_GLYPH_SIZE: u32 = 192;
+/// Rows of samples per row of pixels, coverage being exact horizontally
+const SUBSAMPLES: usize = 5;
+/// Empty texels around glyphs, so that filtering does not bleed neighbours in
+const PADDING: u32 = 1;
+
+/// Where a glyph rasterized at some size is in the atlas
+#[derive(Clone, Copy)]
+pub struct AtlasGlyph {
+    /// Top left corner in the atlas, in texels
+    pub origin: [u32; 2],
+    pub dimensions: [u32; 2],
+    /// Top left corner relative to the pen on the baseline, in pixels with y
+    /// pointing down
+    pub offset: [i32; 2]
+}
+
+/// Coverage of the glyphs drawn so far, packed in rows into a single texture
+///
+/// Glyphs are rasterized when the texts they belong to are prepared, before the
+/// atlas is uploaded, and drawing only looks them up. Once full, the atlas has
+/// to be cleared and the glyphs still being drawn prepared again.
+pub struct GlyphAtlas {
+    texels: Vec<u8>,
+    /// Glyphs by font, glyph and size, `None` for those with nothing to draw
+    glyphs: HashMap<(usize, GlyphId, u32), Option<AtlasGlyph>>,
+    /// Where the next glyph goes, and the height of the row it goes in
+    cursor: [u32; 2],
+    row_height: u32,
+    /// Whether glyphs were added since the texels were last taken
+    changed: bool
+}
+
+impl Default for GlyphAtlas {
+    fn default() -> Self {
+        GlyphAtlas {
+            texels: vec![0; (ATLAS_SIZE * ATLAS_SIZE) as usize],
+            glyphs: HashMap::new(),
+            cursor: [PADDING, PADDING],
+            row_height: 0,
+            changed: false
+        }
+    }
+}
+
+impl GlyphAtlas {
+    /// Returns where a glyph is in the atlas, if it was prepared and has
+    /// something to draw
+    pub fn get(&self, font: &Font, glyph: GlyphId, size: u32) -> Option<AtlasGlyph> {
+        self.glyphs.get(&(font.id(), glyph, size)).copied().flatten()
+    }
+
+    /// Rasterizes a glyph into the atlas unless it already is there, returning
+    /// whether there was room for it
+    pub fn prepare(&mut self, font: &Font, glyph: GlyphId, size: u32) -> bool {
+        let key = (font.id(), glyph, size);
+        if self.glyphs.contains_key(&key) {
+            return true
+        }
+
+        let (dimensions, offset, coverage) = match rasterize(font, glyph, size) {
+            Some(rasterized) => rasterized,
+            None => {
+                self.glyphs.insert(key, None);
+                return true
+            }
+        };
+        let origin = match self.allocate(dimensions) {
+            Some(origin) => origin,
+            None => return false
+        };
+        for (y, row) in coverage.chunks_exact(dimensions[0] as usize).enumerate() {
+            let start = ((origin[1] + y as u32) * ATLAS_SIZE + origin[0]) as usize;
+            self.texels[start..start + row.len()].copy_from_slice(row);
+        }
+        self.changed = true;
+        self.glyphs.insert(key, Some(AtlasGlyph { origin, dimensions, offset }));
+        true
+    }
+
+    /// Removes every glyph, making room for new ones
+    pub fn clear(&mut self) {
+        *self = GlyphAtlas { changed: true, ..Default::default() };
+    }
+
+    /// Returns the texels of the atlas if glyphs were added since they were
+    /// last taken
+    pub fn take_changes(&mut self) -> Option<&[u8]> {
+        if !self.changed {
+            return None
+        }
+        self.changed = false;
+        Some(&self.texels)
+    }
+
+    /// Finds room for a glyph, at the end of the current row or in a new one
+    fn allocate(&mut self, [width, height]: [u32; 2]) -> Option<[u32; 2]> {
+        if self.cursor[0] + width + PADDING > ATLAS_SIZE {
+            self.cursor = [PADDING, self.cursor[1] + self.row_height + PADDING];
+            self.row_height = 0;
+        }
+        if self.cursor[0] + width + PADDING > ATLAS_SIZE || self.cursor[1] + height + PADDING > ATLAS_SIZE {
+            return None
+        }
+        let origin = self.cursor;
+        self.cursor[0] += width + PADDING;
+        self.row_height = self.row_height.max(height);
+        Some(origin)
+    }
+}
+
+/// Returns the dimensions of a glyph rasterized at `size` pixels per em, its
+/// offset from the pen and its coverage, row by row
+fn rasterize(font: &Font, glyph: GlyphId, size: u32) -> Option<([u32; 2], [i32; 2], Vec<u8>)> {
+    let face = font.face();
+    let scale = size as f32 / face.units_per_em() as f32;
+    let mut outline = Outline { scale, edges: vec![], start: [0.0; 2], last: [0.0; 2] };
+    let bounds = face.outline_glyph(glyph, &mut outline)?;
+    outline.close();
+
+    // Pixels with y pointing down, the outline being y up
+    let left = (bounds.x_min as f32 * scale).floor() as i32;
+    let top = (-bounds.y_max as f32 * scale).floor() as i32;
+    let width = ((bounds.x_max as f32 * scale).ceil() as i32 - left).max(1) as usize;
+    let height = ((-bounds.y_min as f32 * scale).ceil() as i32 - top).max(1) as usize;
+    let edges: Vec<[[f32; 2]; 2]> = outline.edges.iter()
+        .map(|edge| edge.map(|[x, y]| [x - left as f32, -y - top as f32]))
+        .collect();
+
+    let mut coverage = vec![0.0f32; width * height];
+    let mut crossings: Vec<(f32, i32)> = vec![];
+    for (y, row) in coverage.chunks_exact_mut(width).enumerate() {
+        for sample in 0..SUBSAMPLES {
+            let sample_y = y as f32 + (sample as f32 + 0.5) / SUBSAMPLES as f32;
+            crossings.clear();
+            for &[a, b] in &edges {
+                if (a[1] <= sample_y) != (b[1] <= sample_y) {
+                    let x = a[0] + (sample_y - a[1]) * (b[0] - a[0]) / (b[1] - a[1]);
+                    crossings.push((x, if b[1] > a[1] { 1 } else { -1 }));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            // Glyphs are filled where they wind around at least once
+            let mut winding = 0;
+            for pair in crossings.windows(2) {
+                winding += pair[0].1;
+                if winding != 0 {
+                    cover(row, pair[0].0, pair[1].0, 1.0 / SUBSAMPLES as f32);
+                }
+            }
+        }
+    }
+
+    let coverage = coverage.into_iter().map(|c| (c.min(1.0) * 255.0).round() as u8).collect();
+    Some(([width as u32, height as u32], [left, top], coverage))
+}
+
+/// Adds the coverage of a span going from `start` to `end` to a row of pixels
+fn cover(row: &mut [f32], start: f32, end: f32, weight: f32) {
+    let (start, end) = (start.max(0.0), end.min(row.len() as f32));
+    if start >= end {
+        return
+    }
+    let (first, last) = (start as usize, (end.ceil() as usize).min(row.len()) - 1);
+    if first == last {
+        row[first] += (end - start) * weight;
+        return
+    }
+    row[first] += (first as f32 + 1.0 - start) * weight;
+    for pixel in &mut row[first + 1..last] {
+        *pixel += weight;
+    }
+    row[last] += (end - last as f32) * weight;
+}
+
+/// Flattens the outline of a glyph into edges, in pixels with y pointing up
+struct Outline {
+    scale: f32,
+    edges: Vec<[[f32; 2]; 2]>,
+    start: [f32; 2],
+    last: [f32; 2]
+}
+impl Outline {
+    fn line(&mut self, to: [f32; 2]) {
+        if to != self.last {
+            self.edges.push([self.last, to]);
+        }
+        self.last = to;
+    }
+
+    /// Splits curves in enough segments for them to look smooth at the size
+    /// they are drawn at
+    fn segments(points: &[[f32; 2]]) -> usize {
+        let length: f32 = points.windows(2).map(|p| ((p[1][0] - p[0][0]).powi(2) + (p[1][1] - p[0][1]).powi(2)).sqrt()).sum();
+        (length / 2.0).ceil().clamp(1.0, 64.0) as usize
+    }
+}
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.close();
+        self.start = [x * self.scale, y * self.scale];
+        self.last = self.start;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.line([x * self.scale, y * self.scale]);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (p0, p1, p2) = (self.last, [x1 * self.scale, y1 * self.scale], [x * self.scale, y * self.scale]);
+        let segments = Self::segments(&[p0, p1, p2]);
+        for i in 1..=segments {
+            let t = i as f32 / segments as f32;
+            let u = 1.0 - t;
+            self.line([0, 1].map(|c| u * u * p0[c] + 2.0 * u * t * p1[c] + t * t * p2[c]));
+        }
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let p0 = self.last;
+        let [p1, p2, p3] = [[x1, y1], [x2, y2], [x, y]].map(|[x, y]| [x * self.scale, y * self.scale]);
+        let segments = Self::segments(&[p0, p1, p2, p3]);
+        for i in 1..=segments {
+            let t = i as f32 / segments as f32;
+            let u = 1.0 - t;
+            self.line([0, 1].map(|c| {
+                u * u * u * p0[c] + 3.0 * u * u * t * p1[c] + 3.0 * u * t * t * p2[c] + t * t * t * p3[c]
+            }));
+        }
+    }
+
+    fn close(&mut self) {
+        let start = self.start;
+        self.line(start);
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use bytemuck::Pod;
+use vulkano::buffer::{BufferContents, ImmutableBuffer};
+
+/// Geometry models share, uploaded to device-local memory the first time it is
+/// drawn and kept there until the last handle to it is dropped
+pub struct Mesh<T> where [T]: BufferContents {
+    vertices: Vec<T>,
+    indices: Vec<u32>,
+    /// Copy of the geometry on the GPU, once uploaded
+    buffers: Mutex<Option<MeshBuffers<T>>>
+}
+
+pub struct MeshBuffers<T> where [T]: BufferContents {
+    pub vertices: Arc<ImmutableBuffer<[T]>>,
+    pub indices: Arc<ImmutableBuffer<[u32]>>
+}
+impl<T> Clone for MeshBuffers<T> where [T]: BufferContents {
+    fn clone(&self) -> Self {
+        MeshBuffers { vertices: self.vertices.clone(), indices: self.indices.clone() }
+    }
+}
+
+impl<T: Pod> Mesh<T> where [T]: BufferContents {
+    /// Creates a mesh out of a list of triangles, sharing identical vertices
+    pub fn new(triangles: Vec<T>) -> Self {
+        let mut vertices = vec![];
+        let mut indices = Vec::with_capacity(triangles.len());
+        let mut known: HashMap<Vec<u8>, u32> = HashMap::new();
+        for vertex in triangles {
+            let index = *known.entry(bytemuck::bytes_of(&vertex).to_vec()).or_insert_with(|| {
+                vertices.push(vertex);
+                vertices.len() as u32 - 1
+            });
+            indices.push(index);
+        }
+        Mesh { vertices, indices, buffers: Mutex::new(None) }
+    }
+
+    /// Creates a mesh out of vertices already shared by triangles, three
+    /// indices at a time
+    pub fn indexed(vertices: Vec<T>, indices: Vec<u32>) -> Self {
+        Mesh { vertices, indices, buffers: Mutex::new(None) }
+    }
+}
+
+impl<T> Mesh<T> where [T]: BufferContents {
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    /// Returns the buffers on the GPU, uploading them with `upload` if they are
+    /// not there yet
+    pub fn buffers<F>(&self, upload: F) -> MeshBuffers<T>
+        where F: FnOnce(&[T], &[u32]) -> MeshBuffers<T>
+    {
+        self.buffers.lock().unwrap()
+            .get_or_insert_with(|| upload(&self.vertices, &self.indices))
+            .clone()
+    }
+}
@@ -1,7 +1,15 @@
+pub mod camera;
+pub mod canvas;
+pub mod glyph_atlas;
+pub mod mesh;
 pub mod rendering_system;
+pub mod shader;
+pub mod text;
+pub mod video;
 pub use rendering_system::RenderingSystem;
 pub use winit::event_loop::EventLoop;
 use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
@@ -22,19 +30,73 @@ impl Vertex2D {
 }
 vulkano::impl_vertex!(Vertex2D, position);
 
+/// Vertex of the shapes drawn over the frame, in straight alpha
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct OverlayVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4]
+}
+vulkano::impl_vertex!(OverlayVertex, position, color);
+
+/// Corner of a glyph drawn over the frame, along with where it is in the
+/// glyph atlas
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct TextVertex {
+    pub position: [f32; 2],
+    pub uv: [f32; 2]
+}
+vulkano::impl_vertex!(TextVertex, position, uv);
+
+/// What differs between the instances of a mesh drawn in a single call
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct InstanceData {
+    pub instance_model: [[f32; 4]; 4],
+    pub instance_normals: [[f32; 4]; 4],
+    /// Multiplies the colors of the mesh
+    pub instance_color: [f32; 3],
+    pub instance_emissive: [f32; 3],
+    pub instance_opacity: f32
+}
+vulkano::impl_vertex!(InstanceData, instance_model, instance_normals, instance_color, instance_emissive, instance_opacity);
+
+/// How a model is drawn, which can change every frame without touching its
+/// mesh
+#[derive(Debug, Clone, Copy)]
+pub struct MaterialParameters {
+    /// Multiplies the colors of the mesh
+    pub color: [f32; 3],
+    /// Light given off whatever the lights of the scene
+    pub emissive: [f32; 3],
+    /// Transparency is dithered, anything under 1 letting some of what is
+    /// behind through
+    pub opacity: f32
+}
+impl Default for MaterialParameters {
+    fn default() -> Self {
+        MaterialParameters {
+            color: [1.0, 1.0, 1.0],
+            emissive: [0.0, 0.0, 0.0],
+            opacity: 1.0
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct VP {
     pub view: glm::TMat4<f32>,
     pub projection: glm::TMat4<f32>
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct AmbientLight {
     pub color: [f32; 3],
     pub intensity: f32
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct DirectionalLight {
     pub position: [f32; 3],
     pub intensity: f32,
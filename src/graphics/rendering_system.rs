@@ -1,19 +1,26 @@
+use std::collections::HashMap;
+use std::fs;
 use std::mem;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use glm::{look_at, ortho, perspective, TMat4, vec3};
-use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, CpuBufferPool, TypedBufferAccess};
+use glm::{identity, scaling, TMat4, vec2, vec3};
+use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, CpuBufferPool, ImmutableBuffer, TypedBufferAccess};
+use vulkano::DeviceSize;
 use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer, SubpassContents};
 use vulkano::device::{physical::PhysicalDevice, DeviceExtensions, DeviceCreateInfo, QueueCreateInfo, Queue, Device};
 use vulkano::device::physical::PhysicalDeviceType;
-use vulkano::image::{AttachmentImage, ImageAccess, ImageUsage, SwapchainImage};
+use vulkano::image::{
+    AttachmentImage, ImageAccess, ImageCreateFlags, ImageDimensions, ImageUsage, ImmutableImage, MipmapsCount,
+    StorageImage
+};
 use vulkano::image::view::ImageView;
-use vulkano::format::Format;
+use vulkano::format::{Format, NumericType};
 use vulkano::instance::{Instance, InstanceCreateInfo};
 use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
 use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
 use vulkano::swapchain::{AcquireError, Surface, Swapchain, SwapchainAcquireFuture, SwapchainCreateInfo, SwapchainCreationError};
 use vulkano::{swapchain, sync};
-use vulkano::buffer::cpu_pool::CpuBufferPoolSubbuffer;
+use vulkano::buffer::cpu_pool::{CpuBufferPoolChunk, CpuBufferPoolSubbuffer};
 use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
 use vulkano::memory::pool::StdMemoryPool;
 use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
@@ -22,13 +29,32 @@ use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
 use vulkano::pipeline::graphics::color_blend::{AttachmentBlend, BlendFactor, BlendOp, ColorBlendState};
 use vulkano::pipeline::graphics::depth_stencil::DepthStencilState;
 use vulkano::pipeline::graphics::rasterization::{CullMode, RasterizationState};
+use vulkano::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo};
+use vulkano::shader::{EntryPoint, ShaderModule};
+use shaderc::ShaderKind;
 use vulkano::sync::{FlushError, GpuFuture};
 use vulkano_win::VkSurfaceBuild;
 use winit::event_loop::EventLoop;
 use winit::window::{Window, WindowBuilder};
 use winit::window::Fullscreen::Borderless;
 use super::{AmbientLight, DirectionalLight};
-use crate::graphics::{Vertex2D, VP};
+use super::canvas::Canvas;
+use super::glyph_atlas::{ATLAS_SIZE, GlyphAtlas, MAX_GLYPH_SIZE};
+use super::mesh::{Mesh, MeshBuffers};
+use super::shader::{self, AudioUniforms, CHANNEL_DIMENSIONS, Shadertoy, ShadertoyUniforms};
+use super::text::Text;
+use crate::graphics::camera::Camera;
+use crate::graphics::{InstanceData, MaterialParameters, OverlayVertex, TextVertex, Vertex2D, VP};
+use crate::resource_pool::NormalVertex;
+use crate::resource_pool::texture_loader::Texture;
+
+/// Directory the shaders are compiled from, whose files can be reloaded while
+/// developing
+pub const SHADER_DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/graphics/shaders");
+
+/// Format of the images rendered offscreen, which is also the layout of the
+/// frames read back from them
+const OFFSCREEN_FORMAT: Format = Format::R8G8B8A8_SRGB;
 
 #[derive(Debug)]
 pub enum RenderingState {
@@ -36,44 +62,217 @@ pub enum RenderingState {
     Deferred,
     Ambient,
     Directional,
+    Effects,
+    Overlay,
     WaitingRedraw,
 }
 #[derive(Debug)]
 pub enum RenderingError {
-    NonConformingState(String)
+    NonConformingState(String),
+    /// The frame could not be submitted to the GPU
+    Flush(String)
 }
 
 pub trait Render<T: Vertex> {
-    fn vertices(&self) -> Vec<T>;
+    fn mesh(&self) -> &Mesh<T>;
     fn model_matrices(&self) -> (TMat4<f32>, TMat4<f32>);
+    fn parameters(&self) -> MaterialParameters { Default::default() }
+    /// Material to draw with instead of the default one, if any
+    fn material(&self) -> Option<&Path> { None }
+    /// Texture the colors of the mesh are multiplied by, if any
+    fn texture(&self) -> Option<&Arc<Texture>> { None }
+}
+
+/// Something drawn as many instances of a mesh, in a single call
+pub trait RenderInstanced {
+    fn mesh(&self) -> &Mesh<NormalVertex>;
+    fn instances(&self) -> Vec<InstanceData>;
+    /// Material to draw with instead of the default one, if any
+    fn material(&self) -> Option<&Path> { None }
+    /// Texture the colors of the mesh are multiplied by, if any
+    fn texture(&self) -> Option<&Arc<Texture>> { None }
+}
+
+/// The deferred, directional, ambient, instanced, overlay and text pipelines
+type Pipelines = (
+    Arc<GraphicsPipeline>,
+    Arc<GraphicsPipeline>,
+    Arc<GraphicsPipeline>,
+    Arc<GraphicsPipeline>,
+    Arc<GraphicsPipeline>,
+    Arc<GraphicsPipeline>
+);
+
+/// Shaders the pipelines are made of
+#[derive(Clone)]
+struct Shaders {
+    deferred_vertex: Arc<ShaderModule>,
+    deferred_fragment: Arc<ShaderModule>,
+    ambient_vertex: Arc<ShaderModule>,
+    ambient_fragment: Arc<ShaderModule>,
+    directional_vertex: Arc<ShaderModule>,
+    directional_fragment: Arc<ShaderModule>,
+    instanced_vertex: Arc<ShaderModule>,
+    overlay_vertex: Arc<ShaderModule>,
+    overlay_fragment: Arc<ShaderModule>,
+    text_vertex: Arc<ShaderModule>,
+    text_fragment: Arc<ShaderModule>
+}
+impl Shaders {
+    /// Loads the shaders compiled along with the program
+    fn load(device: &Arc<Device>) -> Self {
+        Shaders {
+            deferred_vertex: deferred_vertex::load(device.clone()).unwrap(),
+            deferred_fragment: deferred_fragment::load(device.clone()).unwrap(),
+            ambient_vertex: ambient_vertex::load(device.clone()).unwrap(),
+            ambient_fragment: ambient_fragment::load(device.clone()).unwrap(),
+            directional_vertex: directional_vertex::load(device.clone()).unwrap(),
+            directional_fragment: directional_fragment::load(device.clone()).unwrap(),
+            instanced_vertex: instanced_vertex::load(device.clone()).unwrap(),
+            overlay_vertex: overlay_vertex::load(device.clone()).unwrap(),
+            overlay_fragment: overlay_fragment::load(device.clone()).unwrap(),
+            text_vertex: text_vertex::load(device.clone()).unwrap(),
+            text_fragment: text_fragment::load(device.clone()).unwrap()
+        }
+    }
+
+    /// Returns the shader compiled from the file of the given name
+    fn get_mut(&mut self, file_name: &str) -> Option<&mut Arc<ShaderModule>> {
+        match file_name {
+            "deferred.vert" => Some(&mut self.deferred_vertex),
+            "deferred.frag" => Some(&mut self.deferred_fragment),
+            "ambient.vert" => Some(&mut self.ambient_vertex),
+            "ambient.frag" => Some(&mut self.ambient_fragment),
+            "directional.vert" => Some(&mut self.directional_vertex),
+            "directional.frag" => Some(&mut self.directional_fragment),
+            "instanced.vert" => Some(&mut self.instanced_vertex),
+            "overlay.vert" => Some(&mut self.overlay_vertex),
+            "overlay.frag" => Some(&mut self.overlay_fragment),
+            "text.vert" => Some(&mut self.text_vertex),
+            "text.frag" => Some(&mut self.text_fragment),
+            _ => None
+        }
+    }
+}
+
+fn entry_point(shader: &ShaderModule) -> Result<EntryPoint<'_>, String> {
+    shader.entry_point("main").ok_or_else(|| String::from("No main function in shader"))
+}
+
+/// Creates a descriptor set out of the writes the pipeline has a binding for,
+/// as shaders compiled at runtime only get the descriptors they use. There is
+/// no set when they use none of them
+fn used_descriptor_set(
+    pipeline: &GraphicsPipeline,
+    set: usize,
+    writes: Vec<WriteDescriptorSet>
+) -> Option<Arc<PersistentDescriptorSet>> {
+    let layout = pipeline.layout().set_layouts().get(set)?;
+    if layout.bindings().is_empty() {
+        return None
+    }
+    let writes = writes.into_iter().filter(|w| layout.bindings().contains_key(&w.binding()));
+    Some(PersistentDescriptorSet::new(layout.clone(), writes).unwrap())
+}
+
+/// A fragment shader brought by a scene, and the pipeline drawing with it
+struct CustomShader {
+    fragment: Arc<ShaderModule>,
+    pipeline: Arc<GraphicsPipeline>
+}
+
+/// Custom shaders by the file they were compiled from
+type CustomShaders = HashMap<PathBuf, CustomShader>;
+
+/// Rebuilds the pipelines of custom shaders, after a shader they share changed
+fn rebuild_pipelines<F>(customs: &CustomShaders, create: F) -> Result<CustomShaders, String>
+    where F: Fn(&ShaderModule) -> Result<Arc<GraphicsPipeline>, String>
+{
+    customs.iter()
+        .map(|(path, custom)| Ok((path.clone(), CustomShader {
+            fragment: custom.fragment.clone(),
+            pipeline: create(&custom.fragment).map_err(|e| format!("{}: {}", path.display(), e))?
+        })))
+        .collect()
+}
+
+/// Where rendered frames end up
+enum RenderTarget {
+    /// Presented on a window
+    Window {
+        surface: Arc<Surface<Window>>,
+        swapchain: Arc<Swapchain<Window>>,
+        future: Option<SwapchainAcquireFuture<Window>>
+    },
+    /// Rendered into an image which is then copied back to the CPU
+    Offscreen {
+        image: Arc<AttachmentImage>,
+        frame: Arc<CpuAccessibleBuffer<[u8]>>
+    }
 }
 
 pub struct RenderingSystem {
-    surface: Arc<Surface<Window>>,
     device: Arc<Device>,
     queue: Arc<Queue>,
 
-    swapchain: Arc<Swapchain<Window>>,
+    target: RenderTarget,
 
+    /// Camera the view and projection are computed from, again on resize
+    camera: Camera,
     vp: VP,
     vp_descriptor_set: Arc<PersistentDescriptorSet>,
     vp_buffer: Arc<CpuAccessibleBuffer<deferred_vertex::ty::VP>>,
     model_buffer: CpuBufferPool<deferred_vertex::ty::Model>,
+    overlay_buffer: CpuBufferPool<overlay_vertex::ty::Overlay>,
+    canvas_vertex_buffer: CpuBufferPool<OverlayVertex>,
+    canvas_index_buffer: CpuBufferPool<u32>,
+    text_vertex_buffer: CpuBufferPool<TextVertex>,
+    instance_buffer: CpuBufferPool<InstanceData>,
     ambient_buffer: CpuBufferPool<ambient_fragment::ty::AmbientLight>,
     directional_buffer: CpuBufferPool<directional_fragment::ty::DirectionalLight>,
+    audio: AudioUniforms,
+    audio_buffer: CpuBufferPool<AudioUniforms>,
+    shadertoy_buffer: CpuBufferPool<ShadertoyUniforms>,
+    /// `iChannel0` of Shadertoy shaders, and what to fill it with next frame
+    channel: Arc<ImageView<StorageImage>>,
+    channel_upload: Option<Arc<CpuBufferPoolChunk<u8, Arc<StdMemoryPool>>>>,
+    /// Texels copied to the channel and the glyph atlas
+    texel_buffer: CpuBufferPool<u8>,
+    channel_sampler: Arc<Sampler>,
+    glyph_atlas: GlyphAtlas,
+    /// Texture of the glyph atlas, updated at the start of the frame after
+    /// glyphs are added to it
+    atlas: Arc<ImageView<StorageImage>>,
+    /// Textures of models by their address, uploaded the first time they are
+    /// drawn and dropped once no model holds them anymore
+    textures: HashMap<usize, (Arc<Texture>, Arc<ImageView<ImmutableImage>>)>,
+    /// Drawn with by models without a texture
+    white_texture: Arc<ImageView<ImmutableImage>>,
+    texture_sampler: Arc<Sampler>,
 
     render_pass: Arc<RenderPass>,
+    shaders: Shaders,
     deferred_pipeline: Arc<GraphicsPipeline>,
     directional_pipeline: Arc<GraphicsPipeline>,
     ambient_pipeline: Arc<GraphicsPipeline>,
+    instanced_pipeline: Arc<GraphicsPipeline>,
+    overlay_pipeline: Arc<GraphicsPipeline>,
+    text_pipeline: Arc<GraphicsPipeline>,
+    materials: CustomShaders,
+    /// The same materials, for instanced meshes
+    instanced_materials: CustomShaders,
+    effects: CustomShaders,
+    shadertoys: CustomShaders,
     viewport: Viewport,
+    /// Meshes uploaded since the last frame, which it waits for
+    uploads: Option<Box<dyn GpuFuture>>,
 
     framebuffers: Vec<Arc<Framebuffer>>,
     color_buffer: Arc<ImageView<AttachmentImage>>,
     normal_buffer: Arc<ImageView<AttachmentImage>>,
+    emissive_buffer: Arc<ImageView<AttachmentImage>>,
     vertex2d_buffer: Arc<CpuAccessibleBuffer<[Vertex2D]>>,
-    
-    future: Option<SwapchainAcquireFuture<Window>>,
+
     image_index: Option<usize>,
     commands: Option<AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>>,
     state: RenderingState
@@ -89,9 +288,26 @@ impl RenderingSystem {
         let surface = WindowBuilder::new()
             .build_vk_surface(event_loop, instance.clone())
             .unwrap();
+        let dimensions: [u32; 2] = surface.window().inner_size().into();
 
+        Self::with_target(instance, Some(surface), dimensions)
+    }
+
+    /// Creates a rendering system drawing into an offscreen image of the given
+    /// dimensions, which works without any window or display
+    pub fn new_headless(dimensions: [u32; 2]) -> (Self, Option<Box<dyn GpuFuture>>) {
+        let instance = Instance::new(InstanceCreateInfo::default()).unwrap();
+        Self::with_target(instance, None, dimensions)
+    }
+
+    /// Renders to the surface if there is one, offscreen otherwise
+    fn with_target(
+        instance: Arc<Instance>,
+        surface: Option<Arc<Surface<Window>>>,
+        dimensions: [u32; 2]
+    ) -> (Self, Option<Box<dyn GpuFuture>>) {
         let device_extensions = DeviceExtensions {
-            khr_swapchain: true,
+            khr_swapchain: surface.is_some(),
             ..DeviceExtensions::none()
         };
 
@@ -109,7 +325,10 @@ impl RenderingSystem {
             .filter(|&p| p.supported_extensions().is_superset_of(&device_extensions))
             .filter_map(|p| {
                 p.queue_families()
-                    .find(|&q| q.supports_graphics() && q.supports_surface(&surface).unwrap_or(false))
+                    .find(|&q| q.supports_graphics() && match &surface {
+                        Some(surface) => q.supports_surface(surface).unwrap_or(false),
+                        None => true
+                    })
                     .map(|q| (p, q))
             })
             .min_by_key(|(p, _)| match p.properties().device_type {
@@ -134,25 +353,51 @@ impl RenderingSystem {
         ).unwrap();
         let queue = queues.next().unwrap();
 
-        let mut vp = VP::default();
-        let (swapchain, images) = {
-            let capabilities = physical_device.surface_capabilities(&surface, Default::default()).unwrap();
-            let dimensions: [u32; 2] = surface.window().inner_size().into();
-            vp.projection = perspective(dimensions[0] as f32 / dimensions[1] as f32, 45.0, 0.01, 100.0);
-            vp.view = look_at(&vec3(0.0, 0.0, 0.01), &vec3(0.0, 0.0, 0.0), &vec3(0.0, -1.0, 0.0));
+        let camera = Camera::default();
+        let vp = VP {
+            projection: camera.projection(dimensions[0] as f32 / dimensions[1] as f32),
+            view: camera.view()
+        };
 
-            Swapchain::new(
-                device.clone(),
-                surface.clone(),
-                SwapchainCreateInfo {
-                    min_image_count: capabilities.min_image_count,
-                    image_format: Some(physical_device.surface_formats(&surface, Default::default()).unwrap()[0].0),
-                    image_extent: dimensions,
-                    image_usage: ImageUsage::color_attachment(),
-                    composite_alpha: capabilities.supported_composite_alpha.iter().next().unwrap(),
-                    ..Default::default()
-                },
-            ).unwrap()
+        let (target, images, image_format): (RenderTarget, Vec<Arc<dyn ImageAccess>>, Format) = match surface {
+            Some(surface) => {
+                let capabilities = physical_device.surface_capabilities(&surface, Default::default()).unwrap();
+                let (swapchain, images) = Swapchain::new(
+                    device.clone(),
+                    surface.clone(),
+                    SwapchainCreateInfo {
+                        min_image_count: capabilities.min_image_count,
+                        image_format: Some(physical_device.surface_formats(&surface, Default::default()).unwrap()[0].0),
+                        image_extent: dimensions,
+                        image_usage: ImageUsage::color_attachment(),
+                        composite_alpha: capabilities.supported_composite_alpha.iter().next().unwrap(),
+                        ..Default::default()
+                    },
+                ).unwrap();
+                let image_format = swapchain.image_format();
+                let images = images.into_iter().map(|i| i as Arc<dyn ImageAccess>).collect();
+
+                (RenderTarget::Window { surface, swapchain, future: None }, images, image_format)
+            },
+            None => {
+                let image = AttachmentImage::with_usage(
+                    device.clone(),
+                    dimensions,
+                    OFFSCREEN_FORMAT,
+                    ImageUsage { transfer_source: true, ..ImageUsage::none() }
+                ).unwrap();
+                let frame = unsafe {
+                    CpuAccessibleBuffer::uninitialized_array(
+                        device.clone(),
+                        (dimensions[0] * dimensions[1] * 4) as DeviceSize,
+                        BufferUsage::transfer_destination(),
+                        true
+                    ).unwrap()
+                };
+                let images = vec![image.clone() as Arc<dyn ImageAccess>];
+
+                (RenderTarget::Offscreen { image, frame }, images, OFFSCREEN_FORMAT)
+            }
         };
 
         let vp_buffer = CpuAccessibleBuffer::from_data(
@@ -165,15 +410,54 @@ impl RenderingSystem {
             }
         ).unwrap();
         let model_buffer = CpuBufferPool::<deferred_vertex::ty::Model>::uniform_buffer(device.clone());
+        let overlay_buffer = CpuBufferPool::<overlay_vertex::ty::Overlay>::uniform_buffer(device.clone());
+        let canvas_vertex_buffer = CpuBufferPool::<OverlayVertex>::vertex_buffer(device.clone());
+        let canvas_index_buffer = CpuBufferPool::<u32>::new(device.clone(), BufferUsage::index_buffer());
+        let text_vertex_buffer = CpuBufferPool::<TextVertex>::vertex_buffer(device.clone());
+        let instance_buffer = CpuBufferPool::<InstanceData>::vertex_buffer(device.clone());
         let ambient_buffer = CpuBufferPool::<ambient_fragment::ty::AmbientLight>::uniform_buffer(device.clone());
         let directional_buffer = CpuBufferPool::<directional_fragment::ty::DirectionalLight>::uniform_buffer(device.clone());
+        let audio_buffer = CpuBufferPool::<AudioUniforms>::uniform_buffer(device.clone());
+        let shadertoy_buffer = CpuBufferPool::<ShadertoyUniforms>::uniform_buffer(device.clone());
+        let texel_buffer = CpuBufferPool::<u8>::upload(device.clone());
+        let channel = ImageView::new_default(
+            StorageImage::with_usage(
+                device.clone(),
+                ImageDimensions::Dim2d { width: CHANNEL_DIMENSIONS[0], height: CHANNEL_DIMENSIONS[1], array_layers: 1 },
+                Format::R8_UNORM,
+                ImageUsage { transfer_destination: true, sampled: true, ..ImageUsage::none() },
+                ImageCreateFlags::none(),
+                [queue.family()]
+            ).unwrap()
+        ).unwrap();
+        let atlas = ImageView::new_default(
+            StorageImage::with_usage(
+                device.clone(),
+                ImageDimensions::Dim2d { width: ATLAS_SIZE, height: ATLAS_SIZE, array_layers: 1 },
+                Format::R8_UNORM,
+                ImageUsage { transfer_destination: true, sampled: true, ..ImageUsage::none() },
+                ImageCreateFlags::none(),
+                [queue.family()]
+            ).unwrap()
+        ).unwrap();
+        let channel_sampler = Sampler::new(device.clone(), SamplerCreateInfo {
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            address_mode: [SamplerAddressMode::ClampToEdge; 3],
+            ..Default::default()
+        }).unwrap();
+        let (white_texture, white_upload) = Self::upload_texture(
+            &queue,
+            &Texture { dimensions: [1, 1], pixels: vec![255; 4] }
+        );
+        let texture_sampler = Sampler::new(device.clone(), SamplerCreateInfo::simple_repeat_linear()).unwrap();
 
         let render_pass = vulkano::ordered_passes_renderpass!(device.clone(),
             attachments: {
                 final_color: {
                     load: Clear,
                     store: Store,
-                    format: swapchain.image_format(),
+                    format: image_format,
                     samples: 1,
                 },
                 vertex_color: {
@@ -188,6 +472,12 @@ impl RenderingSystem {
                     format: Format::R16G16B16A16_SFLOAT,
                     samples: 1,
                 },
+                emissive: {
+                    load: Clear,
+                    store: DontCare,
+                    format: Format::R16G16B16A16_SFLOAT,
+                    samples: 1,
+                },
                 depth: {
                     load: Clear,
                     store: DontCare,
@@ -197,49 +487,122 @@ impl RenderingSystem {
             },
             passes: [
                 {
-                    color: [vertex_color, normals],
+                    color: [vertex_color, normals, emissive],
                     depth_stencil: {depth},
                     input: []
                 },
                 {
                     color: [final_color],
                     depth_stencil: {},
-                    input: [vertex_color, normals]
+                    input: [vertex_color, normals, emissive]
+                },
+                {
+                    color: [final_color],
+                    depth_stencil: {},
+                    input: []
                 }
             ]
         ).unwrap();
 
-        let deferred_pass = Subpass::from(render_pass.clone(), 0).unwrap();
-        let lighting_pass = Subpass::from(render_pass.clone(), 1).unwrap();
+        let shaders = Shaders::load(&device);
+        let (deferred_pipeline, directional_pipeline, ambient_pipeline, instanced_pipeline, overlay_pipeline, text_pipeline) = Self::create_pipelines(&device, &render_pass, &shaders).unwrap();
 
-        let deferred_vertex = deferred_vertex::load(device.clone()).unwrap();
-        let deferred_fragment = deferred_fragment::load(device.clone()).unwrap();
-        let ambient_vertex = ambient_vertex::load(device.clone()).unwrap();
-        let ambient_fragment = ambient_fragment::load(device.clone()).unwrap();
-        let directional_vertex = directional_vertex::load(device.clone()).unwrap();
-        let directional_fragment = directional_fragment::load(device.clone()).unwrap();
+        let vp_layout = deferred_pipeline.layout().set_layouts().get(0).unwrap();
+        let vp_descriptor_set = PersistentDescriptorSet::new(
+            vp_layout.clone(),
+            [WriteDescriptorSet::buffer(0, vp_buffer.clone())]
+        ).unwrap();
 
-        let deferred_pipeline = GraphicsPipeline::start()
-            .vertex_input_state(BuffersDefinition::new().vertex::<crate::resource_pool::NormalVertex>())
-            .vertex_shader(deferred_vertex.entry_point("main").unwrap(), ())
-            .input_assembly_state(InputAssemblyState::new())
-            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
-            .fragment_shader(deferred_fragment.entry_point("main").unwrap(), ())
-            .depth_stencil_state(DepthStencilState::simple_depth_test())
-            .rasterization_state(
-                RasterizationState::new()
-                    .cull_mode(CullMode::Back)
-                    /*.polygon_mode(PolygonMode::Line)*/
-            )
-            .render_pass(deferred_pass.clone())
-            .build(device.clone())
-            .unwrap();
+        let mut viewport = Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [0.0, 0.0],
+            depth_range: 0.0..1.0,
+        };
+
+        let vertex2d_buffer = CpuAccessibleBuffer::from_iter(
+            device.clone(),
+            BufferUsage::all(),
+            false,
+            Vertex2D::screen_plane().iter().cloned()
+        ).unwrap();
+
+        let (framebuffers, color_buffer, normal_buffer, emissive_buffer) = Self::window_size_dependent_setup(&device, &images, render_pass.clone(), &mut viewport);
+        let previous_frame_end = Some(Box::new(sync::now(device.clone())) as Box<dyn GpuFuture>);
+
+        (RenderingSystem {
+            device,
+            queue,
+            target,
+            render_pass,
+            viewport,
+
+            shaders,
+            deferred_pipeline,
+            ambient_pipeline,
+            directional_pipeline,
+            instanced_pipeline,
+            overlay_pipeline,
+            text_pipeline,
+            materials: HashMap::new(),
+            instanced_materials: HashMap::new(),
+            effects: HashMap::new(),
+            shadertoys: HashMap::new(),
+            uploads: Some(white_upload.boxed()),
+
+            framebuffers,
+            color_buffer,
+            normal_buffer,
+            emissive_buffer,
+            vertex2d_buffer,
+
+            camera,
+            vp,
+            vp_descriptor_set,
+            vp_buffer,
+            model_buffer,
+            overlay_buffer,
+            canvas_vertex_buffer,
+            canvas_index_buffer,
+            text_vertex_buffer,
+            instance_buffer,
+            ambient_buffer,
+            directional_buffer,
+            audio: Default::default(),
+            audio_buffer,
+            shadertoy_buffer,
+            channel,
+            channel_upload: None,
+            texel_buffer,
+            channel_sampler,
+            glyph_atlas: Default::default(),
+            atlas,
+            textures: HashMap::new(),
+            white_texture,
+            texture_sampler,
+
+            image_index: None,
+            commands: None,
+            state: RenderingState::Stopped,
+        }, previous_frame_end)
+    }
+
+    /// Creates the deferred, directional, ambient, instanced, overlay and
+    /// text pipelines out of shaders
+    fn create_pipelines(
+        device: &Arc<Device>,
+        render_pass: &Arc<RenderPass>,
+        shaders: &Shaders
+    ) -> Result<Pipelines, String> {
+        let lighting_pass = Subpass::from(render_pass.clone(), 1).unwrap();
+
+        let deferred_pipeline = Self::material_pipeline(device, render_pass, &shaders.deferred_vertex, &shaders.deferred_fragment, false)?;
+        let instanced_pipeline = Self::material_pipeline(device, render_pass, &shaders.instanced_vertex, &shaders.deferred_fragment, true)?;
         let directional_pipeline = GraphicsPipeline::start()
             .vertex_input_state(BuffersDefinition::new().vertex::<Vertex2D>())
-            .vertex_shader(directional_vertex.entry_point("main").unwrap(), ())
+            .vertex_shader(entry_point(&shaders.directional_vertex)?, ())
             .input_assembly_state(InputAssemblyState::new())
             .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
-            .fragment_shader(directional_fragment.entry_point("main").unwrap(), ())
+            .fragment_shader(entry_point(&shaders.directional_fragment)?, ())
             .color_blend_state(
                 ColorBlendState::new(lighting_pass.num_color_attachments()).blend(
                     AttachmentBlend {
@@ -254,13 +617,13 @@ impl RenderingSystem {
             )
             .render_pass(lighting_pass.clone())
             .build(device.clone())
-            .unwrap();
+            .map_err(|e| e.to_string())?;
         let ambient_pipeline = GraphicsPipeline::start()
             .vertex_input_state(BuffersDefinition::new().vertex::<Vertex2D>())
-            .vertex_shader(ambient_vertex.entry_point("main").unwrap(), ())
+            .vertex_shader(entry_point(&shaders.ambient_vertex)?, ())
             .input_assembly_state(InputAssemblyState::new())
             .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
-            .fragment_shader(ambient_fragment.entry_point("main").unwrap(), ())
+            .fragment_shader(entry_point(&shaders.ambient_fragment)?, ())
             .color_blend_state(
                 ColorBlendState::new(lighting_pass.num_color_attachments()).blend(
                     AttachmentBlend {
@@ -275,59 +638,243 @@ impl RenderingSystem {
             )
             .render_pass(lighting_pass.clone())
             .build(device.clone())
-            .unwrap();
+            .map_err(|e| e.to_string())?;
+        let overlay_pass = Subpass::from(render_pass.clone(), 2).unwrap();
+        let overlay_pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().vertex::<OverlayVertex>())
+            .vertex_shader(entry_point(&shaders.overlay_vertex)?, ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(entry_point(&shaders.overlay_fragment)?, ())
+            .color_blend_state(ColorBlendState::new(overlay_pass.num_color_attachments()).blend_alpha())
+            .render_pass(overlay_pass.clone())
+            .build(device.clone())
+            .map_err(|e| e.to_string())?;
+        let text_pipeline = GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().vertex::<TextVertex>())
+            .vertex_shader(entry_point(&shaders.text_vertex)?, ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(entry_point(&shaders.text_fragment)?, ())
+            .color_blend_state(ColorBlendState::new(overlay_pass.num_color_attachments()).blend_alpha())
+            .render_pass(overlay_pass)
+            .build(device.clone())
+            .map_err(|e| e.to_string())?;
 
-        let vp_layout = deferred_pipeline.layout().set_layouts().get(0).unwrap();
-        let vp_descriptor_set = PersistentDescriptorSet::new(
+        Ok((deferred_pipeline, directional_pipeline, ambient_pipeline, instanced_pipeline, overlay_pipeline, text_pipeline))
+    }
+
+    /// Creates a pipeline drawing models into the geometry buffers, the
+    /// fragment shader being their material. Instanced pipelines also take
+    /// the data of each instance as vertex input
+    fn material_pipeline(
+        device: &Arc<Device>,
+        render_pass: &Arc<RenderPass>,
+        vertex: &ShaderModule,
+        fragment: &ShaderModule,
+        instanced: bool
+    ) -> Result<Arc<GraphicsPipeline>, String> {
+        let mut vertex_input = BuffersDefinition::new().vertex::<NormalVertex>();
+        if instanced {
+            vertex_input = vertex_input.instance::<InstanceData>();
+        }
+        GraphicsPipeline::start()
+            .vertex_input_state(vertex_input)
+            .vertex_shader(entry_point(vertex)?, ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(entry_point(fragment)?, ())
+            .depth_stencil_state(DepthStencilState::simple_depth_test())
+            .rasterization_state(
+                RasterizationState::new()
+                    .cull_mode(CullMode::Back)
+                    /*.polygon_mode(PolygonMode::Line)*/
+            )
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(device.clone())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Creates a pipeline drawing a fragment shader over the whole frame,
+    /// either blended with what is already there or replacing it
+    fn fullscreen_pipeline(
+        device: &Arc<Device>,
+        render_pass: &Arc<RenderPass>,
+        vertex: &ShaderModule,
+        fragment: &ShaderModule,
+        blend: Option<AttachmentBlend>
+    ) -> Result<Arc<GraphicsPipeline>, String> {
+        let lighting_pass = Subpass::from(render_pass.clone(), 1).unwrap();
+        let mut color_blend_state = ColorBlendState::new(lighting_pass.num_color_attachments());
+        if let Some(blend) = blend {
+            color_blend_state = color_blend_state.blend(blend);
+        }
+        GraphicsPipeline::start()
+            .vertex_input_state(BuffersDefinition::new().vertex::<Vertex2D>())
+            .vertex_shader(entry_point(vertex)?, ())
+            .input_assembly_state(InputAssemblyState::new())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .fragment_shader(entry_point(fragment)?, ())
+            .color_blend_state(color_blend_state)
+            .render_pass(lighting_pass)
+            .build(device.clone())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Recompiles a shader from its source, and rebuilds the pipelines with it.
+    /// The pipelines are left untouched if anything goes wrong
+    pub fn reload_shader(&mut self, path: &Path) -> Result<(), String> {
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        let mut shaders = self.shaders.clone();
+        *shaders.get_mut(file_name).ok_or(format!("{} is not used by any pipeline", file_name))? = shader::compile_file(&self.device, path)?;
+        let (deferred_pipeline, directional_pipeline, ambient_pipeline, instanced_pipeline, overlay_pipeline, text_pipeline) = Self::create_pipelines(&self.device, &self.render_pass, &shaders)?;
+        // Custom shaders depend on the vertex shaders
+        let (device, render_pass) = (&self.device, &self.render_pass);
+        let materials = rebuild_pipelines(&self.materials, |f| Self::material_pipeline(device, render_pass, &shaders.deferred_vertex, f, false))?;
+        let instanced_materials = rebuild_pipelines(&self.instanced_materials, |f| {
+            Self::material_pipeline(device, render_pass, &shaders.instanced_vertex, f, true)
+        })?;
+        let effects = rebuild_pipelines(&self.effects, |f| {
+            Self::fullscreen_pipeline(device, render_pass, &shaders.ambient_vertex, f, Some(AttachmentBlend::alpha()))
+        })?;
+        let shadertoys = rebuild_pipelines(&self.shadertoys, |f| {
+            Self::fullscreen_pipeline(device, render_pass, &shaders.ambient_vertex, f, None)
+        })?;
+
+        self.shaders = shaders;
+        self.deferred_pipeline = deferred_pipeline;
+        self.directional_pipeline = directional_pipeline;
+        self.ambient_pipeline = ambient_pipeline;
+        self.instanced_pipeline = instanced_pipeline;
+        self.overlay_pipeline = overlay_pipeline;
+        self.text_pipeline = text_pipeline;
+        self.materials = materials;
+        self.instanced_materials = instanced_materials;
+        self.effects = effects;
+        self.shadertoys = shadertoys;
+        // The view and projection were bound to the layout of the old pipeline
+        let vp_layout = self.deferred_pipeline.layout().set_layouts().get(0).unwrap();
+        self.vp_descriptor_set = PersistentDescriptorSet::new(
             vp_layout.clone(),
-            [WriteDescriptorSet::buffer(0, vp_buffer.clone())]
+            [WriteDescriptorSet::buffer(0, self.vp_buffer.clone())]
         ).unwrap();
+        Ok(())
+    }
 
-        let mut viewport = Viewport {
-            origin: [0.0, 0.0],
-            dimensions: [0.0, 0.0],
-            depth_range: 0.0..1.0,
-        };
+    /// Compiles a material that models can then be drawn with, replacing the
+    /// one compiled from the same file if any
+    pub fn load_material(&mut self, path: &Path) -> Result<(), String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let fragment = shader::compile(&self.device, &shader::material_source(&source), ShaderKind::Fragment, &path.display().to_string())?;
+        let pipeline = Self::material_pipeline(&self.device, &self.render_pass, &self.shaders.deferred_vertex, &fragment, false)?;
+        let instanced_pipeline = Self::material_pipeline(&self.device, &self.render_pass, &self.shaders.instanced_vertex, &fragment, true)?;
+        self.materials.insert(path.to_path_buf(), CustomShader { fragment: fragment.clone(), pipeline });
+        self.instanced_materials.insert(path.to_path_buf(), CustomShader { fragment, pipeline: instanced_pipeline });
+        Ok(())
+    }
 
-        let vertex2d_buffer = CpuAccessibleBuffer::from_iter(
-            device.clone(),
-            BufferUsage::all(),
-            false,
-            Vertex2D::screen_plane().iter().cloned()
-        ).unwrap();
+    /// Returns the buffers of a mesh, uploading it first if it is not on the
+    /// GPU yet. Uploads are done along with the next frame
+    fn mesh_buffers<T: Vertex>(&mut self, mesh: &Mesh<T>) -> MeshBuffers<T> {
+        let (queue, uploads) = (&self.queue, &mut self.uploads);
+        mesh.buffers(|vertices, indices| {
+            let (vertex_buffer, vertex_upload) = ImmutableBuffer::from_iter(
+                vertices.iter().copied(),
+                BufferUsage::vertex_buffer(),
+                queue.clone()
+            ).unwrap();
+            let (index_buffer, index_upload) = ImmutableBuffer::from_iter(
+                indices.iter().copied(),
+                BufferUsage::index_buffer(),
+                queue.clone()
+            ).unwrap();
+            let upload = vertex_upload.join(index_upload);
+            *uploads = Some(match uploads.take() {
+                Some(previous) => previous.join(upload).boxed(),
+                None => upload.boxed()
+            });
+            MeshBuffers { vertices: vertex_buffer, indices: index_buffer }
+        })
+    }
 
-        let (framebuffers, color_buffer, normal_buffer) = Self::window_size_dependent_setup(&device, &images, render_pass.clone(), &mut viewport);
-        let previous_frame_end = Some(Box::new(sync::now(device.clone())) as Box<dyn GpuFuture>);
+    /// Returns the image of a texture, uploading it first if it is not on the
+    /// GPU yet. Uploads are done along with the next frame
+    fn texture_view(&mut self, texture: Option<&Arc<Texture>>) -> Arc<ImageView<ImmutableImage>> {
+        let texture = match texture {
+            Some(texture) if texture.dimensions[0] > 0 && texture.dimensions[1] > 0 => texture,
+            _ => return self.white_texture.clone()
+        };
+        let (queue, uploads) = (&self.queue, &mut self.uploads);
+        let (_, view) = self.textures.entry(Arc::as_ptr(texture) as usize).or_insert_with(|| {
+            let (view, upload) = Self::upload_texture(queue, texture);
+            *uploads = Some(match uploads.take() {
+                Some(previous) => previous.join(upload).boxed(),
+                None => upload.boxed()
+            });
+            (texture.clone(), view)
+        });
+        view.clone()
+    }
 
-        (RenderingSystem {
-            surface,
-            device,
-            queue,
-            swapchain,
-            render_pass,
-            viewport,
+    /// Uploads a texture along with its mipmaps, scaling it down first if it
+    /// is larger than the device supports
+    fn upload_texture(queue: &Arc<Queue>, texture: &Texture) -> (Arc<ImageView<ImmutableImage>>, impl GpuFuture) {
+        let max_dimension = queue.device().physical_device().properties().max_image_dimension2_d;
+        let fitted;
+        let texture = if texture.dimensions.iter().any(|&d| d > max_dimension) {
+            fitted = texture.fitted(max_dimension);
+            &fitted
+        } else {
+            texture
+        };
+        let (image, upload) = ImmutableImage::from_iter(
+            texture.pixels.iter().copied(),
+            ImageDimensions::Dim2d { width: texture.dimensions[0], height: texture.dimensions[1], array_layers: 1 },
+            MipmapsCount::Log2,
+            Format::R8G8B8A8_SRGB,
+            queue.clone()
+        ).unwrap();
+        (ImageView::new_default(image).unwrap(), upload)
+    }
 
-            deferred_pipeline,
-            ambient_pipeline,
-            directional_pipeline,
+    /// Compiles an effect that can then be drawn over frames, replacing the
+    /// one compiled from the same file if any
+    pub fn load_effect(&mut self, path: &Path) -> Result<(), String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let fragment = shader::compile(&self.device, &shader::effect_source(&source), ShaderKind::Fragment, &path.display().to_string())?;
+        let pipeline = Self::fullscreen_pipeline(
+            &self.device,
+            &self.render_pass,
+            &self.shaders.ambient_vertex,
+            &fragment,
+            Some(AttachmentBlend::alpha())
+        )?;
+        self.effects.insert(path.to_path_buf(), CustomShader { fragment, pipeline });
+        Ok(())
+    }
 
-            framebuffers,
-            color_buffer,
-            normal_buffer,
-            vertex2d_buffer,
+    /// Compiles a Shadertoy shader, replacing the one compiled from the same
+    /// file if any
+    pub fn load_shadertoy(&mut self, path: &Path) -> Result<(), String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let srgb = self.render_pass.attachments()[0].format.and_then(|f| f.type_color()) == Some(NumericType::SRGB);
+        let fragment = shader::compile(&self.device, &shader::shadertoy_source(&source, srgb), ShaderKind::Fragment, &path.display().to_string())?;
+        let pipeline = Self::fullscreen_pipeline(&self.device, &self.render_pass, &self.shaders.ambient_vertex, &fragment, None)?;
+        self.shadertoys.insert(path.to_path_buf(), CustomShader { fragment, pipeline });
+        Ok(())
+    }
 
-            vp,
-            vp_descriptor_set,
-            vp_buffer,
-            model_buffer,
-            ambient_buffer,
-            directional_buffer,
+    /// Sets the texels of the `iChannel0` of Shadertoy shaders, from the next
+    /// render on
+    pub fn set_channel(&mut self, texels: &[u8]) {
+        self.channel_upload = Some(self.texel_buffer.chunk(texels.iter().copied()).unwrap());
+    }
 
-            image_index: None,
-            future: None,
-            commands: None,
-            state: RenderingState::Stopped,
-        }, previous_frame_end)
+    /// Sets what materials and effects know about the sound for the next
+    /// frames
+    pub fn set_audio(&mut self, audio: AudioUniforms) {
+        self.audio = audio;
+        self.audio.resolution = self.viewport.dimensions;
     }
 
     pub fn start_render(&mut self) -> Result<(), RenderingError> {
@@ -350,22 +897,28 @@ impl RenderingSystem {
             }
         };
 
-        let (image, image_num, future) = match swapchain::acquire_next_image(self.swapchain.clone(), None) {
-            Ok((image_num, suboptimal, future)) => {
-                if suboptimal { self.recreate_swapchain() }
-                (self.framebuffers[image_num].clone(), image_num, future)
-            },
-            Err(AcquireError::OutOfDate) => {
-                self.recreate_swapchain();
-                return Err(RenderingError::NonConformingState(format!("No image")))
+        let image_num = match &mut self.target {
+            RenderTarget::Window { swapchain, future, .. } => match swapchain::acquire_next_image(swapchain.clone(), None) {
+                Ok((image_num, suboptimal, acquire_future)) => {
+                    *future = Some(acquire_future);
+                    if suboptimal { self.recreate_swapchain() }
+                    image_num
+                },
+                Err(AcquireError::OutOfDate) => {
+                    self.recreate_swapchain();
+                    return Err(RenderingError::NonConformingState(format!("No image")))
+                },
+                Err(e) => panic!("{:?}", e)
             },
-            Err(e) => panic!("{:?}", e)
+            RenderTarget::Offscreen { .. } => 0
         };
+        let image = self.framebuffers[image_num].clone();
 
         let clear_values = vec![
             [0.0, 0.0, 0.0, 1.0].into(),
             [0.0, 0.0, 0.0, 1.0].into(),
             [0.0, 0.0, 0.0, 1.0].into(),
+            [0.0, 0.0, 0.0, 1.0].into(),
             1f32.into()
         ];
         let mut commands = AutoCommandBufferBuilder::primary(
@@ -373,6 +926,16 @@ impl RenderingSystem {
             self.queue.family(),
             CommandBufferUsage::OneTimeSubmit
         ).unwrap();
+        // Textures no model holds anymore are not going to be drawn again
+        self.textures.retain(|_, (texture, _)| Arc::strong_count(texture) > 1);
+        // Images cannot be written to within a render pass
+        if let Some(texels) = self.channel_upload.take() {
+            commands.copy_buffer_to_image(texels, self.channel.image().clone()).unwrap();
+        }
+        if let Some(texels) = self.glyph_atlas.take_changes() {
+            let texels = self.texel_buffer.chunk(texels.iter().copied()).unwrap();
+            commands.copy_buffer_to_image(texels, self.atlas.image().clone()).unwrap();
+        }
         commands
             .begin_render_pass(
                 image,
@@ -382,7 +945,6 @@ impl RenderingSystem {
             .unwrap();
 
         self.commands = Some(commands);
-        self.future = Some(future);
         self.image_index = Some(image_num);
 
         Ok(())
@@ -406,40 +968,112 @@ impl RenderingSystem {
 
         let model_subbuffer = {
             let (model, normal) = object.model_matrices();
+            let parameters = object.parameters();
             let uniform_data = deferred_vertex::ty::Model {
                 model: model.into(),
-                normals: normal.into()
+                normals: normal.into(),
+                color: parameters.color,
+                opacity: parameters.opacity,
+                emissive: parameters.emissive
             };
 
             self.model_buffer.next(uniform_data).unwrap()
         };
-        let model_layout = self.deferred_pipeline.layout().set_layouts().get(1).unwrap();
-        let model_descriptor_set = PersistentDescriptorSet::new(
-            model_layout.clone(),
-            [
-                WriteDescriptorSet::buffer(0, model_subbuffer)
-            ]
-        ).unwrap();
-
-        let vertex_buffer = CpuAccessibleBuffer::from_iter(
-            self.device.clone(),
-            BufferUsage::all(),
-            false,
-            object.vertices().iter().cloned()
-        ).unwrap();
+        // Models whose material failed to compile keep the default one
+        let pipeline = object.material()
+            .and_then(|m| self.materials.get(m))
+            .map(|m| m.pipeline.clone())
+            .unwrap_or_else(|| self.deferred_pipeline.clone());
+
+        if object.mesh().indices().is_empty() {
+            return Ok(())
+        }
+        // Materials may not sample the texture
+        let texture = self.texture_view(object.texture());
+        let model_descriptor_set = used_descriptor_set(&pipeline, 1, vec![
+            WriteDescriptorSet::buffer(0, model_subbuffer),
+            WriteDescriptorSet::image_view_sampler(1, texture, self.texture_sampler.clone())
+        ]).unwrap();
+        let mesh = self.mesh_buffers(object.mesh());
 
         let mut commands = self.commands.take().unwrap();
         commands
             .set_viewport(0, [self.viewport.clone()])
-            .bind_pipeline_graphics(self.deferred_pipeline.clone())
+            .bind_pipeline_graphics(pipeline.clone())
             .bind_descriptor_sets(
                 PipelineBindPoint::Graphics,
-                self.deferred_pipeline.layout().clone(),
+                pipeline.layout().clone(),
                 0,
                 (self.vp_descriptor_set.clone(), model_descriptor_set)
-            )
-            .bind_vertex_buffers(0, vertex_buffer.clone())
-            .draw(vertex_buffer.len() as u32, 1, 0, 0)
+            );
+        let audio_subbuffer = self.audio_buffer.next(self.audio).unwrap();
+        if let Some(audio_descriptor_set) = used_descriptor_set(&pipeline, 2, vec![WriteDescriptorSet::buffer(0, audio_subbuffer)]) {
+            commands.bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 2, audio_descriptor_set);
+        }
+        commands
+            .bind_vertex_buffers(0, mesh.vertices)
+            .bind_index_buffer(mesh.indices.clone())
+            .draw_indexed(mesh.indices.len() as u32, 1, 0, 0, 0)
+            .unwrap();
+
+        self.commands = Some(commands);
+
+        Ok(())
+    }
+
+    /// Draws every instance of a mesh at once, only their data being uploaded
+    /// every frame
+    pub fn add_instances<T: RenderInstanced>(&mut self, object: &T) -> Result<(), RenderingError> {
+        match self.state {
+            RenderingState::Deferred => (),
+            RenderingState::WaitingRedraw => {
+                self.recreate_swapchain();
+                self.state = RenderingState::Stopped;
+                self.commands = None;
+                return Err(RenderingError::NonConformingState(String::new()))
+            },
+            _ => {
+                self.state = RenderingState::Stopped;
+                self.commands = None;
+                return Err(RenderingError::NonConformingState(String::new()))
+            }
+        }
+
+        let instances = object.instances();
+        if instances.is_empty() || object.mesh().indices().is_empty() {
+            return Ok(())
+        }
+        let mesh = self.mesh_buffers(object.mesh());
+        let instance_count = instances.len() as u32;
+        let instance_buffer = self.instance_buffer.chunk(instances).unwrap();
+        let pipeline = object.material()
+            .and_then(|m| self.instanced_materials.get(m))
+            .map(|m| m.pipeline.clone())
+            .unwrap_or_else(|| self.instanced_pipeline.clone());
+        let texture = self.texture_view(object.texture());
+
+        let mut commands = self.commands.take().unwrap();
+        commands
+            .set_viewport(0, [self.viewport.clone()])
+            .bind_pipeline_graphics(pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                self.vp_descriptor_set.clone()
+            );
+        let texture_writes = vec![WriteDescriptorSet::image_view_sampler(1, texture, self.texture_sampler.clone())];
+        if let Some(texture_descriptor_set) = used_descriptor_set(&pipeline, 1, texture_writes) {
+            commands.bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 1, texture_descriptor_set);
+        }
+        let audio_subbuffer = self.audio_buffer.next(self.audio).unwrap();
+        if let Some(audio_descriptor_set) = used_descriptor_set(&pipeline, 2, vec![WriteDescriptorSet::buffer(0, audio_subbuffer)]) {
+            commands.bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 2, audio_descriptor_set);
+        }
+        commands
+            .bind_vertex_buffers(0, (mesh.vertices, instance_buffer))
+            .bind_index_buffer(mesh.indices.clone())
+            .draw_indexed(mesh.indices.len() as u32, instance_count, 0, 0, 0)
             .unwrap();
 
         self.commands = Some(commands);
@@ -474,26 +1108,27 @@ impl RenderingSystem {
             }
         ).unwrap();
 
-        let ambient_layout = self.ambient_pipeline.layout().set_layouts().get(0).unwrap();
-        let ambient_descriptor_set = PersistentDescriptorSet::new(
-            ambient_layout.clone(),
-            [
-                WriteDescriptorSet::image_view(0, self.color_buffer.clone()),
-                WriteDescriptorSet::buffer(1, ambient_buffer),
-            ]
-        ).unwrap();
+        // The shader, which can be reloaded, may leave some of these out
+        let ambient_descriptor_set = used_descriptor_set(&self.ambient_pipeline, 0, vec![
+            WriteDescriptorSet::image_view(0, self.color_buffer.clone()),
+            WriteDescriptorSet::buffer(1, ambient_buffer),
+            WriteDescriptorSet::image_view(2, self.emissive_buffer.clone()),
+        ]);
 
         let mut commands = self.commands.take().unwrap();
         commands
             .next_subpass(SubpassContents::Inline)
             .unwrap()
-            .bind_pipeline_graphics(self.ambient_pipeline.clone())
-            .bind_descriptor_sets(
+            .bind_pipeline_graphics(self.ambient_pipeline.clone());
+        if let Some(ambient_descriptor_set) = ambient_descriptor_set {
+            commands.bind_descriptor_sets(
                 PipelineBindPoint::Graphics,
                 self.ambient_pipeline.layout().clone(),
                 0,
                 ambient_descriptor_set
-            )
+            );
+        }
+        commands
             .set_viewport(0, [self.viewport.clone()])
             .bind_vertex_buffers(0, self.vertex2d_buffer.clone())
             .draw(self.vertex2d_buffer.len() as u32, 1, 0,0)
@@ -551,9 +1186,310 @@ impl RenderingSystem {
         Ok(())
     }
 
+    /// Draws a Shadertoy shader over the whole frame, in place of models and
+    /// lights. Shaders that are not loaded leave the frame black
+    pub fn draw_shadertoy(&mut self, shadertoy: &Shadertoy) -> Result<(), RenderingError> {
+        match self.state {
+            RenderingState::Deferred => self.state = RenderingState::Effects,
+            RenderingState::WaitingRedraw => {
+                self.recreate_swapchain();
+                self.state = RenderingState::Stopped;
+                self.commands = None;
+                return Err(RenderingError::NonConformingState(String::new()));
+            },
+            _ => {
+                self.state = RenderingState::Stopped;
+                self.commands = None;
+                return Err(RenderingError::NonConformingState(String::new()));
+            }
+        }
+
+        let mut commands = self.commands.take().unwrap();
+        commands
+            .next_subpass(SubpassContents::Inline)
+            .unwrap();
+        if let Some(shadertoy_shader) = self.shadertoys.get(&shadertoy.path) {
+            let pipeline = shadertoy_shader.pipeline.clone();
+            let [width, height] = self.viewport.dimensions;
+            let channel_resolution = [CHANNEL_DIMENSIONS[0] as f32, CHANNEL_DIMENSIONS[1] as f32, 1.0, 0.0];
+            let uniforms = ShadertoyUniforms {
+                resolution: [width, height, 1.0],
+                channel_resolution: [channel_resolution; 4],
+                ..shadertoy.uniforms
+            };
+            let descriptor_set = used_descriptor_set(&pipeline, 0, vec![
+                WriteDescriptorSet::buffer(0, self.shadertoy_buffer.next(uniforms).unwrap()),
+                WriteDescriptorSet::image_view_sampler(1, self.channel.clone(), self.channel_sampler.clone())
+            ]);
+
+            commands
+                .set_viewport(0, [self.viewport.clone()])
+                .bind_pipeline_graphics(pipeline.clone())
+                .bind_vertex_buffers(0, self.vertex2d_buffer.clone());
+            if let Some(descriptor_set) = descriptor_set {
+                commands.bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), 0, descriptor_set);
+            }
+            commands
+                .draw(self.vertex2d_buffer.len() as u32, 1, 0, 0)
+                .unwrap();
+        }
+        self.commands = Some(commands);
+
+        Ok(())
+    }
+
+    /// Draws an effect over the lit frame, effects being drawn in the order
+    /// they are added. Effects that are not loaded are skipped
+    pub fn add_effect(&mut self, path: &Path) -> Result<(), RenderingError> {
+        match self.state {
+            RenderingState::Ambient | RenderingState::Directional | RenderingState::Effects => self.state = RenderingState::Effects,
+            RenderingState::WaitingRedraw => {
+                self.recreate_swapchain();
+                self.state = RenderingState::Stopped;
+                self.commands = None;
+                return Err(RenderingError::NonConformingState(String::new()));
+            },
+            _ => {
+                self.state = RenderingState::Stopped;
+                self.commands = None;
+                return Err(RenderingError::NonConformingState(String::new()));
+            }
+        }
+
+        let pipeline = match self.effects.get(path) {
+            Some(effect) => effect.pipeline.clone(),
+            None => return Ok(())
+        };
+        let input_descriptor_set = used_descriptor_set(&pipeline, 0, vec![
+            WriteDescriptorSet::image_view(0, self.color_buffer.clone()),
+            WriteDescriptorSet::image_view(1, self.normal_buffer.clone())
+        ]);
+        let audio_subbuffer = self.audio_buffer.next(self.audio).unwrap();
+        let audio_descriptor_set = used_descriptor_set(&pipeline, 1, vec![WriteDescriptorSet::buffer(0, audio_subbuffer)]);
+
+        let mut commands = self.commands.take().unwrap();
+        commands
+            .set_viewport(0, [self.viewport.clone()])
+            .bind_pipeline_graphics(pipeline.clone())
+            .bind_vertex_buffers(0, self.vertex2d_buffer.clone());
+        for (set, descriptor_set) in [(0, input_descriptor_set), (1, audio_descriptor_set)] {
+            if let Some(descriptor_set) = descriptor_set {
+                commands.bind_descriptor_sets(PipelineBindPoint::Graphics, pipeline.layout().clone(), set, descriptor_set);
+            }
+        }
+        commands
+            .draw(self.vertex2d_buffer.len() as u32, 1, 0, 0)
+            .unwrap();
+        self.commands = Some(commands);
+
+        Ok(())
+    }
+
+    /// Draws a 2D shape over the frame, once it is lit and its effects drawn.
+    /// The frame spans [-1, 1] vertically, y pointing down, and as much as its
+    /// aspect ratio allows horizontally
+    pub fn add_overlay<T: Render<OverlayVertex>>(&mut self, object: &T) -> Result<(), RenderingError> {
+        let mut commands = self.overlay_commands()?;
+
+        if !object.mesh().indices().is_empty() {
+            let (model, _) = object.model_matrices();
+            let parameters = object.parameters();
+            let [r, g, b] = parameters.color;
+            let overlay_descriptor_set = self.overlay_descriptor_set(&model, [r, g, b, parameters.opacity]);
+            let mesh = self.mesh_buffers(object.mesh());
+
+            commands
+                .set_viewport(0, [self.viewport.clone()])
+                .bind_pipeline_graphics(self.overlay_pipeline.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    self.overlay_pipeline.layout().clone(),
+                    0,
+                    overlay_descriptor_set
+                )
+                .bind_vertex_buffers(0, mesh.vertices)
+                .bind_index_buffer(mesh.indices.clone())
+                .draw_indexed(mesh.indices.len() as u32, 1, 0, 0, 0)
+                .unwrap();
+        }
+        self.commands = Some(commands);
+
+        Ok(())
+    }
+
+    /// Draws the shapes of a canvas over the frame, like overlays. They are
+    /// uploaded again every frame, as canvases are usually drawn anew
+    pub fn add_canvas(&mut self, canvas: &Canvas) -> Result<(), RenderingError> {
+        let mut commands = self.overlay_commands()?;
+
+        if !canvas.indices().is_empty() {
+            let overlay_descriptor_set = self.overlay_descriptor_set(&identity(), [1.0; 4]);
+            let vertices = self.canvas_vertex_buffer.chunk(canvas.vertices().iter().copied()).unwrap();
+            let indices = self.canvas_index_buffer.chunk(canvas.indices().iter().copied()).unwrap();
+
+            commands
+                .set_viewport(0, [self.viewport.clone()])
+                .bind_pipeline_graphics(self.overlay_pipeline.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    self.overlay_pipeline.layout().clone(),
+                    0,
+                    overlay_descriptor_set
+                )
+                .bind_vertex_buffers(0, vertices)
+                .bind_index_buffer(indices)
+                .draw_indexed(canvas.indices().len() as u32, 1, 0, 0, 0)
+                .unwrap();
+        }
+        self.commands = Some(commands);
+
+        Ok(())
+    }
+
+    /// Rasterizes the glyphs the texts of the frame need, to have them in the
+    /// atlas when they are drawn. This has to be done before the render
+    /// starts, as the atlas is uploaded then
+    pub fn prepare_texts(&mut self, texts: &[&Text]) {
+        // Starting over once full makes room for the texts of this frame, and
+        // if they need more than the whole atlas some glyphs are left out
+        // rather than clearing it again
+        if !self.prepare_glyphs(texts) {
+            self.glyph_atlas.clear();
+            self.prepare_glyphs(texts);
+        }
+    }
+
+    /// Returns whether all the glyphs fit in the atlas
+    fn prepare_glyphs(&mut self, texts: &[&Text]) -> bool {
+        let mut fits = true;
+        for text in texts {
+            let size = self.glyph_size(text);
+            for placed in text.layout() {
+                fits &= self.glyph_atlas.prepare(text.font(), placed.glyph, size);
+            }
+        }
+        fits
+    }
+
+    /// Draws a text over the frame, like overlays
+    pub fn add_text(&mut self, text: &Text) -> Result<(), RenderingError> {
+        let mut commands = self.overlay_commands()?;
+
+        let size = self.glyph_size(text);
+        // Glyphs are rasterized at about the size they are drawn at
+        let scale = text.size() / size as f32;
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        for placed in text.layout() {
+            // Glyphs which did not fit in the atlas are left out
+            let glyph = match self.glyph_atlas.get(text.font(), placed.glyph, size) {
+                Some(glyph) => glyph,
+                None => continue
+            };
+            let start = placed.position + vec2(glyph.offset[0] as f32, glyph.offset[1] as f32) * scale;
+            let end = start + vec2(glyph.dimensions[0] as f32, glyph.dimensions[1] as f32) * scale;
+            let uv_start = glyph.origin.map(|t| t as f32 / ATLAS_SIZE as f32);
+            let uv_end = [0, 1].map(|i| (glyph.origin[i] + glyph.dimensions[i]) as f32 / ATLAS_SIZE as f32);
+            let first = vertices.len() as u32;
+            vertices.extend([
+                TextVertex { position: [start.x, start.y], uv: [uv_start[0], uv_start[1]] },
+                TextVertex { position: [end.x, start.y], uv: [uv_end[0], uv_start[1]] },
+                TextVertex { position: [end.x, end.y], uv: [uv_end[0], uv_end[1]] },
+                TextVertex { position: [start.x, end.y], uv: [uv_start[0], uv_end[1]] }
+            ]);
+            indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+
+        if !indices.is_empty() {
+            let color = text.color();
+            let [width, height] = self.viewport.dimensions;
+            let overlay_subbuffer = self.overlay_buffer.next(overlay_vertex::ty::Overlay {
+                transform: scaling(&vec3(height / width, 1.0, 1.0)).into(),
+                color: [color.x, color.y, color.z, text.opacity()]
+            }).unwrap();
+            let text_layout = self.text_pipeline.layout().set_layouts().get(0).unwrap();
+            let text_descriptor_set = PersistentDescriptorSet::new(
+                text_layout.clone(),
+                [
+                    WriteDescriptorSet::buffer(0, overlay_subbuffer),
+                    WriteDescriptorSet::image_view_sampler(1, self.atlas.clone(), self.channel_sampler.clone())
+                ]
+            ).unwrap();
+            let index_count = indices.len() as u32;
+            let vertices = self.text_vertex_buffer.chunk(vertices).unwrap();
+            let indices = self.canvas_index_buffer.chunk(indices).unwrap();
+
+            commands
+                .set_viewport(0, [self.viewport.clone()])
+                .bind_pipeline_graphics(self.text_pipeline.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    self.text_pipeline.layout().clone(),
+                    0,
+                    text_descriptor_set
+                )
+                .bind_vertex_buffers(0, vertices)
+                .bind_index_buffer(indices)
+                .draw_indexed(index_count, 1, 0, 0, 0)
+                .unwrap();
+        }
+        self.commands = Some(commands);
+
+        Ok(())
+    }
+
+    /// Pixels per em the glyphs of a text are rasterized at
+    fn glyph_size(&self, text: &Text) -> u32 {
+        let height = self.viewport.dimensions[1];
+        // The frame spans 2 units vertically
+        ((text.size() * height / 2.0).round() as u32).clamp(1, MAX_GLYPH_SIZE)
+    }
+
+    /// Moves on to the overlay subpass if it is not being drawn yet, and
+    /// returns the commands of the frame
+    fn overlay_commands(&mut self) -> Result<AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, RenderingError> {
+        match self.state {
+            RenderingState::Ambient | RenderingState::Directional | RenderingState::Effects => {
+                self.state = RenderingState::Overlay;
+                let mut commands = self.commands.take().unwrap();
+                commands.next_subpass(SubpassContents::Inline).unwrap();
+                Ok(commands)
+            },
+            RenderingState::Overlay => Ok(self.commands.take().unwrap()),
+            RenderingState::WaitingRedraw => {
+                self.recreate_swapchain();
+                self.state = RenderingState::Stopped;
+                self.commands = None;
+                Err(RenderingError::NonConformingState(String::new()))
+            },
+            _ => {
+                self.state = RenderingState::Stopped;
+                self.commands = None;
+                Err(RenderingError::NonConformingState(String::new()))
+            }
+        }
+    }
+
+    /// Places overlays with `model` in the frame, whatever its aspect ratio,
+    /// and multiplies their colors by `color`
+    fn overlay_descriptor_set(&self, model: &TMat4<f32>, color: [f32; 4]) -> Arc<PersistentDescriptorSet> {
+        let [width, height] = self.viewport.dimensions;
+        let overlay_subbuffer = self.overlay_buffer.next(overlay_vertex::ty::Overlay {
+            transform: (scaling(&vec3(height / width, 1.0, 1.0)) * model).into(),
+            color
+        }).unwrap();
+        let overlay_layout = self.overlay_pipeline.layout().set_layouts().get(0).unwrap();
+        PersistentDescriptorSet::new(
+            overlay_layout.clone(),
+            [WriteDescriptorSet::buffer(0, overlay_subbuffer)]
+        ).unwrap()
+    }
+
     pub fn finish_render(&mut self, previous_frame_end: &mut Option<Box<dyn GpuFuture>>)  -> Result<(), RenderingError> {
+        // Every subpass has to be gone through, even with nothing to overlay
+        let overlaid = matches!(self.state, RenderingState::Overlay);
         match self.state {
-            RenderingState::Directional => {
+            RenderingState::Directional | RenderingState::Effects | RenderingState::Overlay => {
                 self.state = RenderingState::Stopped
             }
             RenderingState::WaitingRedraw => {
@@ -569,22 +1505,48 @@ impl RenderingSystem {
         }
 
         let mut commands = self.commands.take().unwrap();
+        if !overlaid {
+            commands.next_subpass(SubpassContents::Inline).unwrap();
+        }
         commands
             .end_render_pass()
             .unwrap();
+        if let RenderTarget::Offscreen { image, frame } = &self.target {
+            commands
+                .copy_image_to_buffer(image.clone(), frame.clone())
+                .unwrap();
+        }
         let command_buffer = commands.build().unwrap();
 
-        let previous_future = self.future.take().unwrap();
         let mut local_future: Option<Box<dyn GpuFuture>> = Some(Box::new(sync::now(self.device.clone())) as Box<_>);
 
         mem::swap(&mut local_future, previous_frame_end);
+        let mut local_future = local_future.take().unwrap();
+        if let Some(uploads) = self.uploads.take() {
+            local_future = local_future.join(uploads).boxed();
+        }
 
-        let future = local_future.take().unwrap()
-            .join(previous_future)
-            .then_execute(self.queue.clone(), command_buffer)
-            .unwrap()
-            .then_swapchain_present(self.queue.clone(), self.swapchain.clone(), self.image_index.unwrap())
-            .then_signal_fence_and_flush();
+        let swapchain = match &mut self.target {
+            RenderTarget::Window { swapchain, future, .. } => Some((swapchain.clone(), future.take().unwrap())),
+            RenderTarget::Offscreen { .. } => None
+        };
+        let future = match swapchain {
+            Some((swapchain, acquire_future)) => local_future
+                .join(acquire_future)
+                .then_execute(self.queue.clone(), command_buffer)
+                .unwrap()
+                .then_swapchain_present(self.queue.clone(), swapchain, self.image_index.unwrap())
+                .boxed()
+                .then_signal_fence_and_flush(),
+            // Frames rendered offscreen are meant to be read right away, so we
+            // wait for them to be done
+            None => local_future
+                .then_execute(self.queue.clone(), command_buffer)
+                .unwrap()
+                .boxed()
+                .then_signal_fence_and_flush()
+                .and_then(|future| future.wait(None).map(|_| future))
+        };
 
         match future {
             Ok(future) => {
@@ -595,8 +1557,14 @@ impl RenderingSystem {
                 *previous_frame_end = Some(Box::new(sync::now(self.device.clone())) as Box<_>);
             }
             Err(e) => {
-                println!("Failed to flush future: {:?}", e);
                 *previous_frame_end = Some(Box::new(sync::now(self.device.clone())) as Box<_>);
+                self.commands = None;
+                // Frames rendered offscreen are written out, and would be
+                // replaced by the previous one
+                if let RenderTarget::Offscreen { .. } = self.target {
+                    return Err(RenderingError::Flush(format!("{:?}", e)))
+                }
+                eprintln!("Failed to flush future: {:?}", e);
             }
         }
 
@@ -604,15 +1572,33 @@ impl RenderingSystem {
         Ok(())
     }
 
+    /// Returns a copy of the last frame rendered offscreen, as rows of RGBA
+    /// pixels, or `None` when rendering to a window
+    pub fn frame(&self) -> Option<Vec<u8>> {
+        match &self.target {
+            RenderTarget::Offscreen { frame, .. } => Some(frame.read().unwrap().to_vec()),
+            RenderTarget::Window { .. } => None
+        }
+    }
+
+    /// Dimensions of the rendered frames, in pixels
+    pub fn dimensions(&self) -> [u32; 2] {
+        match &self.target {
+            RenderTarget::Window { surface, .. } => surface.window().inner_size().into(),
+            RenderTarget::Offscreen { image, .. } => image.dimensions().width_height()
+        }
+    }
+
     /// Helper function taken from the Vulkano guide
     fn window_size_dependent_setup(
         device: &Arc<Device>,
-        images: &[Arc<SwapchainImage<Window>>],
+        images: &[Arc<dyn ImageAccess>],
         render_pass: Arc<RenderPass>,
         viewport: &mut Viewport,
     ) -> (
         Vec<Arc<Framebuffer>>,
         Arc<ImageView<AttachmentImage>>,
+        Arc<ImageView<AttachmentImage>>,
         Arc<ImageView<AttachmentImage>>
     ) {
         let dimensions = images[0].dimensions().width_height();
@@ -630,6 +1616,13 @@ impl RenderingSystem {
                 Format::R16G16B16A16_SFLOAT
             ).unwrap()
         ).unwrap();
+        let emissive_buffer = ImageView::new_default(
+            AttachmentImage::transient_input_attachment(
+                device.clone(),
+                dimensions,
+                Format::R16G16B16A16_SFLOAT
+            ).unwrap()
+        ).unwrap();
         let depth_buffer = ImageView::new_default(
             AttachmentImage::transient(device.clone(), dimensions, Format::D16_UNORM).unwrap()
         ).unwrap();
@@ -647,6 +1640,7 @@ impl RenderingSystem {
                                 view,
                                 color_buffer.clone(),
                                 normal_buffer.clone(),
+                                emissive_buffer.clone(),
                                 depth_buffer.clone()
                             ],
                             ..Default::default()
@@ -654,33 +1648,29 @@ impl RenderingSystem {
                     ).unwrap()
                 }).collect::<Vec<_>>(),
             color_buffer,
-            normal_buffer
+            normal_buffer,
+            emissive_buffer
         )
     }
 
-    pub fn set_view(&mut self, view: &TMat4<f32>) {
-        self.vp.view = *view;
-        self.vp_buffer = CpuAccessibleBuffer::from_data(
-            self.device.clone(),
-            BufferUsage::all(),
-            false,
-            deferred_vertex::ty::VP {
-                view: self.vp.view.into(),
-                projection: self.vp.projection.into()
-            }
-        ).unwrap();
-
-        let vp_layout = self.deferred_pipeline.layout().set_layouts().get(0).unwrap();
-        self.vp_descriptor_set = PersistentDescriptorSet::new(
-            vp_layout.clone(),
-            [WriteDescriptorSet::buffer(0, self.vp_buffer.clone())]
-        ).unwrap();
-
-        self.state = RenderingState::WaitingRedraw;
+    /// Sees the scene through a camera from the next render on
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.camera = camera.clone();
+        self.update_vp();
     }
 
-    pub fn set_projection(&mut self, projection: &TMat4<f32>) {
-        self.vp.projection = *projection;
+    /// Computes the view and projection out of the camera for the current
+    /// viewport, uploading them if they changed
+    fn update_vp(&mut self) {
+        let [width, height] = self.viewport.dimensions;
+        let vp = VP {
+            view: self.camera.view(),
+            projection: self.camera.projection(width / height)
+        };
+        if vp.view == self.vp.view && vp.projection == self.vp.projection {
+            return
+        }
+        self.vp = vp;
         self.vp_buffer = CpuAccessibleBuffer::from_data(
             self.device.clone(),
             BufferUsage::all(),
@@ -696,54 +1686,33 @@ impl RenderingSystem {
             vp_layout.clone(),
             [WriteDescriptorSet::buffer(0, self.vp_buffer.clone())]
         ).unwrap();
-
-        self.state = RenderingState::WaitingRedraw;
-    }
-
-    pub fn set_orthogonal_projection(&mut self) {
-        let dimensions: [f32; 2] = self.surface.window().inner_size().into();
-        self.set_projection(&ortho(0.0, dimensions[0], 0.0, dimensions[1], 0.01, 100.0))
     }
 
     pub fn recreate_swapchain(&mut self) {
         self.state = RenderingState::WaitingRedraw;
         self.commands = None;
 
-        let dimensions: [u32; 2] = self.surface.window().inner_size().into();
-        let (new_swapchain, new_images) = match self.swapchain.recreate(SwapchainCreateInfo {
-            image_extent: dimensions,
-            ..self.swapchain.create_info()
-        }) {
-            Ok(r) => r,
-            Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return,
-            Err(e) => panic!("Failed to recreate swapchain {:?}", e)
-        };
-        self.swapchain = new_swapchain;
-        let (new_fb, new_cb, new_nb) = Self::window_size_dependent_setup(&self.device, &new_images, self.render_pass.clone(), &mut self.viewport);
-        self.framebuffers = new_fb;
-        self.color_buffer = new_cb;
-        self.normal_buffer = new_nb;
-
-        self.vp.projection = perspective(
-            dimensions[0] as f32 / dimensions[1] as f32,
-            180.0,
-            0.01,
-            100.0
-        );
-        self.vp_buffer = CpuAccessibleBuffer::from_data(
-            self.device.clone(),
-            BufferUsage::all(),
-            false,
-            deferred_vertex::ty::VP {
-                view: self.vp.view.into(),
-                projection: self.vp.projection.into(),
-            },
-        ).unwrap();
-        let deferred_layout = self.deferred_pipeline.layout().set_layouts().get(0).unwrap();
-        self.vp_descriptor_set = PersistentDescriptorSet::new(
-            deferred_layout.clone(),
-            [WriteDescriptorSet::buffer(0, self.vp_buffer.clone())]
-        ).unwrap();
+        let dimensions = self.dimensions();
+        // Offscreen images never change size, so only the projection is reset
+        if let RenderTarget::Window { swapchain, .. } = &mut self.target {
+            let (new_swapchain, new_images) = match swapchain.recreate(SwapchainCreateInfo {
+                image_extent: dimensions,
+                ..swapchain.create_info()
+            }) {
+                Ok(r) => r,
+                Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return,
+                Err(e) => panic!("Failed to recreate swapchain {:?}", e)
+            };
+            *swapchain = new_swapchain;
+            let new_images: Vec<Arc<dyn ImageAccess>> = new_images.into_iter().map(|i| i as Arc<dyn ImageAccess>).collect();
+            let (new_fb, new_cb, new_nb, new_eb) = Self::window_size_dependent_setup(&self.device, &new_images, self.render_pass.clone(), &mut self.viewport);
+            self.framebuffers = new_fb;
+            self.color_buffer = new_cb;
+            self.normal_buffer = new_nb;
+            self.emissive_buffer = new_eb;
+        }
+
+        self.update_vp();
 
         self.state = RenderingState::Stopped;
     }
@@ -760,13 +1729,24 @@ impl RenderingSystem {
         buffer_pool.next(uniform_data).unwrap()
     }
 
+    /// Sets the title of the window, if there is one
+    pub fn set_title(&self, title: &str) {
+        if let RenderTarget::Window { surface, .. } = &self.target {
+            surface.window().set_title(title);
+        }
+    }
+
     pub fn set_fullscreen(&mut self) {
-        if self.surface.window().fullscreen().is_some() {
-            self.surface.window().set_fullscreen(None);
+        let surface = match &self.target {
+            RenderTarget::Window { surface, .. } => surface,
+            RenderTarget::Offscreen { .. } => return
+        };
+        if surface.window().fullscreen().is_some() {
+            surface.window().set_fullscreen(None);
         } else {
-            self.surface.window().set_fullscreen(
+            surface.window().set_fullscreen(
                 Some(
-                    Borderless(self.surface.window().current_monitor())
+                    Borderless(surface.window().current_monitor())
                 )
             );
         }
@@ -793,6 +1773,43 @@ mod deferred_fragment {
     }
 }
 
+mod instanced_vertex {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        path: "src/graphics/shaders/instanced.vert"
+    }
+}
+
+mod overlay_vertex {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        path: "src/graphics/shaders/overlay.vert",
+        types_meta: {
+            use bytemuck::{Pod, Zeroable};
+            #[derive(Clone, Copy, Zeroable, Pod)]
+        }
+    }
+}
+mod overlay_fragment {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        path: "src/graphics/shaders/overlay.frag"
+    }
+}
+
+mod text_vertex {
+    vulkano_shaders::shader!{
+        ty: "vertex",
+        path: "src/graphics/shaders/text.vert"
+    }
+}
+mod text_fragment {
+    vulkano_shaders::shader!{
+        ty: "fragment",
+        path: "src/graphics/shaders/text.frag"
+    }
+}
+
 mod ambient_vertex {
     vulkano_shaders::shader!{
         ty: "vertex",
@@ -0,0 +1,240 @@
+//! Shaders compiled from GLSL at runtime.
+//!
+//! Scenes can bring their own fragment shaders, either as materials replacing
+//! the one models are drawn with, or as effects drawn over the lit frame. Both
+//! get what they need declared for them, audio uniforms included, so a
+//! material can be as short as:
+//!
+//! ```glsl
+//! void main() {
+//!     color = vec4(in_color * (0.5 + band(int(gl_FragCoord.x) % BAND_COUNT)), 1.0);
+//!     normal = in_normal;
+//! }
+//! ```
+//!
+//! Scenes can also be a single [Shadertoy](https://www.shadertoy.com) shader,
+//! defining `mainImage` and drawn over the whole frame.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use bytemuck::{Pod, Zeroable};
+use shaderc::ShaderKind;
+use vulkano::device::Device;
+use vulkano::shader::ShaderModule;
+
+/// Number of bands in the audio uniforms
+pub const BAND_COUNT: usize = 64;
+
+/// What custom shaders know about the sound, laid out as the `Audio` block of
+/// the prelude
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct AudioUniforms {
+    /// Levels of the bands in [0, 1], four per vector to match std140
+    pub bands: [[f32; 4]; BAND_COUNT / 4],
+    /// Seconds since the track started
+    pub time: f32,
+    /// 1 on the frame a beat happens, 0 otherwise
+    pub beat: f32,
+    pub beat_phase: f32,
+    pub onset_strength: f32,
+    /// Momentary loudness, in [0, 1]
+    pub loudness: f32,
+    pub bpm: f32,
+    /// How much of the track was played, in [0, 1]
+    pub progress: f32,
+    pub section: f32,
+    /// Dimensions of the frame, in pixels
+    pub resolution: [f32; 2],
+    _padding: [f32; 2]
+}
+impl AudioUniforms {
+    pub fn set_bands(&mut self, levels: &[f32]) {
+        for (i, level) in levels.iter().take(BAND_COUNT).enumerate() {
+            self.bands[i / 4][i % 4] = *level;
+        }
+    }
+}
+
+/// Dimensions of the texture Shadertoy shaders get as `iChannel0`
+pub const CHANNEL_DIMENSIONS: [u32; 2] = [512, 2];
+
+/// Uniforms of Shadertoy shaders, laid out as their `Shadertoy` block
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Pod, Zeroable)]
+pub struct ShadertoyUniforms {
+    /// Dimensions of the frame, in pixels
+    pub resolution: [f32; 3],
+    /// Seconds since the scene started
+    pub time: f32,
+    pub time_delta: f32,
+    pub frame: i32,
+    pub frame_rate: f32,
+    pub sample_rate: f32,
+    /// Always zero, as there is nothing to click on
+    pub mouse: [f32; 4],
+    /// Only the seconds since midnight, UTC, are filled in
+    pub date: [f32; 4],
+    /// Dimensions of the channels, the fourth component being padding
+    pub channel_resolution: [[f32; 4]; 4]
+}
+
+/// A scene made of a Shadertoy shader
+pub struct Shadertoy {
+    /// Path of the shader, which defines `mainImage`
+    pub path: PathBuf,
+    pub uniforms: ShadertoyUniforms,
+    /// Texels of `iChannel0`, one byte each: like on Shadertoy, the spectrum
+    /// makes the first row and the waveform the second
+    pub channel: Vec<u8>
+}
+
+/// Declares the audio uniforms, bound to the given set
+fn audio_block(set: u32) -> String {
+    format!(
+        "layout(set = {}, binding = 0) uniform Audio {{
+    vec4 bands[{}];
+    float time;
+    float beat;
+    float beat_phase;
+    float onset_strength;
+    float loudness;
+    float bpm;
+    float progress;
+    float section;
+    vec2 resolution;
+}} audio;
+
+#define BAND_COUNT {}
+float band(int i) {{
+    i = clamp(i, 0, BAND_COUNT - 1);
+    return audio.bands[i / 4][i % 4];
+}}
+",
+        set, BAND_COUNT / 4, BAND_COUNT
+    )
+}
+
+/// Puts a prelude before a source, keeping the line numbers of errors those
+/// of the source. Sources may start with their own version directive
+fn with_prelude(prelude: &str, source: &str) -> String {
+    let (first_line, source) = match source.strip_prefix("#version") {
+        Some(rest) => (2, rest.split_once('\n').map(|(_, s)| s).unwrap_or_default()),
+        None => (1, source)
+    };
+    format!("#version 450\n{}#line {}\n{}", prelude, first_line, source)
+}
+
+/// Wraps the source of a material, which gets the color, normal and texture
+/// coordinates of the fragment and writes them to the geometry buffers, the
+/// texture of the model being `albedo`. The emissive color and opacity of the
+/// model are taken care of before its `main` is called, but it can still
+/// write `emissive`
+pub fn material_source(source: &str) -> String {
+    let prelude = format!(
+        "layout(location = 0) in vec3 in_color;
+layout(location = 1) in vec3 in_normal;
+layout(location = 2) flat in vec3 in_emissive;
+layout(location = 3) flat in float in_opacity;
+layout(location = 4) in vec2 in_uv;
+
+layout(location = 0) out vec4 color;
+layout(location = 1) out vec3 normal;
+layout(location = 2) out vec4 emissive;
+
+layout(set = 1, binding = 1) uniform sampler2D albedo;
+{}
+const float DITHER[16] = float[](
+    0.0, 8.0, 2.0, 10.0,
+    12.0, 4.0, 14.0, 6.0,
+    3.0, 11.0, 1.0, 9.0,
+    15.0, 7.0, 13.0, 5.0
+);
+
+void material_main();
+void main() {{
+    ivec2 pixel = ivec2(mod(gl_FragCoord.xy, 4.0));
+    if (in_opacity <= (DITHER[pixel.y * 4 + pixel.x] + 0.5) / 16.0) {{
+        discard;
+    }}
+    emissive = vec4(in_emissive, 1.0);
+    material_main();
+}}
+#define main material_main
+",
+        audio_block(2)
+    );
+    with_prelude(&prelude, source)
+}
+
+/// Wraps the source of an effect, which can read the unlit colors and the
+/// normals of the scene and whose output is blended over the lit frame
+pub fn effect_source(source: &str) -> String {
+    let prelude = format!(
+        "layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput u_color;
+layout(input_attachment_index = 1, set = 0, binding = 1) uniform subpassInput u_normals;
+
+layout(location = 0) out vec4 f_color;
+
+{}",
+        audio_block(1)
+    );
+    with_prelude(&prelude, source)
+}
+
+/// Wraps the source of a Shadertoy shader, whose colors are converted if the
+/// frame is in sRGB since they are meant to be displayed as they are
+pub fn shadertoy_source(source: &str, srgb: bool) -> String {
+    format!(
+        "#version 450
+layout(set = 0, binding = 0) uniform Shadertoy {{
+    vec3 iResolution;
+    float iTime;
+    float iTimeDelta;
+    int iFrame;
+    float iFrameRate;
+    float iSampleRate;
+    vec4 iMouse;
+    vec4 iDate;
+    vec3 iChannelResolution[4];
+}};
+layout(set = 0, binding = 1) uniform sampler2D iChannel0;
+
+layout(location = 0) out vec4 f_color;
+
+void mainImage(out vec4 fragColor, in vec2 fragCoord);
+#line 1
+{}
+void main() {{
+    vec4 color = vec4(0.0, 0.0, 0.0, 1.0);
+    // Shadertoy puts the origin at the bottom left corner
+    mainImage(color, vec2(gl_FragCoord.x, iResolution.y - gl_FragCoord.y));
+    color.rgb = clamp(color.rgb, 0.0, 1.0);
+    f_color = vec4({}, 1.0);
+}}
+",
+        source,
+        if srgb { "pow(color.rgb, vec3(2.2))" } else { "color.rgb" }
+    )
+}
+
+/// Compiles GLSL into a shader module, errors being reported against `name`
+pub fn compile(device: &Arc<Device>, source: &str, kind: ShaderKind, name: &str) -> Result<Arc<ShaderModule>, String> {
+    let spirv = shaderc::Compiler::new()
+        .ok_or("Could not initialise the shader compiler")?
+        .compile_into_spirv(source, kind, name, "main", None)
+        .map_err(|e| e.to_string())?;
+    // Safe as long as shaderc produces valid SPIR-V, which it does
+    unsafe { ShaderModule::from_words(device.clone(), spirv.as_binary()).map_err(|e| e.to_string()) }
+}
+
+/// Compiles a shader file, whose stage is given by its extension
+pub fn compile_file(device: &Arc<Device>, path: &Path) -> Result<Arc<ShaderModule>, String> {
+    let kind = match path.extension().and_then(|e| e.to_str()) {
+        Some("vert") => ShaderKind::Vertex,
+        Some("frag") => ShaderKind::Fragment,
+        _ => return Err(format!("Unknown shader stage for {}", path.display()))
+    };
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    compile(device, &source, kind, &path.display().to_string())
+}
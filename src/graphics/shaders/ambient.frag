@@ -1,6 +1,7 @@
 #version 450
 
 layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput u_color;
+layout(input_attachment_index = 2, set = 0, binding = 2) uniform subpassInput u_emissive;
 
 layout(set = 0, binding = 1) uniform AmbientLight {
     vec3 color;
@@ -12,5 +13,6 @@ layout(location = 0) out vec4 f_color;
 void main() {
     vec3 ambient_color = ambient.intensity * ambient.color;
     vec3 combined_color = ambient_color * subpassLoad(u_color).rgb;
-    f_color = vec4(combined_color, 1.0);
+    // Emissive surfaces shine whatever the lights, so they are only added once
+    f_color = vec4(combined_color + subpassLoad(u_emissive).rgb, 1.0);
 }
@@ -1,11 +1,33 @@
 #version 450
 layout(location = 0) in vec3 in_color;
 layout(location = 1) in vec3 in_normal;
+layout(location = 2) flat in vec3 in_emissive;
+layout(location = 3) flat in float in_opacity;
+layout(location = 4) in vec2 in_uv;
 
 layout(location = 0) out vec4 color;
 layout(location = 1) out vec3 normal;
+layout(location = 2) out vec4 emissive;
+
+// White for models without a texture
+layout(set = 1, binding = 1) uniform sampler2D albedo;
+
+// The geometry buffers only hold one surface per pixel, so transparency is
+// dithered rather than blended
+const float DITHER[16] = float[](
+    0.0, 8.0, 2.0, 10.0,
+    12.0, 4.0, 14.0, 6.0,
+    3.0, 11.0, 1.0, 9.0,
+    15.0, 7.0, 13.0, 5.0
+);
 
 void main() {
-    color = vec4(in_color, 1.0);
+    vec4 texel = texture(albedo, in_uv);
+    ivec2 pixel = ivec2(mod(gl_FragCoord.xy, 4.0));
+    if (in_opacity * texel.a <= (DITHER[pixel.y * 4 + pixel.x] + 0.5) / 16.0) {
+        discard;
+    }
+    color = vec4(in_color * texel.rgb, 1.0);
     normal = in_normal;
+    emissive = vec4(in_emissive, 1.0);
 }
@@ -2,9 +2,13 @@
 layout(location = 0) in vec3 position;
 layout(location = 1) in vec3 normal;
 layout(location = 2) in vec3 color;
+layout(location = 3) in vec2 uv;
 
 layout(location = 0) out vec3 out_color;
 layout(location = 1) out vec3 out_normal;
+layout(location = 2) flat out vec3 out_emissive;
+layout(location = 3) flat out float out_opacity;
+layout(location = 4) out vec2 out_uv;
 
 layout(set = 0, binding = 0) uniform VP {
     mat4 view;
@@ -13,12 +17,18 @@ layout(set = 0, binding = 0) uniform VP {
 layout(set = 1, binding = 0) uniform Model {
     mat4 model;
     mat4 normals;
+    vec3 color;
+    float opacity;
+    vec3 emissive;
 } model;
 
 void main() {
     mat4 MV = vp.view * model.model;
     gl_Position = vp.projection * MV * vec4(position, 1.0);
 
-    out_color = color;
+    out_color = color * model.color;
     out_normal = mat3(model.normals) * normal;
+    out_emissive = model.emissive;
+    out_opacity = model.opacity;
+    out_uv = uv;
 }
\ No newline at end of file
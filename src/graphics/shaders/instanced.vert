@@ -0,0 +1,32 @@
+#version 450
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec3 color;
+layout(location = 3) in vec2 uv;
+layout(location = 4) in mat4 instance_model;
+layout(location = 8) in mat4 instance_normals;
+layout(location = 12) in vec3 instance_color;
+layout(location = 13) in vec3 instance_emissive;
+layout(location = 14) in float instance_opacity;
+
+layout(location = 0) out vec3 out_color;
+layout(location = 1) out vec3 out_normal;
+layout(location = 2) flat out vec3 out_emissive;
+layout(location = 3) flat out float out_opacity;
+layout(location = 4) out vec2 out_uv;
+
+layout(set = 0, binding = 0) uniform VP {
+    mat4 view;
+    mat4 projection;
+} vp;
+
+void main() {
+    mat4 MV = vp.view * instance_model;
+    gl_Position = vp.projection * MV * vec4(position, 1.0);
+
+    out_color = color * instance_color;
+    out_normal = mat3(instance_normals) * normal;
+    out_emissive = instance_emissive;
+    out_opacity = instance_opacity;
+    out_uv = uv;
+}
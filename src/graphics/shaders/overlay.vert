@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec4 color;
+
+layout(location = 0) out vec4 out_color;
+
+layout(set = 0, binding = 0) uniform Overlay {
+    mat4 transform;
+    vec4 color;
+} overlay;
+
+void main() {
+    gl_Position = overlay.transform * vec4(position, 0.0, 1.0);
+    out_color = color * overlay.color;
+}
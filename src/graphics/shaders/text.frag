@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform Overlay {
+    mat4 transform;
+    vec4 color;
+} overlay;
+// Coverage of the glyphs
+layout(set = 0, binding = 1) uniform sampler2D atlas;
+
+void main() {
+    f_color = vec4(overlay.color.rgb, overlay.color.a * texture(atlas, in_uv).r);
+}
@@ -0,0 +1,201 @@
+use glm::{TVec2, TVec3, vec2};
+use serde::Deserialize;
+use ttf_parser::{Face, GlyphId};
+use crate::resource_pool::font_loader::Font;
+
+/// Where lines go relative to the position of their text
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum Align {
+    Left,
+    Center,
+    Right
+}
+
+/// A glyph placed by the layout, on its baseline
+pub struct PlacedGlyph {
+    pub glyph: GlyphId,
+    pub position: TVec2<f32>
+}
+
+/// Text drawn over the frame, in the coordinates of overlays
+///
+/// The position is the top of the first line, its left end, middle or right
+/// end depending on the alignment. The size is the height of the em square,
+/// about that of the tallest letters and their descenders. Lines are broken
+/// at new lines, and between words once wider than the maximum width if
+/// there is one.
+#[derive(Clone)]
+pub struct Text {
+    font: Font,
+    content: String,
+    position: TVec2<f32>,
+    size: f32,
+    color: TVec3<f32>,
+    opacity: f32,
+    align: Align,
+    max_width: Option<f32>
+}
+
+impl Text {
+    pub fn new(font: Font, content: &str) -> Self {
+        Text {
+            font,
+            content: content.into(),
+            position: vec2(0.0, 0.0),
+            size: 0.1,
+            color: TVec3::new(1.0, 1.0, 1.0),
+            opacity: 1.0,
+            align: Align::Left,
+            max_width: None
+        }
+    }
+
+    pub fn font(&self) -> &Font {
+        &self.font
+    }
+
+    pub fn set_font(&mut self, font: Font) -> &mut Self {
+        self.font = font;
+        self
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    pub fn set_content(&mut self, content: &str) -> &mut Self {
+        self.content = content.into();
+        self
+    }
+
+    pub fn get_position(&self) -> TVec2<f32> {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: TVec2<f32>) -> &mut Self {
+        self.position = position;
+        self
+    }
+
+    pub fn translate(&mut self, v: TVec2<f32>) -> &mut Self {
+        self.position += v;
+        self
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    pub fn set_size(&mut self, size: f32) -> &mut Self {
+        self.size = size.max(0.0);
+        self
+    }
+
+    pub fn color(&self) -> TVec3<f32> {
+        self.color
+    }
+
+    pub fn set_color(&mut self, color: TVec3<f32>) -> &mut Self {
+        self.color = color;
+        self
+    }
+
+    pub fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    /// Sets how opaque the text is, in [0, 1]
+    pub fn set_opacity(&mut self, opacity: f32) -> &mut Self {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn set_align(&mut self, align: Align) -> &mut Self {
+        self.align = align;
+        self
+    }
+
+    /// Sets the width lines are wrapped at, if any
+    pub fn set_max_width(&mut self, max_width: Option<f32>) -> &mut Self {
+        self.max_width = max_width;
+        self
+    }
+
+    /// Places the glyphs of the text, leaving out those which have nothing to
+    /// draw such as spaces
+    pub fn layout(&self) -> Vec<PlacedGlyph> {
+        let face = self.font.face();
+        let scale = self.size / face.units_per_em() as f32;
+        let line_height = (face.ascender() - face.descender() + face.line_gap()) as f32 * scale;
+        let mut baseline = self.position.y + face.ascender() as f32 * scale;
+
+        let mut placed = vec![];
+        for line in self.lines(&face, scale) {
+            let glyphs = shape(&face, &line, scale);
+            let width = glyphs.last().map(|(_, x, advance)| x + advance).unwrap_or(0.0);
+            let start = self.position.x - match self.align {
+                Align::Left => 0.0,
+                Align::Center => width / 2.0,
+                Align::Right => width
+            };
+            placed.extend(glyphs.into_iter()
+                .filter(|(c, _, _)| !c.is_whitespace())
+                .filter_map(|(c, x, _)| face.glyph_index(c).map(|glyph| PlacedGlyph {
+                    glyph,
+                    position: vec2(start + x, baseline)
+                })));
+            baseline += line_height;
+        }
+        placed
+    }
+
+    /// Splits the text into the lines it is drawn on
+    fn lines(&self, face: &Face, scale: f32) -> Vec<String> {
+        let mut lines = vec![];
+        for paragraph in self.content.lines() {
+            let max_width = match self.max_width {
+                Some(max_width) => max_width,
+                None => {
+                    lines.push(paragraph.trim_end().to_string());
+                    continue
+                }
+            };
+            // Words go on the current line as long as they fit, words wider
+            // than the maximum width getting a line of their own
+            let mut line = String::new();
+            for word in paragraph.split_whitespace() {
+                let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+                if !line.is_empty() && width(face, &candidate, scale) > max_width {
+                    lines.push(std::mem::replace(&mut line, word.to_string()));
+                } else {
+                    line = candidate;
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+/// Places the characters of a line from its start, returning each of them
+/// along with where it starts and how far it advances
+fn shape(face: &Face, line: &str, scale: f32) -> Vec<(char, f32, f32)> {
+    let kerning = face.tables().kern.and_then(|kern| kern.subtables.into_iter().find(|s| s.horizontal && !s.variable));
+    let mut x = 0.0;
+    let mut previous: Option<GlyphId> = None;
+    line.chars().map(|c| {
+        let glyph = face.glyph_index(c);
+        if let (Some(left), Some(right), Some(kerning)) = (previous, glyph, &kerning) {
+            x += kerning.glyphs_kerning(left, right).unwrap_or(0) as f32 * scale;
+        }
+        previous = glyph;
+        let advance = glyph.and_then(|g| face.glyph_hor_advance(g)).unwrap_or(0) as f32 * scale;
+        let placed = (c, x, advance);
+        x += advance;
+        placed
+    }).collect()
+}
+
+fn width(face: &Face, line: &str, scale: f32) -> f32 {
+    shape(face, line, scale).last().map(|(_, x, advance)| x + advance).unwrap_or(0.0)
+}
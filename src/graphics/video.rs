@@ -0,0 +1,87 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use image::codecs::png::PngEncoder;
+use image::{ExtendedColorType, ImageEncoder};
+
+/// Where the frames of an offline render are written
+pub enum VideoOutput {
+    /// One numbered PNG file per frame, in the given directory
+    PngSequence(PathBuf),
+    /// Raw RGBA frames, one after the other, to be piped into a muxer
+    Raw(Box<dyn Write>),
+    /// A video file encoded by ffmpeg, which must be installed, with the
+    /// rendered sound as its audio track
+    Ffmpeg(PathBuf)
+}
+
+enum Writer {
+    PngSequence { directory: PathBuf, index: usize },
+    Raw(Box<dyn Write>),
+    Ffmpeg(Child)
+}
+
+/// Writes rendered frames to a `VideoOutput`
+pub struct FrameWriter {
+    writer: Writer,
+    dimensions: [u32; 2]
+}
+impl FrameWriter {
+    pub fn new(output: VideoOutput, dimensions: [u32; 2], frame_rate: u32, sound: &Path) -> Result<Self, String> {
+        let writer = match output {
+            VideoOutput::PngSequence(directory) => {
+                fs::create_dir_all(&directory).map_err(|e| e.to_string())?;
+                Writer::PngSequence { directory, index: 0 }
+            },
+            VideoOutput::Raw(output) => Writer::Raw(output),
+            VideoOutput::Ffmpeg(path) => {
+                let child = Command::new("ffmpeg")
+                    .args(["-y", "-loglevel", "error"])
+                    .args(["-f", "rawvideo", "-pixel_format", "rgba"])
+                    .arg("-video_size").arg(format!("{}x{}", dimensions[0], dimensions[1]))
+                    .arg("-framerate").arg(frame_rate.to_string())
+                    .args(["-i", "-", "-i"]).arg(sound)
+                    .args(["-map", "0:v", "-map", "1:a"])
+                    .args(["-c:v", "libx264", "-pix_fmt", "yuv420p", "-c:a", "aac", "-shortest"])
+                    .arg(path)
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .map_err(|e| format!("Could not start ffmpeg: {}", e))?;
+                Writer::Ffmpeg(child)
+            }
+        };
+
+        Ok(FrameWriter { writer, dimensions })
+    }
+
+    /// Writes a frame made of rows of RGBA pixels
+    pub fn write(&mut self, frame: &[u8]) -> Result<(), String> {
+        match &mut self.writer {
+            Writer::PngSequence { directory, index } => {
+                let file = File::create(directory.join(format!("{:06}.png", index))).map_err(|e| e.to_string())?;
+                PngEncoder::new(BufWriter::new(file))
+                    .write_image(frame, self.dimensions[0], self.dimensions[1], ExtendedColorType::Rgba8)
+                    .map_err(|e| e.to_string())?;
+                *index += 1;
+                Ok(())
+            },
+            Writer::Raw(output) => output.write_all(frame).map_err(|e| e.to_string()),
+            Writer::Ffmpeg(child) => child.stdin.as_mut().unwrap().write_all(frame).map_err(|e| e.to_string())
+        }
+    }
+
+    /// Flushes the remaining frames, and waits for the video to be encoded
+    pub fn finish(self) -> Result<(), String> {
+        match self.writer {
+            Writer::PngSequence { .. } => Ok(()),
+            Writer::Raw(mut output) => output.flush().map_err(|e| e.to_string()),
+            Writer::Ffmpeg(mut child) => {
+                // Closing its input tells ffmpeg there are no more frames
+                drop(child.stdin.take());
+                let status = child.wait().map_err(|e| e.to_string())?;
+                if status.success() { Ok(()) } else { Err(format!("ffmpeg failed: {}", status)) }
+            }
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// How long files must stay untouched before a change is reported, editors
+/// often writing files in several steps
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(200);
+
+/// Watches files for changes.
+///
+/// The directories containing them are watched rather than the files
+/// themselves, so that files replaced by editors on save keep being watched.
+pub struct FileWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+    files: HashSet<PathBuf>,
+    directories: HashSet<PathBuf>
+}
+impl FileWatcher {
+    pub fn new() -> Result<Self, String> {
+        let (sender, events) = channel();
+        let watcher = notify::watcher(sender, DEBOUNCE_DELAY).map_err(|e| e.to_string())?;
+        Ok(FileWatcher {
+            watcher,
+            events,
+            files: HashSet::new(),
+            directories: HashSet::new()
+        })
+    }
+
+    pub fn watch(&mut self, file: &Path) -> Result<(), String> {
+        let file = file.canonicalize().map_err(|e| format!("{}: {}", file.display(), e))?;
+        let directory = file.parent().unwrap().to_path_buf();
+        if !self.directories.contains(&directory) {
+            self.watcher.watch(&directory, RecursiveMode::NonRecursive).map_err(|e| e.to_string())?;
+            self.directories.insert(directory);
+        }
+        self.files.insert(file);
+        Ok(())
+    }
+
+    /// Returns the watched files that changed since the last call
+    pub fn changes(&self) -> Vec<PathBuf> {
+        let mut changes = vec![];
+        for event in self.events.try_iter() {
+            let path = match event {
+                DebouncedEvent::Create(path) | DebouncedEvent::Write(path) | DebouncedEvent::Rename(_, path) => path,
+                _ => continue
+            };
+            if self.files.contains(&path) && !changes.contains(&path) {
+                changes.push(path);
+            }
+        }
+        changes
+    }
+}
@@ -1,30 +1,68 @@
 extern crate nalgebra_glm as glm;
 
+use std::cell::RefCell;
 use std::f32::consts::PI;
-use std::sync::Arc;
-use std::time::Instant;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use glm::vec3;
 use vulkano::sync::GpuFuture;
-use winit::event::{Event, WindowEvent};
+use winit::event::{ElementState, Event, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
-use crate::audio::{AudioPlayer, GeneralAttributes, RealtimeAttributes};
+use crate::audio::{AudioPlayer, Analyzer, GeneralAttributes, RealtimeAttributes, take_realtime_attributes};
+use crate::audio::analysis::TrackAnalysis;
+use crate::audio::playlist::Repeat;
+use crate::audio::signal_processing::{BandMapper, BandScale};
+pub use crate::audio::capture::CaptureSource;
+use crate::graphics::camera::Camera;
+use crate::graphics::canvas::Canvas;
+use crate::graphics::text::{Align, Text};
 use crate::graphics::{AmbientLight, DirectionalLight, RenderingSystem};
+use crate::graphics::rendering_system::{RenderingError, SHADER_DIRECTORY};
+use crate::graphics::shader::{AudioUniforms, BAND_COUNT, Shadertoy};
+use crate::graphics::video::FrameWriter;
+pub use crate::graphics::video::VideoOutput;
+use crate::hot_reload::FileWatcher;
+use crate::resource_pool::font_loader::Font;
+use crate::resource_pool::instanced_model::InstancedModel;
 use crate::resource_pool::model_loader::Model;
 use crate::resource_pool::ResourcePool;
-use crate::resource_pool::sound_loader::Sound;
+use crate::resource_pool::svg_loader::Svg;
+pub use crate::resource_pool::sound_loader::Sound;
 
 mod graphics;
 mod resource_pool;
 mod audio;
+mod scripting;
+mod hot_reload;
+
+/// How far the arrow keys move playback
+const SEEK_STEP: Duration = Duration::from_secs(5);
+/// Loudness the G key brings the analysed sound to, in LUFS
+const AUTO_GAIN_TARGET: f32 = -14.0;
+/// Title of the window, followed by the last reload error if any
+const TITLE: &str = "Synesthesia";
 
 pub struct Synesthesia {
     scene: Option<Scene>,
+    /// Script the scene was loaded from, if any
+    scene_path: Option<PathBuf>,
+    /// Reloads the scene and shaders when they change, while running
+    watcher: Option<FileWatcher>,
+    /// Files that could not be reloaded, and why
+    reload_errors: Vec<(PathBuf, String)>,
+    /// Drawn over the frame while there are reload errors
+    reload_error_text: Option<Text>,
 
-    event_loop: EventLoop<()>,
+    /// Absent when rendering offline
+    event_loop: Option<EventLoop<()>>,
     rendering_system: RenderingSystem,
     previous_frame_end: Option<Box<dyn GpuFuture>>,
 
     audio_player: AudioPlayer,
+    /// Bands given to materials and effects
+    bands: BandMapper,
 
     model_pool: ResourcePool<Model>,
     sound_pool: ResourcePool<Sound>,
@@ -32,44 +70,138 @@ pub struct Synesthesia {
 impl Synesthesia {
     pub fn init() -> Self {
         let event_loop = EventLoop::new();
-        let (mut rendering_system, previous_frame_end) = RenderingSystem::new(&event_loop);
-        rendering_system.set_orthogonal_projection();
+        let (rendering_system, previous_frame_end) = RenderingSystem::new(&event_loop);
+        rendering_system.set_title(TITLE);
+
+        Self {
+            scene: None,
+            scene_path: None,
+            watcher: None,
+            reload_errors: vec![],
+            reload_error_text: None,
+
+            event_loop: Some(event_loop),
+            rendering_system,
+            previous_frame_end,
+
+            audio_player: Default::default(),
+            bands: Self::uniform_bands(),
+
+            model_pool: Default::default(),
+            sound_pool: Default::default(),
+        }
+    }
+
+    /// Initialises everything needed to render offline frames of the given
+    /// dimensions, without opening any window
+    pub fn init_headless(dimensions: [u32; 2]) -> Self {
+        let (rendering_system, previous_frame_end) = RenderingSystem::new_headless(dimensions);
 
         Self {
             scene: None,
+            scene_path: None,
+            watcher: None,
+            reload_errors: vec![],
+            reload_error_text: None,
 
-            event_loop,
+            event_loop: None,
             rendering_system,
             previous_frame_end,
 
             audio_player: Default::default(),
+            bands: Self::uniform_bands(),
 
             model_pool: Default::default(),
             sound_pool: Default::default(),
         }
     }
 
-    pub fn load_scene(&mut self, script_path: &str) {
-        self.sound_pool.load("sound", script_path);
-        self.audio_player.play(self.sound_pool.get("sound").unwrap());
+    fn uniform_bands() -> BandMapper {
+        BandMapper::new(BandScale::Logarithmic(BAND_COUNT))
+            .frequency_range(30.0, 16000.0)
+            .smoothing(0.6, 0.6)
+    }
 
-        self.model_pool.load("cube", "assets/models/cube.obj");
+    /// Loads a scene from a script, replacing the demo scene
+    pub fn load_scene(&mut self, script_path: &str) -> Result<(), String> {
+        let scene = scripting::load(script_path)?;
+        self.load_resources(&scene)?;
+        self.scene = Some(scene);
+        self.scene_path = Some(PathBuf::from(script_path));
+        Ok(())
+    }
+
+    /// Compiles the materials and effects of a scene
+    fn load_resources(&mut self, scene: &Scene) -> Result<(), String> {
+        for material in &scene.materials {
+            self.rendering_system.load_material(material)?;
+        }
+        for effect in &scene.effects {
+            self.rendering_system.load_effect(effect)?;
+        }
+        if let Some(shadertoy) = &scene.shadertoy {
+            self.rendering_system.load_shadertoy(&shadertoy.path)?;
+        }
+        Ok(())
+    }
+
+    /// Gives the sound of the frame about to be drawn to materials and effects
+    fn update_audio_uniforms(&mut self, rta: &RealtimeAttributes, ga: &GeneralAttributes) {
+        let time = rta.timestamp.as_secs_f32();
+        let mut audio = AudioUniforms::default();
+        audio.time = time;
+        audio.beat = if rta.beat { 1.0 } else { 0.0 };
+        audio.beat_phase = rta.beat_phase;
+        audio.onset_strength = rta.onset_strength;
+        audio.loudness = (1.0 + rta.momentary_loudness / 60.0).clamp(0.0, 1.0);
+        audio.bpm = rta.bpm;
+        audio.progress = if ga.duration.is_zero() { 0.0 } else { time / ga.duration.as_secs_f32() };
+        audio.section = ga.analysis.as_ref().map(|a| a.section_at(rta.timestamp)).unwrap_or_default() as f32;
+        self.bands.map(&rta.spectrum);
+        audio.set_bands(&self.bands.levels(-60.0));
+        self.rendering_system.set_audio(audio);
+    }
+
+    /// Plays a sound file, showing the demo scene unless a scene was loaded
+    pub fn play(&mut self, file_path: &str) {
+        self.sound_pool.load(file_path, file_path).unwrap();
+        self.audio_player.play(self.sound_pool.get(file_path).unwrap());
+        if self.scene.is_none() {
+            self.scene = Some(self.demo_scene());
+        }
+    }
+
+    /// Reacts to samples captured from the given source instead of a sound
+    /// file, showing the demo scene unless a scene was loaded
+    pub fn capture(&mut self, source: CaptureSource) {
+        self.audio_player.capture(source);
+        if self.scene.is_none() {
+            self.scene = Some(self.demo_scene());
+        }
+    }
+
+    fn demo_scene(&mut self) -> Scene {
+        // The demo ships with the cube, so failing to load it is a bug
+        self.model_pool.load("cube", "assets/models/cube.obj")
+            .unwrap_or_else(|e| panic!("could not load the demo cube: {}", e));
         let cubes_count = 64;
-        let cubes: Vec<Model> = (0..cubes_count + 2).map(|_| {
+        let cubes: Vec<Model> = (0..2).map(|_| {
             self.model_pool.get_copy("cube").unwrap()
         }).collect();
+        let bars = InstancedModel::new(self.model_pool.get("cube").unwrap().mesh().clone(), cubes_count);
+        let bands = RefCell::new(
+            BandMapper::new(BandScale::Logarithmic(cubes_count))
+                .frequency_range(30.0, 16000.0)
+                .smoothing(0.6, 0.6)
+        );
 
-        let scene = Scene {
+        Scene {
             main: Arc::new(move |scene, dt, rta, ga| {
-                let hamming_window = |n: usize, a: f32| a - (1.0 - a) * ((2.0 * PI * n as f32) / rta.fft.len() as f32).cos();
-                let normalization_factor = 1.0 / (rta.fft.len() as f32).sqrt();
-                let powers: Vec<f32> = rta.fft.iter().enumerate().map(|(i, v)| {
-                    v.norm() * hamming_window(i, 0.53836) * normalization_factor
-                }).collect();
-                let intensity = powers.iter().fold(0.0, |acc, p| acc + (p + 1.0).log10()) / powers.len() as f32;
-                let cubes_count = scene.models.len();
+                // Map decibels to [0, 1], anything under -60dB being silence
+                let powers: Vec<f32> = rta.spectrum.decibels.iter().map(|db| (1.0 + db / 60.0).max(0.0)).collect();
+                let intensity = powers.iter().sum::<f32>() / powers.len() as f32;
+                let cubes_count = scene.models.len() + scene.instanced[0].instances().len();
                 let cube_width = 1.0 / cubes_count as f32;
-                let smoothing_delta = 0.6;
 
                 let mut cubes = scene.models.iter_mut();
 
@@ -87,33 +219,21 @@ impl Synesthesia {
                 background_cube.set_color(vec3(1.0, 0.2, 0.0));
 
                 let progress_bar = cubes.next().unwrap();
-                let progress = rta.timestamp.as_secs_f32() / ga.duration.as_secs_f32();
+                let progress = if ga.duration.is_zero() { 0.0 } else { rta.timestamp.as_secs_f32() / ga.duration.as_secs_f32() };
                 progress_bar.set_position(vec3(-1.0 + progress - cube_width, 0.5 + 0.05, -0.5));
                 progress_bar.reset_scaling();
                 progress_bar.scale(vec3(progress, 0.05, 0.001));
-                progress_bar.set_color(vec3(0.0, 0.2, 1.0));
-
-                for (i, cube) in cubes.enumerate() {
-                    // Collect values in a gamma-corrected frequency range (using gamma = 2.2)
-                    let values: Vec<f32> = powers
-                        .iter()
-                        .enumerate()
-                        .filter(|(fi, _)| {
-                            i == (((*fi as f32 / powers.len() as f32).powf(1.0/2.2)) * cubes_count as f32) as usize
-                        })
-                        .map(|(_, f)| *f)
-                        .collect();
-
-                    let mut scale_value = if values.is_empty() {
-                        0.0
-                    } else {
-                        // Take only the logarithm of the maximum power to avoid swamping values
-                        (values.iter().max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap() + 1.0).log10()
-                    };
-                    // Smooth the scale value
-                    let last_iteration_scale = cube.get_scale().y;
-                    scale_value = last_iteration_scale * smoothing_delta + scale_value * (1.0 - smoothing_delta);
+                // Each section of the track gets its own shade of blue
+                let section = ga.analysis.as_ref().map(|a| a.section_at(rta.timestamp)).unwrap_or_default();
+                progress_bar.set_color(vec3(0.0, 0.2 + 0.15 * (section % 4) as f32, 1.0));
 
+                let levels = {
+                    let mut bands = bands.borrow_mut();
+                    bands.map(&rta.spectrum);
+                    bands.levels(-60.0)
+                };
+                let bars = scene.instanced[0].instances_mut().iter_mut();
+                for (i, (cube, &scale_value)) in bars.zip(&levels).enumerate() {
                     cube.set_position(vec3(-1.0 + (cube_width * 2.0 * i as f32), 0.5 - scale_value, -0.5));
                     cube.reset_scaling();
                     cube.scale(vec3(cube_width, scale_value, 0.0001));
@@ -126,19 +246,224 @@ impl Synesthesia {
                     );
                 }
             }),
+            camera: Default::default(),
             models: cubes,
+            instanced: vec![bars],
             ambient: AmbientLight { color: [1.0, 1.0, 1.0], intensity: 0.5 },
             directionals: vec![
                 DirectionalLight { position: [-4.0, 0.0, 0.0], color: [1.0, 1.0, 1.0], intensity: 1.0 },
-            ]
+            ],
+            materials: vec![],
+            effects: vec![],
+            svgs: vec![],
+            canvas: Default::default(),
+            texts: vec![],
+            shadertoy: None,
+            files: vec![]
+        }
+    }
+
+    /// Adds a sound file at the end of the playlist
+    pub fn enqueue(&mut self, file_path: &str) {
+        self.sound_pool.load(file_path, file_path).unwrap();
+        self.audio_player.enqueue(self.sound_pool.get(file_path).unwrap());
+    }
+
+    /// Renders the loaded scene, or the demo one, reacting to a sound file
+    /// frame by frame at a fixed frame rate, without playing anything. Every
+    /// frame sees exactly the samples up to its timestamp, so renders are
+    /// reproducible
+    pub fn render(mut self, file_path: &str, frame_rate: u32, output: VideoOutput) -> Result<(), String> {
+        if frame_rate == 0 {
+            return Err(String::from("the frame rate should be at least 1"));
+        }
+        self.sound_pool.load(file_path, file_path)?;
+        let sound = self.sound_pool.get(file_path).unwrap().clone();
+        let mut decoder = sound.decoder()?;
+        let mut analyzer = Analyzer::new(sound.sample_rate(), sound.channel_count(), Default::default());
+        let rta: Mutex<RealtimeAttributes> = Default::default();
+        let ga = GeneralAttributes {
+            duration: sound.duration(),
+            track_changed: true,
+            title: sound.title(),
+            metadata: sound.metadata().clone(),
+            analysis: TrackAnalysis::load_or_analyse(&sound).ok().map(Arc::new),
+            ..Default::default()
         };
 
-        self.scene = Some(scene);
+        let mut writer = FrameWriter::new(output, self.rendering_system.dimensions(), frame_rate, sound.path())?;
+        let mut scene = match self.scene.take() {
+            Some(scene) => scene,
+            None => self.demo_scene()
+        };
+        let mut samples = vec![];
+        let mut samples_read = 0;
+        let dt = 1.0 / frame_rate as f32;
+        for frame in 1.. {
+            let timestamp = Duration::from_secs_f64(frame as f64 / frame_rate as f64);
+            let frames_due = (timestamp.as_secs_f64() * sound.sample_rate() as f64).round() as usize;
+            samples.resize(frames_due * sound.channel_count() - samples_read, 0.0);
+            let read = decoder.read(&mut samples);
+            if read == 0 { break }
+            samples_read += read;
+            analyzer.analyse(&samples[..read], timestamp, &rta);
+
+            let main = scene.main.clone();
+            let (rta, ga) = (take_realtime_attributes(&rta), GeneralAttributes { track_changed: frame == 1, ..ga.clone() });
+            self.update_audio_uniforms(&rta, &ga);
+            main(&mut scene, dt, rta, ga);
+            self.draw(&scene).map_err(|e| format!("{:?}", e))?;
+            writer.write(&self.rendering_system.frame().unwrap())?;
+        }
+
+        writer.finish()
+    }
+
+    /// Draws a scene, which is presented or read back once done
+    fn draw(&mut self, scene: &Scene) -> Result<(), RenderingError> {
+        if let Some(shadertoy) = &scene.shadertoy {
+            self.rendering_system.set_channel(&shadertoy.channel);
+        }
+        self.rendering_system.set_camera(&scene.camera);
+        let error_text = self.reload_error_text.as_ref().filter(|_| !self.reload_errors.is_empty());
+        let texts: Vec<&Text> = scene.texts.iter().chain(error_text).collect();
+        self.rendering_system.prepare_texts(&texts);
+        self.rendering_system.start_render()?;
+        match &scene.shadertoy {
+            Some(shadertoy) => self.rendering_system.draw_shadertoy(shadertoy)?,
+            None => {
+                for model in &scene.models {
+                    self.rendering_system.add_model(model)?;
+                }
+                for instanced in &scene.instanced {
+                    self.rendering_system.add_instances(instanced)?;
+                }
+                self.rendering_system.calculate_ambient_light(&scene.ambient)?;
+                for light in &scene.directionals {
+                    self.rendering_system.calculate_directional_light(light).unwrap();
+                }
+            }
+        }
+        for effect in &scene.effects {
+            self.rendering_system.add_effect(effect)?;
+        }
+        for svg in &scene.svgs {
+            self.rendering_system.add_overlay(svg)?;
+        }
+        self.rendering_system.add_canvas(&scene.canvas)?;
+        for text in texts {
+            self.rendering_system.add_text(text)?;
+        }
+        self.rendering_system.finish_render(&mut self.previous_frame_end)
+    }
+
+    /// Starts watching the shaders and the files of the scene
+    fn watch(&mut self) {
+        let mut watcher = match FileWatcher::new() {
+            Ok(watcher) => watcher,
+            Err(e) => return eprintln!("Hot reload is disabled: {}", e)
+        };
+        // Shaders are only around when running from the sources
+        for entry in fs::read_dir(SHADER_DIRECTORY).into_iter().flatten().flatten() {
+            if let Err(e) = watcher.watch(&entry.path()) {
+                eprintln!("Could not watch {}", e);
+            }
+        }
+        self.watcher = Some(watcher);
+        self.watch_scene();
+    }
+
+    fn watch_scene(&mut self) {
+        if let (Some(watcher), Some(scene)) = (&mut self.watcher, &self.scene) {
+            for file in &scene.files {
+                if let Err(e) = watcher.watch(file) {
+                    eprintln!("Could not watch {}", e);
+                }
+            }
+        }
+    }
+
+    /// Reloads the shaders and the scene if their files changed, keeping what
+    /// was there before if anything goes wrong. Playback is left untouched
+    fn hot_reload(&mut self) {
+        let changes = match &self.watcher {
+            Some(watcher) => watcher.changes(),
+            None => return
+        };
+        let shader_directory = Path::new(SHADER_DIRECTORY).canonicalize().ok();
+        let mut reload_scene = false;
+        for path in changes {
+            if path.parent() == shader_directory.as_deref() {
+                let result = self.rendering_system.reload_shader(&path);
+                self.report_reload(&path, result);
+            } else if self.scene.iter().flat_map(|s| &s.files).any(|f| f.canonicalize().ok() == Some(path.clone())) {
+                reload_scene = true;
+            }
+        }
+
+        if let (true, Some(scene_path)) = (reload_scene, self.scene_path.clone()) {
+            let result = scripting::load(scene_path.to_str().unwrap()).and_then(|scene| {
+                self.load_resources(&scene)?;
+                self.scene = Some(scene);
+                self.watch_scene();
+                Ok(())
+            });
+            self.report_reload(&scene_path, result);
+        }
+    }
+
+    /// Keeps track of the files that could not be reloaded, showing the last
+    /// error in the title of the window and all of them over the frame until
+    /// they are fixed
+    fn report_reload(&mut self, path: &Path, result: Result<(), String>) {
+        self.reload_errors.retain(|(p, _)| p != path);
+        if let Err(e) = result {
+            eprintln!("Could not reload {}: {}", path.display(), e);
+            self.reload_errors.push((path.to_path_buf(), e));
+        }
+
+        let errors: Vec<_> = self.reload_errors.iter()
+            .map(|(path, e)| format!("{}: {}", path.file_name().unwrap_or_default().to_string_lossy(), Self::error_line(e)))
+            .collect();
+        let title = match errors.last() {
+            Some(error) => format!("{} - {}", TITLE, error),
+            None => String::from(TITLE)
+        };
+        self.rendering_system.set_title(&title);
+
+        if errors.is_empty() {
+            return;
+        }
+        if self.reload_error_text.is_none() {
+            match Font::sans_serif() {
+                Ok(font) => {
+                    let mut text = Text::new(font, "");
+                    text.set_position(glm::vec2(0.0, -0.95))
+                        .set_size(0.05)
+                        .set_color(vec3(1.0, 0.3, 0.3))
+                        .set_align(Align::Center)
+                        .set_max_width(Some(1.8));
+                    self.reload_error_text = Some(text);
+                },
+                Err(e) => return eprintln!("Reload errors can't be shown over the frame: {}", e)
+            }
+        }
+        if let Some(text) = &mut self.reload_error_text {
+            text.set_content(&errors.join("\n"));
+        }
+    }
+
+    /// Compilers tend to spread their errors over several lines, starting with
+    /// the one worth showing
+    fn error_line(error: &str) -> &str {
+        error.lines().find(|l| l.contains("error:")).or_else(|| error.lines().next()).unwrap_or_default()
     }
 
     pub fn run(mut self) {
         let mut last_frame = Instant::now();
-        self.event_loop.run(move |event, _, control_flow| match event {
+        let event_loop = self.event_loop.take().expect("Running requires a window");
+        self.watch();
+        event_loop.run(move |event, _, control_flow| match event {
             Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => {
                 *control_flow = ControlFlow::Exit;
             },
@@ -154,28 +479,53 @@ impl Synesthesia {
                     } else {
                         self.audio_player.pause()
                     }
+                    0x69 if i.state == ElementState::Pressed => {
+                        let position = self.audio_player.position();
+                        self.audio_player.seek(position.saturating_sub(SEEK_STEP))
+                    }
+                    0x6a if i.state == ElementState::Pressed => {
+                        let position = self.audio_player.position();
+                        self.audio_player.seek(position + SEEK_STEP)
+                    }
+                    0x31 if i.state == ElementState::Pressed => self.audio_player.next(),
+                    0x19 if i.state == ElementState::Pressed => self.audio_player.previous(),
+                    0x1f if i.state == ElementState::Pressed => {
+                        let shuffle = !self.audio_player.playlist().shuffle();
+                        self.audio_player.set_shuffle(shuffle)
+                    }
+                    0x13 if i.state == ElementState::Pressed => {
+                        let repeat = match self.audio_player.playlist().repeat() {
+                            Repeat::Off => Repeat::All,
+                            Repeat::All => Repeat::One,
+                            Repeat::One => Repeat::Off
+                        };
+                        self.audio_player.set_repeat(repeat)
+                    }
+                    0x22 if i.state == ElementState::Pressed => {
+                        let auto_gain = match self.audio_player.auto_gain() {
+                            Some(_) => None,
+                            None => Some(AUTO_GAIN_TARGET)
+                        };
+                        self.audio_player.set_auto_gain(auto_gain)
+                    }
                     _ => ()
                 }
             },
             Event::RedrawEventsCleared => {
                 self.previous_frame_end.as_mut().take().unwrap().cleanup_finished();
+                self.audio_player.update();
+                self.hot_reload();
                 if let Some(mut s) = self.scene.take() {
                     // Update our scene
                     let dt = last_frame.elapsed().as_secs_f32();
                     last_frame = Instant::now();
                     let main = s.main.clone();
-                    main(&mut s, dt, self.audio_player.get_realtime_attributes(), self.audio_player.get_general_attributes());
+                    let (rta, ga) = (self.audio_player.get_realtime_attributes(), self.audio_player.get_general_attributes());
+                    self.update_audio_uniforms(&rta, &ga);
+                    main(&mut s, dt, rta, ga);
 
-                    // Then drawing it
-                    self.rendering_system.start_render().unwrap();
-                    for model in &s.models {
-                        if self.rendering_system.add_model(model).is_err() { self.scene = Some(s); return; };
-                    }
-                    if self.rendering_system.calculate_ambient_light(&s.ambient).is_err() { self.scene = Some(s); return;};
-                    for light in &s.directionals {
-                        self.rendering_system.calculate_directional_light(light).unwrap();
-                    }
-                    if self.rendering_system.finish_render(&mut self.previous_frame_end).is_err() { self.scene = Some(s); return; }
+                    // Then drawing it, skipping frames that cannot be drawn
+                    let _ = self.draw(&s);
 
                     self.scene = Some(s);
                 }
@@ -187,9 +537,14 @@ impl Synesthesia {
 
 /// A scene should be composed of:
 /// - a main function, that will be run once every single frame
-/// - zero or more entities
+/// - a camera the entities are seen through
+/// - zero or more entities, repeated ones being drawn instanced
 /// - one ambient light
 /// - zero or more directional lights
+/// - zero or more effects, drawn over the lit frame
+/// - zero or more SVG drawings, drawn over everything else
+///
+/// or of a Shadertoy shader drawn in place of the models and lights
 struct Scene {
     pub main: Arc<dyn Fn(
         &mut Scene,
@@ -197,7 +552,20 @@ struct Scene {
         RealtimeAttributes,
         GeneralAttributes
     )>,
+    pub camera: Camera,
     pub models: Vec<Model>,
+    pub instanced: Vec<InstancedModel>,
     pub ambient: AmbientLight,
-    pub directionals: Vec<DirectionalLight>
+    pub directionals: Vec<DirectionalLight>,
+    /// Fragment shaders models can be drawn with, compiled when loading
+    pub materials: Vec<PathBuf>,
+    pub effects: Vec<PathBuf>,
+    pub svgs: Vec<Svg>,
+    /// Shapes drawn over the SVG drawings
+    pub canvas: Canvas,
+    /// Texts drawn over everything else
+    pub texts: Vec<Text>,
+    pub shadertoy: Option<Shadertoy>,
+    /// Files the scene was made from, reloading it when they change
+    pub files: Vec<PathBuf>
 }
\ No newline at end of file
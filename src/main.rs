@@ -1,7 +1,89 @@
-use synesthesia::Synesthesia;
+use std::path::{Path, PathBuf};
+use synesthesia::{CaptureSource, Synesthesia, VideoOutput};
+
+/// Loads the scene given on the command line, if any
+fn load_scene(synesthesia: &mut Synesthesia, scene: &Option<String>) {
+    if let Some(scene) = scene {
+        if let Err(e) = synesthesia.load_scene(scene) {
+            eprintln!("Could not load {}: {}", scene, e);
+            std::process::exit(1);
+        }
+    }
+}
 
 fn main()  {
-    let mut synesthesia: Synesthesia = Synesthesia::init();
-    synesthesia.load_scene(&std::env::args().nth(1).expect("please provide a sound sample"));
-    synesthesia.run()
-}
\ No newline at end of file
+    let mut args = std::env::args().skip(1).peekable();
+    // Scenes are scripts replacing the demo scene
+    let scene = match args.peek().map(String::as_str) {
+        Some("--scene") => args.nth(1),
+        _ => None
+    };
+    match args.next().expect("please provide a sound sample").as_str() {
+        // Reacts to sound captured from the default input device, a sine wave
+        // of a given frequency, clicks at a given tempo or a file that is never
+        // played back
+        "--capture" => {
+            let mut synesthesia = Synesthesia::init();
+            load_scene(&mut synesthesia, &scene);
+            synesthesia.capture(match args.next() {
+                None => CaptureSource::Device,
+                Some(source) => if let Some(frequency) = source.strip_prefix("sine:") {
+                    CaptureSource::Sine {
+                        frequency: frequency.parse().expect("invalid sine frequency"),
+                        sample_rate: 44100
+                    }
+                } else if let Some(bpm) = source.strip_prefix("clicks:") {
+                    CaptureSource::Clicks {
+                        bpm: bpm.parse().expect("invalid click tempo"),
+                        sample_rate: 44100
+                    }
+                } else {
+                    CaptureSource::File(synesthesia::Sound::load(&source).unwrap())
+                }
+            });
+            synesthesia.run()
+        },
+        // Renders a file offline into a PNG sequence (directories), raw RGBA
+        // frames on the standard output ("-") or a video encoded by ffmpeg
+        "--render" => {
+            let output = args.next().expect("please provide an output");
+            let (mut frame_rate, mut dimensions) = (60, [1920, 1080]);
+            let mut sound = None;
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--fps" => frame_rate = args.next().and_then(|f| f.parse().ok()).filter(|&f| f > 0).expect("invalid frame rate"),
+                    "--size" => {
+                        let size = args.next().expect("please provide a size");
+                        let (width, height) = size.split_once('x').expect("size should look like 1920x1080");
+                        dimensions = [width.parse().expect("invalid width"), height.parse().expect("invalid height")];
+                    },
+                    _ => sound = Some(arg)
+                }
+            }
+            let sound = sound.expect("please provide a sound sample");
+
+            let output = if output == "-" {
+                VideoOutput::Raw(Box::new(std::io::stdout()))
+            } else if output.ends_with('/') || Path::new(&output).is_dir() {
+                VideoOutput::PngSequence(PathBuf::from(output))
+            } else {
+                VideoOutput::Ffmpeg(PathBuf::from(output))
+            };
+            let mut synesthesia = Synesthesia::init_headless(dimensions);
+            load_scene(&mut synesthesia, &scene);
+            if let Err(e) = synesthesia.render(&sound, frame_rate, output) {
+                eprintln!("Rendering failed: {}", e);
+                std::process::exit(1);
+            }
+        },
+        path => {
+            let mut synesthesia = Synesthesia::init();
+            load_scene(&mut synesthesia, &scene);
+            synesthesia.play(path);
+            for path in args {
+                synesthesia.enqueue(&path);
+            }
+            synesthesia.run()
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use fontdb::{Database, Family, Query};
+use ttf_parser::Face;
+use crate::resource_pool::ResourcePool;
+
+/// Gives every font loaded its own id, for glyph caches to tell them apart
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl ResourcePool<Font> {
+    pub fn load(&mut self, resource_id: &str, file_path: &str) -> Result<(), String> {
+        let font = Font::load(file_path)?;
+        self.0.insert(String::from(resource_id), font);
+        Ok(())
+    }
+}
+
+/// A TrueType or OpenType font, whose data copies share
+#[derive(Clone)]
+pub struct Font {
+    id: usize,
+    data: Arc<Vec<u8>>,
+    /// Index of the face in font collections
+    index: u32
+}
+
+impl Font {
+    pub fn load(file_path: &str) -> Result<Self, String> {
+        let data = fs::read(file_path).map_err(|e| format!("{}: {}", file_path, e))?;
+        Self::from_data(data, 0).map_err(|e| format!("{}: {}", file_path, e))
+    }
+
+    /// Finds the regular face of a font family installed on the system
+    pub fn system(family: &str) -> Result<Self, String> {
+        Self::query(&[Family::Name(family)], family)
+    }
+
+    /// Finds the regular face of a sans-serif font installed on the system
+    pub fn sans_serif() -> Result<Self, String> {
+        // The generic family is Arial, which few Linux systems have
+        let families = [
+            Family::SansSerif,
+            Family::Name("DejaVu Sans"),
+            Family::Name("Liberation Sans"),
+            Family::Name("Noto Sans")
+        ];
+        Self::query(&families, "sans-serif")
+    }
+
+    fn query(families: &[Family], name: &str) -> Result<Self, String> {
+        let mut database = Database::new();
+        database.load_system_fonts();
+        let id = database.query(&Query { families, ..Default::default() })
+            .ok_or(format!("no font named {}", name))?;
+        database.with_face_data(id, |data, index| Self::from_data(data.to_vec(), index))
+            .ok_or(format!("{} could not be read", name))?
+    }
+
+    fn from_data(data: Vec<u8>, index: u32) -> Result<Self, String> {
+        Face::from_slice(&data, index).map_err(|e| e.to_string())?;
+        Ok(Font { id: NEXT_ID.fetch_add(1, Ordering::Relaxed), data: Arc::new(data), index })
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn face(&self) -> Face<'_> {
+        // The data was parsed successfully when the font was loaded
+        Face::from_slice(&self.data, self.index).unwrap()
+    }
+}
@@ -0,0 +1,170 @@
+use glm::{identity, inverse_transpose, mat4_to_mat3, TMat4, TVec3, vec3, vec4};
+use gltf::{buffer, image, Node, Primitive};
+use gltf::image::Format;
+use gltf::mesh::Mode;
+use super::NormalVertex;
+use super::texture_loader::Texture;
+
+/// Reads the scene of a glTF file, `.gltf` or `.glb`, flattening it into a
+/// single mesh
+///
+/// Meshes are moved by the nodes holding them, and the base colors of their
+/// materials are baked into their vertices. Models only have one emissive
+/// color and texture, so those of the first material having one are kept.
+pub struct GltfLoader {
+    color: [f32; 3],
+    invert_winding_order: bool,
+    pub vertices: Vec<NormalVertex>,
+    pub indices: Vec<u32>,
+    pub emissive: [f32; 3],
+    /// Base color texture, embedded or not
+    pub texture: Option<Texture>
+}
+
+impl GltfLoader {
+    pub fn new(file_name: &str, custom_color: [f32; 3], invert_winding_order: bool) -> Result<GltfLoader, String> {
+        let (document, buffers, images) = gltf::import(file_name).map_err(|e| format!("{}: {}", file_name, e))?;
+        let scene = document.default_scene()
+            .or_else(|| document.scenes().next())
+            .ok_or(format!("{} has no scene", file_name))?;
+
+        let mut loader = GltfLoader {
+            color: custom_color,
+            invert_winding_order,
+            vertices: vec![],
+            indices: vec![],
+            emissive: [0.0; 3],
+            texture: None
+        };
+        for node in scene.nodes() {
+            loader.add_node(&node, &identity(), &buffers, &images)
+                .map_err(|e| format!("{}: {}", file_name, e))?;
+        }
+        Ok(loader)
+    }
+
+    fn add_node(
+        &mut self,
+        node: &Node,
+        parent: &TMat4<f32>,
+        buffers: &[buffer::Data],
+        images: &[image::Data]
+    ) -> Result<(), String> {
+        let transform = parent * TMat4::from(node.transform().matrix());
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                self.add_primitive(&primitive, &transform, buffers, images)?;
+            }
+        }
+        for child in node.children() {
+            self.add_node(&child, &transform, buffers, images)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the triangles of a primitive, anything else being skipped
+    fn add_primitive(
+        &mut self,
+        primitive: &Primitive,
+        transform: &TMat4<f32>,
+        buffers: &[buffer::Data],
+        images: &[image::Data]
+    ) -> Result<(), String> {
+        if primitive.mode() != Mode::Triangles {
+            return Ok(())
+        }
+        let reader = primitive.reader(|b| buffers.get(b.index()).map(|d| d.0.as_slice()));
+        let positions: Vec<[f32; 3]> = reader.read_positions().ok_or("primitive without positions")?.collect();
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect()
+        };
+        if indices.iter().any(|&i| i as usize >= positions.len()) {
+            return Err("index out of bounds".into())
+        }
+        let normals = match reader.read_normals() {
+            Some(normals) => normals.collect(),
+            None => smooth_normals(&positions, &indices)
+        };
+        let colors: Vec<[f32; 3]> = reader.read_colors(0).map(|c| c.into_rgb_f32().collect()).unwrap_or_default();
+        let uvs: Vec<[f32; 2]> = reader.read_tex_coords(0).map(|t| t.into_f32().collect()).unwrap_or_default();
+
+        let material = primitive.material();
+        let pbr = material.pbr_metallic_roughness();
+        let base_color = pbr.base_color_factor();
+        if self.emissive == [0.0; 3] {
+            self.emissive = material.emissive_factor();
+        }
+        if self.texture.is_none() {
+            self.texture = pbr.base_color_texture()
+                .and_then(|info| images.get(info.texture().source().index()))
+                .and_then(texture);
+        }
+
+        let normal_matrix = mat4_to_mat3(&inverse_transpose(*transform));
+        let offset = self.vertices.len() as u32;
+        for (i, position) in positions.iter().enumerate() {
+            let position = transform * vec4(position[0], position[1], position[2], 1.0);
+            let normal = normal_matrix * TVec3::from(normals.get(i).copied().unwrap_or_default());
+            let color = colors.get(i).copied().unwrap_or([1.0; 3]);
+            self.vertices.push(NormalVertex {
+                position: [position.x, position.y, position.z],
+                normal: normal.try_normalize(0.0).unwrap_or(normal).into(),
+                color: [0, 1, 2].map(|c| color[c] * base_color[c] * self.color[c]),
+                uv: uvs.get(i).copied().unwrap_or_default()
+            });
+        }
+        for triangle in indices.chunks_exact(3) {
+            let (a, b, c) = (triangle[0] + offset, triangle[1] + offset, triangle[2] + offset);
+            if self.invert_winding_order {
+                self.indices.extend([a, c, b]);
+            } else {
+                self.indices.extend([a, b, c]);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Averages the normals of the triangles around each vertex, weighted by their
+/// area
+fn smooth_normals(positions: &[[f32; 3]], indices: &[u32]) -> Vec<[f32; 3]> {
+    let mut normals = vec![vec3(0.0, 0.0, 0.0); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| TVec3::from(positions[triangle[i] as usize]));
+        let normal = (b - a).cross(&(c - a));
+        for &i in triangle {
+            normals[i as usize] += normal;
+        }
+    }
+    normals.into_iter().map(|n| n.try_normalize(0.0).unwrap_or(n).into()).collect()
+}
+
+/// Converts a decoded image to RGBA, keeping the 8 most significant bits of
+/// 16 bit channels. PNG and JPEG images are never decoded to other formats
+fn texture(image: &image::Data) -> Option<Texture> {
+    let (channels, bytes) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        _ => return None
+    };
+    let pixels = image.pixels.chunks_exact(channels * bytes).flat_map(|pixel| {
+        let channel = |i: usize| match bytes {
+            2 => (u16::from_ne_bytes([pixel[i * 2], pixel[i * 2 + 1]]) >> 8) as u8,
+            _ => pixel[i]
+        };
+        match channels {
+            1 => [channel(0), channel(0), channel(0), 255],
+            2 => [channel(0), channel(0), channel(0), channel(1)],
+            3 => [channel(0), channel(1), channel(2), 255],
+            _ => [channel(0), channel(1), channel(2), channel(3)]
+        }
+    }).collect();
+    Some(Texture { dimensions: [image.width, image.height], pixels })
+}
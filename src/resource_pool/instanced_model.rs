@@ -0,0 +1,165 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use glm::{identity, inverse_transpose, rotate_normalized_axis, scale, TMat4, translate, TVec3, vec3};
+use super::obj_loader::NormalVertex;
+use super::texture_loader::Texture;
+use crate::graphics::{InstanceData, MaterialParameters};
+use crate::graphics::mesh::Mesh;
+use crate::graphics::rendering_system::RenderInstanced;
+
+/// A mesh drawn many times in a single call, each instance having its own
+/// transform and color
+///
+/// Only the instances change from frame to frame, the mesh being uploaded to
+/// the GPU once
+#[derive(Clone)]
+pub struct InstancedModel {
+    mesh: Arc<Mesh<NormalVertex>>,
+    instances: Vec<Instance>,
+    /// Fragment shader to draw with, if not the default one
+    material: Option<PathBuf>,
+    /// Base color texture, the one the file came with unless replaced
+    texture: Option<Arc<Texture>>
+}
+impl InstancedModel {
+    /// Creates `count` instances of a mesh, all at the origin and white
+    pub fn new(mesh: Arc<Mesh<NormalVertex>>, count: usize) -> Self {
+        InstancedModel {
+            mesh,
+            instances: vec![Instance::default(); count],
+            material: None,
+            texture: None
+        }
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    pub fn instances_mut(&mut self) -> &mut Vec<Instance> {
+        &mut self.instances
+    }
+
+    /// Draws the instances with a material loaded by the rendering system, or
+    /// the default one if `None`
+    pub fn set_material(&mut self, material: Option<PathBuf>) -> &mut Self {
+        self.material = material;
+        self
+    }
+
+    pub fn material(&self) -> Option<&Path> {
+        self.material.as_deref()
+    }
+
+    pub fn set_texture(&mut self, texture: Option<Arc<Texture>>) -> &mut Self {
+        self.texture = texture;
+        self
+    }
+
+    pub fn texture(&self) -> Option<&Arc<Texture>> {
+        self.texture.as_ref()
+    }
+}
+impl RenderInstanced for InstancedModel {
+    fn mesh(&self) -> &Mesh<NormalVertex> {
+        &self.mesh
+    }
+
+    fn instances(&self) -> Vec<InstanceData> {
+        self.instances.iter().map(Instance::data).collect()
+    }
+
+    fn material(&self) -> Option<&Path> {
+        self.material()
+    }
+
+    fn texture(&self) -> Option<&Arc<Texture>> {
+        self.texture()
+    }
+}
+
+/// Transform and material parameters of one instance of an `InstancedModel`,
+/// which works like a `Model`
+#[derive(Clone)]
+pub struct Instance {
+    translation: TMat4<f32>,
+    rotation: TMat4<f32>,
+    scale: TMat4<f32>,
+    parameters: MaterialParameters
+}
+impl Default for Instance {
+    fn default() -> Self {
+        Instance {
+            translation: identity(),
+            rotation: identity(),
+            scale: identity(),
+            parameters: Default::default()
+        }
+    }
+}
+impl Instance {
+    pub fn rotate(&mut self, radians: f32, v: TVec3<f32>) -> &mut Self {
+        self.rotation = rotate_normalized_axis(&self.rotation, radians, &v);
+        self
+    }
+
+    pub fn translate(&mut self, v: TVec3<f32>) -> &mut Self {
+        self.translation = translate(&self.translation, &v);
+        self
+    }
+
+    pub fn set_position(&mut self, v: TVec3<f32>) -> &mut Self {
+        self.translation = translate(&identity(), &v);
+        self
+    }
+
+    /// Return the instance's rotation to 0
+    pub fn zero_rotation(&mut self) -> &mut Self {
+        self.rotation = identity();
+        self
+    }
+
+    pub fn scale(&mut self, v: TVec3<f32>) -> &mut Self {
+        self.scale = scale(&self.scale, &v);
+        self
+    }
+
+    pub fn reset_scaling(&mut self) -> &mut Self {
+        self.scale = identity();
+        self
+    }
+
+    /// Multiplies the colors of the mesh, which are white unless it was built
+    /// with another one
+    pub fn set_color(&mut self, color: TVec3<f32>) -> &mut Self {
+        self.parameters.color = color.into();
+        self
+    }
+
+    pub fn set_emissive(&mut self, color: TVec3<f32>) -> &mut Self {
+        self.parameters.emissive = color.into();
+        self
+    }
+
+    /// Sets how opaque the instance is, in [0, 1]
+    pub fn set_opacity(&mut self, opacity: f32) -> &mut Self {
+        self.parameters.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn get_scale(&self) -> TVec3<f32> {
+        vec3(self.scale.m11, self.scale.m22, self.scale.m33)
+    }
+    pub fn get_position(&self) -> TVec3<f32> { vec3(self.translation.m14, self.translation.m24, self.translation.m34) }
+
+    fn data(&self) -> InstanceData {
+        let model = self.translation * self.rotation * self.scale;
+        InstanceData {
+            instance_model: model.into(),
+            instance_normals: inverse_transpose(model).into(),
+            instance_color: self.parameters.color,
+            instance_emissive: self.parameters.emissive,
+            instance_opacity: self.parameters.opacity
+        }
+    }
+}
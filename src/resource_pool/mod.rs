@@ -1,6 +1,11 @@
+pub mod font_loader;
+mod gltf_loader;
+pub mod instanced_model;
 pub mod model_loader;
 mod obj_loader;
 pub mod sound_loader;
+pub mod svg_loader;
+pub mod texture_loader;
 
 pub use obj_loader::NormalVertex;
 
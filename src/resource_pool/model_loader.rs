@@ -1,14 +1,22 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use glm::{identity, inverse_transpose, rotate_normalized_axis, scale, TMat4, translate, TVec3, vec3};
+use super::gltf_loader::GltfLoader;
+use super::instanced_model::InstancedModel;
 use super::obj_loader::{ Loader, NormalVertex };
+use super::texture_loader::Texture;
 use crate::resource_pool::ResourcePool;
+use crate::graphics::MaterialParameters;
+use crate::graphics::mesh::Mesh;
 use crate::graphics::rendering_system::Render;
 
 
-vulkano::impl_vertex!(NormalVertex, position, normal, color);
+vulkano::impl_vertex!(NormalVertex, position, normal, color, uv);
 
+/// Copies of a model share its mesh, which is only uploaded once
 impl ResourcePool<Model> {
     pub fn load(&mut self, resource_id: &str, file_path: &str) -> Result<(), String> {
-        let model = Model::new(file_path).build();
+        let model = Model::new(file_path).build()?;
         self.0.insert(String::from(resource_id), model);
         Ok(())
     }
@@ -16,22 +24,35 @@ impl ResourcePool<Model> {
 
 /// This has been taken from taidaesal's Vulkano tutorial
 ///
-/// Holds our data for a renderable model, including the model matrix data
+/// Holds our data for a renderable model: a handle to its mesh, and the model
+/// matrix data
+///
+/// Models are loaded from OBJ files, or glTF ones if their extension is
+/// `.gltf` or `.glb`.
 ///
 /// Note: When building an instance of `Model` the loader will assume that
 /// the input obj file is in clockwise winding order. If it is already in
 /// counter-clockwise winding order, call `.invert_winding_order(false)`
-/// when building the `Model`.
+/// when building the `Model`. glTF files have their winding order inverted
+/// the same way.
 #[derive(Clone)]
 pub struct Model {
-    data: Vec<NormalVertex>,
+    mesh: Arc<Mesh<NormalVertex>>,
     translation: TMat4<f32>,
     rotation: TMat4<f32>,
     model: TMat4<f32>,
     normals: TMat4<f32>,
-    scale: TMat4<f32>
+    scale: TMat4<f32>,
+    parameters: MaterialParameters,
+    /// Fragment shader to draw with, if not the default one
+    material: Option<PathBuf>,
+    /// Base color texture, the one the file came with unless replaced
+    texture: Option<Arc<Texture>>
 }
 
+/// Mesh of a file, with the emissive color and texture it came with
+type LoadedMesh = (Mesh<NormalVertex>, [f32; 3], Option<Texture>);
+
 pub struct ModelBuilder {
     file_name: String,
     custom_color: [f32; 3],
@@ -46,15 +67,44 @@ impl ModelBuilder {
         }
     }
 
-    pub fn build(self) -> Model {
-        let loader = Loader::new(self.file_name.as_str(), self.custom_color, self.invert);
-        Model {
-            data: loader.as_normal_vertices(),
+    pub fn build(self) -> Result<Model, String> {
+        let (mesh, emissive, texture) = self.load()?;
+        Ok(Model {
+            mesh: Arc::new(mesh),
             translation: identity(),
             rotation: identity(),
             model: identity(),
             normals: identity(),
-            scale: identity()
+            scale: identity(),
+            parameters: MaterialParameters { emissive, ..Default::default() },
+            material: None,
+            texture: texture.map(Arc::new)
+        })
+    }
+
+    /// Builds `count` instances of the model, to be drawn in a single call
+    pub fn build_instanced(self, count: usize) -> Result<InstancedModel, String> {
+        let (mesh, emissive, texture) = self.load()?;
+        let mut model = InstancedModel::new(Arc::new(mesh), count);
+        for instance in model.instances_mut() {
+            instance.set_emissive(emissive.into());
+        }
+        model.set_texture(texture.map(Arc::new));
+        Ok(model)
+    }
+
+    /// Reads the mesh of the file, along with the emissive color and texture
+    /// of glTF files
+    fn load(&self) -> Result<LoadedMesh, String> {
+        match Path::new(&self.file_name).extension().and_then(|e| e.to_str()) {
+            Some("gltf" | "glb") => {
+                let loader = GltfLoader::new(self.file_name.as_str(), self.custom_color, self.invert)?;
+                Ok((Mesh::indexed(loader.vertices, loader.indices), loader.emissive, loader.texture))
+            },
+            _ => {
+                let loader = Loader::new(self.file_name.as_str(), self.custom_color, self.invert)?;
+                Ok((Mesh::new(loader.as_normal_vertices()), [0.0; 3], None))
+            }
         }
     }
 
@@ -79,8 +129,8 @@ impl Model {
         ModelBuilder::new(file_name.into())
     }
 
-    pub fn data(&self) -> Vec<NormalVertex> {
-        self.data.clone()
+    pub fn mesh(&self) -> &Arc<Mesh<NormalVertex>> {
+        &self.mesh
     }
 
     pub fn model_matrices(&self) -> (TMat4<f32>, TMat4<f32>) {
@@ -118,12 +168,21 @@ impl Model {
         self.recalculate_models()
     }
 
+    /// Multiplies the colors of the mesh, which are white unless the model was
+    /// built with another one
     pub fn set_color(&mut self, color: TVec3<f32>) -> &mut Self {
-        for v in self.data.iter_mut() {
-            v.color[0] = color.x;
-            v.color[1] = color.y;
-            v.color[2] = color.z;
-        }
+        self.parameters.color = color.into();
+        self
+    }
+
+    pub fn set_emissive(&mut self, color: TVec3<f32>) -> &mut Self {
+        self.parameters.emissive = color.into();
+        self
+    }
+
+    /// Sets how opaque the model is, in [0, 1]
+    pub fn set_opacity(&mut self, opacity: f32) -> &mut Self {
+        self.parameters.opacity = opacity.clamp(0.0, 1.0);
         self
     }
 
@@ -133,17 +192,49 @@ impl Model {
         self
     }
 
+    /// Draws the model with a material loaded by the rendering system, or the
+    /// default one if `None`
+    pub fn set_material(&mut self, material: Option<PathBuf>) -> &mut Self {
+        self.material = material;
+        self
+    }
+
+    pub fn material(&self) -> Option<&Path> {
+        self.material.as_deref()
+    }
+
+    pub fn set_texture(&mut self, texture: Option<Arc<Texture>>) -> &mut Self {
+        self.texture = texture;
+        self
+    }
+
+    pub fn texture(&self) -> Option<&Arc<Texture>> {
+        self.texture.as_ref()
+    }
+
     pub fn get_scale(&self) -> TVec3<f32> {
         vec3(self.scale.m11, self.scale.m22, self.scale.m33)
     }
     pub fn get_position(&self) -> TVec3<f32> { vec3(self.translation.m14, self.translation.m24, self.translation.m34) }
 }
 impl Render<NormalVertex> for Model {
-    fn vertices(&self) -> Vec<NormalVertex> {
-        self.data()
+    fn mesh(&self) -> &Mesh<NormalVertex> {
+        &self.mesh
     }
 
     fn model_matrices(&self) -> (TMat4<f32>, TMat4<f32>) {
         self.model_matrices()
     }
+
+    fn parameters(&self) -> MaterialParameters {
+        self.parameters
+    }
+
+    fn material(&self) -> Option<&Path> {
+        self.material()
+    }
+
+    fn texture(&self) -> Option<&Arc<Texture>> {
+        self.texture()
+    }
 }
\ No newline at end of file
@@ -14,35 +14,44 @@ impl RawFace {
     // call with invert = true if the models are using a clockwise winding order
     //
     // Blender files are a common example
-    pub fn new(raw_arg: &str, invert: bool) -> RawFace {
+    pub fn new(raw_arg: &str, invert: bool) -> Result<RawFace, String> {
         let arguments: Vec<&str> = raw_arg.split_whitespace().collect();
-        RawFace {
-            verts: RawFace::parse(arguments.clone(), 0, invert).unwrap(),
-            norms: RawFace::parse(arguments.clone(), 2, invert),
-            text: RawFace::parse(arguments.clone(), 1, invert),
-        }
+        Ok(RawFace {
+            verts: RawFace::parse(&arguments, 0, invert)?
+                .ok_or(format!("Face without vertices: {}", raw_arg.trim()))?,
+            norms: RawFace::parse(&arguments, 2, invert)?,
+            text: RawFace::parse(&arguments, 1, invert)?,
+        })
     }
 
-    fn parse(inpt: Vec<&str>, index: usize, invert: bool) -> Option<[usize; 3]> {
-        let f1: Vec<&str> = inpt.get(0).unwrap().split("/").collect();
-        let f2: Vec<&str> = inpt.get(1).unwrap().split("/").collect();
-        let f3: Vec<&str> = inpt.get(2).unwrap().split("/").collect();
-        let a1 = f1.get(index).unwrap().clone();
-        let a2 = f2.get(index).unwrap().clone();
-        let a3 = f3.get(index).unwrap().clone();
+    fn parse(inpt: &[&str], index: usize, invert: bool) -> Result<Option<[usize; 3]>, String> {
+        if inpt.len() < 3 {
+            return Err(format!("Face with less than three corners: {}", inpt.join(" ")));
+        }
+        let a1 = inpt[0].split('/').nth(index).unwrap_or("");
+        let a2 = inpt[1].split('/').nth(index).unwrap_or("");
+        let a3 = inpt[2].split('/').nth(index).unwrap_or("");
         match a1 {
-            "" => None,
+            "" => Ok(None),
             _ => {
-                let p1: usize = a1.parse().unwrap();
-                let (p2, p3): (usize, usize) = if invert {
-                    (a3.parse().unwrap(), a2.parse().unwrap())
+                let p1 = RawFace::parse_index(a1)?;
+                let (p2, p3) = if invert {
+                    (RawFace::parse_index(a3)?, RawFace::parse_index(a2)?)
                 } else {
-                    (a2.parse().unwrap(), a3.parse().unwrap())
+                    (RawFace::parse_index(a2)?, RawFace::parse_index(a3)?)
                 };
-                Some([p1 - 1, p2 - 1, p3 - 1]) // .obj files aren't 0-index
+                Ok(Some([p1, p2, p3]))
             }
         }
     }
+
+    // .obj files aren't 0-index
+    fn parse_index(inpt: &str) -> Result<usize, String> {
+        inpt.parse::<usize>()
+            .ok()
+            .and_then(|index| index.checked_sub(1))
+            .ok_or(format!("Invalid face index: {}", inpt))
+    }
 }
 
 impl fmt::Display for RawFace {
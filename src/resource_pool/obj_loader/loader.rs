@@ -20,42 +20,53 @@ pub struct Loader {
 }
 
 impl Loader {
-    pub fn new(file_name: &str, custom_color: [f32; 3], invert_winding_order: bool) -> Loader {
+    pub fn new(file_name: &str, custom_color: [f32; 3], invert_winding_order: bool) -> Result<Loader, String> {
         let color = custom_color;
-        let input = File::open(file_name).unwrap();
+        let input = File::open(file_name).map_err(|e| format!("Could not open {}: {}", file_name, e))?;
         let buffered = BufReader::new(input);
         let mut verts: Vec<RawVertex> = Vec::new();
         let mut norms: Vec<RawVertex> = Vec::new();
         let mut text: Vec<RawVertex> = Vec::new();
         let mut faces: Vec<RawFace> = Vec::new();
         for raw_line in buffered.lines() {
-            let line = raw_line.unwrap();
+            let line = raw_line.map_err(|e| format!("Could not read {}: {}", file_name, e))?;
             if line.len() > 2 {
                 match line.split_at(2) {
                     ("v ", x) => {
-                        verts.push(RawVertex::new(x));
+                        verts.push(RawVertex::new(x)?);
                     }
                     ("vn", x) => {
-                        norms.push(RawVertex::new(x));
+                        norms.push(RawVertex::new(x)?);
                     }
                     ("vt", x) => {
-                        text.push(RawVertex::new(x));
+                        text.push(RawVertex::new(x)?);
                     }
                     ("f ", x) => {
-                        faces.push(RawFace::new(x, invert_winding_order));
+                        faces.push(RawFace::new(x, invert_winding_order)?);
                     }
                     (_, _) => {}
                 };
             }
         }
-        Loader {
+        // Checked up front so building the vertices can't go out of bounds
+        let in_range = |indices: [usize; 3], len: usize| indices.iter().all(|&i| i < len);
+        for face in &faces {
+            let normals = face.norms.ok_or(format!("Face without normals in {}", file_name))?;
+            if !in_range(face.verts, verts.len())
+                || !in_range(normals, norms.len())
+                || face.text.is_some_and(|uvs| !in_range(uvs, text.len()))
+            {
+                return Err(format!("Face index out of range in {}", file_name));
+            }
+        }
+        Ok(Loader {
             color,
             verts,
             norms,
             text,
             faces,
             invert_winding_order,
-        }
+        })
     }
 
     pub fn as_normal_vertices(&self) -> Vec<NormalVertex> {
@@ -67,18 +78,33 @@ impl Loader {
                 position: self.verts.get(verts[0]).unwrap().vals,
                 normal: self.norms.get(normals[0]).unwrap().vals,
                 color: self.color,
+                uv: self.uv(face, 0),
             });
             ret.push(NormalVertex {
                 position: self.verts.get(verts[1]).unwrap().vals,
                 normal: self.norms.get(normals[1]).unwrap().vals,
                 color: self.color,
+                uv: self.uv(face, 1),
             });
             ret.push(NormalVertex {
                 position: self.verts.get(verts[2]).unwrap().vals,
                 normal: self.norms.get(normals[2]).unwrap().vals,
                 color: self.color,
+                uv: self.uv(face, 2),
             });
         }
         ret
     }
+
+    /// Texture coordinates of a corner of a face, flipped vertically as OBJ
+    /// files count from the bottom of images
+    fn uv(&self, face: &RawFace, corner: usize) -> [f32; 2] {
+        match face.text {
+            Some(text) => {
+                let vals = self.text.get(text[corner]).unwrap().vals;
+                [vals[0], 1.0 - vals[1]]
+            }
+            None => [0.0, 0.0],
+        }
+    }
 }
@@ -83,6 +83,8 @@ pub struct NormalVertex {
     pub position: [f32; 3],
     pub normal: [f32; 3],
     pub color: [f32; 3],
+    /// Texture coordinates, 0 where the file has none
+    pub uv: [f32; 2],
 }
 
 impl fmt::Display for DummyVertex {
@@ -122,8 +124,8 @@ impl fmt::Display for NormalVertex {
         );
         write!(
             f,
-            "NormalVertex {{ position: {}, normal: {}, color: {} }}",
-            pos, norms, color
+            "NormalVertex {{ position: {}, normal: {}, color: {}, uv: [{:.6}, {:.6}] }}",
+            pos, norms, color, self.uv[0], self.uv[1]
         )
     }
 }
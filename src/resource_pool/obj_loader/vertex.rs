@@ -7,21 +7,20 @@ pub struct RawVertex {
 }
 
 impl RawVertex {
-    pub fn new(inpt: &str) -> RawVertex {
+    pub fn new(inpt: &str) -> Result<RawVertex, String> {
         let items = inpt.split_whitespace();
         let mut content: Vec<f32> = Vec::new();
         for item in items {
-            content.push(item.parse().unwrap());
+            content.push(item.parse().map_err(|_| format!("Invalid coordinate: {}", item))?);
         }
         if content.len() == 2 {
             content.push(0.0);
         }
-        RawVertex {
-            vals: [
-                *content.get(0).unwrap(),
-                *content.get(1).unwrap(),
-                *content.get(2).unwrap(),
-            ],
+        if content.len() < 3 {
+            return Err(format!("Vertex with less than two coordinates: {}", inpt.trim()));
         }
+        Ok(RawVertex {
+            vals: [content[0], content[1], content[2]],
+        })
     }
 }
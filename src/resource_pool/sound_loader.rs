@@ -1,32 +1,93 @@
 use std::fs::File;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
 use std::time::Duration;
-use symphonia::core::audio::{SampleBuffer};
+use ringbuf::{Consumer, Producer, RingBuffer};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::Decoder;
+use symphonia::core::formats::{FormatReader, SeekMode, SeekTo};
 use symphonia::core::io::MediaSourceStream;
-use symphonia::core::probe::Hint;
+use symphonia::core::meta::{Metadata, MetadataRevision, StandardTagKey, StandardVisualKey};
+use symphonia::core::probe::{Hint, ProbeResult};
+use symphonia::core::units::{Time, TimeBase, TimeStamp};
 use crate::ResourcePool;
+use super::texture_loader::Texture;
+
+/// How much decoded audio the streaming thread keeps ahead of playback
+const BUFFERED_DURATION: Duration = Duration::from_millis(500);
+/// How long the streaming thread waits before trying to refill a full buffer
+const REFILL_INTERVAL: Duration = Duration::from_millis(5);
+/// How long the streaming thread sleeps between checks once a sound is fully decoded
+const IDLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A format reader, a decoder for its default track and the id of said track
+type OpenedFile = (Box<dyn FormatReader>, Box<dyn Decoder>, u32);
 
 impl ResourcePool<Sound> {
     pub fn load(&mut self, resource_id: &str, file_path: &str) -> Result<(), String> {
-        let sound = Sound::load(file_path);
+        let sound = Sound::load(file_path)?;
         self.0.insert(String::from(resource_id), sound);
         Ok(())
     }
 }
 
+/// A handle to an audio file on disk.
+///
+/// Loading a sound only probes the file for its parameters, the samples are
+/// decoded lazily by a `SoundStream` while the sound is being played.
 #[derive(Clone)]
 pub struct Sound {
-    samples: Vec<f32>,
+    path: PathBuf,
     sample_rate: u32,
     channels: usize,
 
-    duration: Duration
+    duration: Duration,
+    metadata: SoundMetadata
 }
 impl Sound {
-    pub fn load(file_path: &str) -> Self {
+    pub fn load(file_path: &str) -> Result<Self, String> {
+        let path = PathBuf::from(file_path);
+        let mut probed = Self::probe(&path)?;
+        // Tags found before the container, such as ID3v2 ones, are read first
+        // so that those of the container take precedence
+        let mut metadata = SoundMetadata::default();
+        if let Some(probed_metadata) = probed.metadata.get() {
+            metadata.read(probed_metadata);
+        }
+        let (mut format, _, track_id) = Self::prepare(probed.format)?;
+        metadata.read(format.metadata());
+
+        let track = format.tracks().iter().find(|t| t.id == track_id).unwrap();
+
+        let timebase = track.codec_params.time_base.ok_or("unknown time base")?;
+        let duration_timestamp = track.codec_params.n_frames
+            .map(|frames| track.codec_params.start_ts + frames)
+            .ok_or("unknown duration")?;
+        let duration_time = timebase.calc_time(duration_timestamp);
+        let duration = Duration::from_secs(duration_time.seconds) + Duration::from_secs_f64(duration_time.frac);
+
+        Ok(Sound {
+            sample_rate: track.codec_params.sample_rate.ok_or("unknown sample rate")?,
+            channels: track.codec_params.channels.ok_or("unknown channel layout")?.count(),
+            path,
+
+            duration,
+            metadata
+        })
+    }
+
+    /// Opens the underlying file and gets it ready for decoding
+    fn open(path: &Path) -> Result<OpenedFile, String> {
+        Self::prepare(Self::probe(path)?.format)
+    }
+
+    /// Opens the underlying file and finds out its format
+    fn probe(path: &Path) -> Result<ProbeResult, String> {
         // Opens the file and create a MediaSourceStream from it
-        let path = Path::new(file_path);
-        let file = Box::new(File::open(path).unwrap());
+        let file = Box::new(File::open(path).map_err(|e| e.to_string())?);
         let media_source = MediaSourceStream::new(file, Default::default());
 
         // Help the library to infer the decoder needed for the file
@@ -38,67 +99,539 @@ impl Sound {
         }
 
         // Probe our MSS for a format
-        let probed = symphonia::default::get_probe().format(
+        symphonia::default::get_probe().format(
             &format_hint,
             media_source,
             &Default::default(),
             &Default::default(),
-        ).unwrap();
-        let mut format = probed.format;
+        ).map_err(|e| e.to_string())
+    }
 
+    /// Creates a decoder for the default track of a format
+    fn prepare(format: Box<dyn FormatReader>) -> Result<OpenedFile, String> {
         // Find a default track to read from
-        let track = format.default_track().unwrap().clone();
+        let track = format.default_track().ok_or("no default track")?;
 
         // Our decoder
-        let mut decoder =
-            symphonia::default::get_codecs().make(&track.codec_params, &Default::default()).unwrap();
+        let decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &Default::default())
+            .map_err(|e| e.to_string())?;
+        let track_id = track.id;
 
-        let timebase = track.codec_params.time_base.unwrap();
-        let duration_timestamp = track.codec_params.n_frames.map(|frames| track.codec_params.start_ts + frames).unwrap();
-        let duration_time = timebase.calc_time(duration_timestamp);
-        let duration = Duration::from_secs(duration_time.seconds) + Duration::from_secs_f64(duration_time.frac);
+        Ok((format, decoder, track_id))
+    }
+
+    /// Starts decoding the sound in a background thread, returning the
+    /// stream its samples can be read from
+    pub fn stream(&self) -> Result<SoundStream, String> {
+        let source = Source::open(self)?;
+
+        let capacity = (BUFFERED_DURATION.as_secs_f32() * (self.sample_rate as usize * self.channels) as f32) as usize;
+        let (producer, consumer) = RingBuffer::<f32>::new(capacity).split();
+        let (commands, requests) = mpsc::channel();
+        let state = Arc::new(StreamState::new());
+
+        let inner_state = state.clone();
+        thread::spawn(move || SoundStream::decode(source, producer, &inner_state, requests));
+
+        Ok(SoundStream {
+            consumer,
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            state,
+            commands
+        })
+    }
+
+    /// Decodes the whole sound as fast as possible, handing its interleaved
+    /// samples to `on_samples` as they come
+    pub fn decode<F: FnMut(&[f32])>(&self, mut on_samples: F) -> Result<(), String> {
+        let mut source = Source::open(self)?;
+        while let Some(samples) = source.next_samples() {
+            on_samples(samples);
+        }
+        Ok(())
+    }
 
-        let mut sound = Sound {
-            samples: vec!(),
-            sample_rate: track.codec_params.sample_rate.unwrap(),
-            channels: track.codec_params.channels.unwrap().count(),
+    /// Opens the sound for decoding on the calling thread, as opposed to
+    /// `stream` which decodes it in the background
+    pub fn decoder(&self) -> Result<SoundDecoder, String> {
+        Ok(SoundDecoder {
+            source: Source::open(self)?,
+            pending: vec![],
+            offset: 0
+        })
+    }
 
-            duration
-        };
+    pub fn path(&self) -> &Path { &self.path }
+    pub fn sample_rate(&self) -> u32 { self.sample_rate }
+    pub fn channel_count(&self) -> usize { self.channels }
+    pub fn duration(&self) -> Duration { self.duration }
+    pub fn metadata(&self) -> &SoundMetadata { &self.metadata }
 
-        // The sample buffer needs information that we get after decoding at least
-        // one packet, so we instantiate it later
-        let mut sample_buffer = None;
-        while let Ok(packet) = format.next_packet() {
+    /// Title of the sound, or the name of its file if it is not tagged
+    pub fn title(&self) -> String {
+        self.metadata.title.clone().unwrap_or_else(|| {
+            self.path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default()
+        })
+    }
+}
+
+#[cfg(test)]
+impl Sound {
+    /// A sound without any file behind it, for tests which only shuffle
+    /// sounds around
+    pub(crate) fn placeholder() -> Self {
+        Sound {
+            path: PathBuf::new(),
+            sample_rate: 44100,
+            channels: 2,
+            duration: Duration::ZERO,
+            metadata: SoundMetadata::default()
+        }
+    }
+}
+
+/// What the tags of a sound tell about it, `None` for what they leave out
+#[derive(Clone, Default)]
+pub struct SoundMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+    /// ReplayGain adjustment of the track, in dB
+    pub track_gain: Option<f32>,
+    /// ReplayGain peak of the track, 1 being full scale
+    pub track_peak: Option<f32>,
+    /// ReplayGain adjustment of the album, in dB
+    pub album_gain: Option<f32>,
+    /// ReplayGain peak of the album, 1 being full scale
+    pub album_peak: Option<f32>,
+    /// Front cover, or the first picture if none is marked as such
+    pub cover: Option<Arc<Texture>>
+}
+impl SoundMetadata {
+    /// Reads every revision of some metadata, oldest first, each of them
+    /// replacing what the previous ones said
+    fn read(&mut self, mut metadata: Metadata) {
+        while let Some(revision) = metadata.pop() {
+            self.read_revision(&revision);
+        }
+        if let Some(revision) = metadata.current() {
+            self.read_revision(revision);
+        }
+    }
+
+    fn read_revision(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            // Some formats pad strings with null characters
+            let value = tag.value.to_string().trim_matches(|c: char| c.is_whitespace() || c == '\0').to_string();
+            if value.is_empty() { continue }
+            // Gains are written as "-6.5 dB", and track numbers as "3/12"
+            let number = value.split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or_default();
+            match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => self.title = Some(value),
+                Some(StandardTagKey::Artist) => self.artist = Some(value),
+                Some(StandardTagKey::Album) => self.album = Some(value),
+                Some(StandardTagKey::TrackNumber) => self.track_number = number.parse().ok().or(self.track_number),
+                Some(StandardTagKey::ReplayGainTrackGain) => self.track_gain = number.parse().ok().or(self.track_gain),
+                Some(StandardTagKey::ReplayGainTrackPeak) => self.track_peak = number.parse().ok().or(self.track_peak),
+                Some(StandardTagKey::ReplayGainAlbumGain) => self.album_gain = number.parse().ok().or(self.album_gain),
+                Some(StandardTagKey::ReplayGainAlbumPeak) => self.album_peak = number.parse().ok().or(self.album_peak),
+                _ => ()
+            }
+        }
+
+        let visuals = revision.visuals();
+        let cover = visuals.iter()
+            .find(|v| v.usage == Some(StandardVisualKey::FrontCover))
+            .or_else(|| visuals.first());
+        // Pictures in formats we cannot decode are left out
+        if let Some(texture) = cover.and_then(|v| Texture::decode(&v.data).ok()) {
+            self.cover = Some(Arc::new(texture));
+        }
+    }
+}
+
+/// A sound opened for decoding, owned by the streaming thread
+struct Source {
+    sound: Sound,
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    time_base: TimeBase,
+
+    // The sample buffer needs information that we get after decoding at least
+    // one packet, so we instantiate it later
+    sample_buffer: Option<SampleBuffer<f32>>,
+    // Seeking is only precise to the packet, samples decoded before this
+    // timestamp are dropped
+    skip_until: TimeStamp
+}
+impl Source {
+    fn open(sound: &Sound) -> Result<Self, String> {
+        let (format, decoder, track_id) = Sound::open(&sound.path)?;
+        let time_base = format.tracks().iter()
+            .find(|t| t.id == track_id)
+            .and_then(|t| t.codec_params.time_base)
+            .ok_or("unknown time base")?;
+
+        Ok(Source {
+            sound: sound.clone(),
+            format,
+            decoder,
+            track_id,
+            time_base,
+
+            sample_buffer: None,
+            skip_until: 0
+        })
+    }
+
+    /// Number of samples between two timestamps
+    fn samples_between(&self, from: TimeStamp, to: TimeStamp) -> usize {
+        let time = self.time_base.calc_time(to.saturating_sub(from));
+        let frames = ((time.seconds as f64 + time.frac) * self.sound.sample_rate as f64) as usize;
+        frames * self.sound.channels
+    }
+
+    /// Seeks to the given time, returning the index of the sample playback
+    /// should resume from
+    fn seek(&mut self, time: Duration) -> Option<usize> {
+        let seeked = self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time { time: Time::from(time.as_secs_f64()), track_id: Some(self.track_id) }
+        ).ok()?;
+        self.decoder.reset();
+        self.skip_until = seeked.required_ts;
+        Some(self.samples_between(0, seeked.required_ts))
+    }
+
+    /// Decodes the next packet of our track, returning its interleaved samples.
+    ///
+    /// Returns `None` once the end of the file has been reached.
+    fn next_samples(&mut self) -> Option<&[f32]> {
+        loop {
+            let packet = self.format.next_packet().ok()?;
             // Skip the packet if it does not belong to our track
-            if packet.track_id() != track.id { continue }
+            if packet.track_id() != self.track_id { continue }
+
+            let skipped = self.samples_between(packet.ts(), self.skip_until);
 
             // Actual decoding
-            if let Ok(audio_buffer) = decoder.decode(&packet) {
-                // cpal (our audio playback backend) needs the samples in an interleaved
-                // format, which is why we need to copy the contents of the buffer to
-                // a sample buffer.
-                if sample_buffer.is_none() {
-                    let specification = *audio_buffer.spec();
-                    let duration = audio_buffer.capacity() as u64;
-                    sample_buffer = Some(SampleBuffer::<f32>::new(duration, specification));
+            let audio_buffer = match self.decoder.decode(&packet) {
+                Ok(audio_buffer) => audio_buffer,
+                Err(_) => continue
+            };
+            // cpal (our audio playback backend) needs the samples in an interleaved
+            // format, which is why we need to copy the contents of the buffer to
+            // a sample buffer.
+            if self.sample_buffer.is_none() {
+                let specification = *audio_buffer.spec();
+                let duration = audio_buffer.capacity() as u64;
+                self.sample_buffer = Some(SampleBuffer::<f32>::new(duration, specification));
+            }
+
+            let buffer = self.sample_buffer.as_mut().unwrap();
+            buffer.copy_interleaved_ref(audio_buffer);
+            let samples = buffer.samples();
+            return Some(&samples[skipped.min(samples.len())..])
+        }
+    }
+}
+
+/// Decodes a sound on demand, for when samples are needed at our own pace
+/// rather than at the pace of playback
+pub struct SoundDecoder {
+    source: Source,
+    /// Samples of the last decoded packet which were not read yet
+    pending: Vec<f32>,
+    offset: usize
+}
+impl SoundDecoder {
+    /// Fills the buffer with the next interleaved samples, returning how many
+    /// were written, which is less than asked once the end of the sound is reached
+    pub fn read(&mut self, data: &mut [f32]) -> usize {
+        let mut written = 0;
+        while written < data.len() {
+            if self.offset == self.pending.len() {
+                self.pending.clear();
+                self.offset = 0;
+                match self.source.next_samples() {
+                    Some(samples) => self.pending.extend_from_slice(samples),
+                    None => break
+                }
+            }
+
+            let count = (data.len() - written).min(self.pending.len() - self.offset);
+            data[written..written + count].copy_from_slice(&self.pending[self.offset..self.offset + count]);
+            written += count;
+            self.offset += count;
+        }
+        written
+    }
+}
+
+/// Requests sent to the streaming thread
+enum StreamRequest {
+    /// Moves to a given time in the current sound
+    Seek(Duration),
+    /// Replaces the current sound right away
+    Play(Sound),
+    /// Sets the sound to play once the current one ends
+    Queue(Sound)
+}
+
+/// State shared between a `SoundStream`, its handles and its decoding thread
+struct StreamState {
+    stopped: AtomicBool,
+    finished: AtomicBool,
+    /// Set by the decoding thread after seeking, until the consumer has thrown
+    /// away every sample decoded before the seek
+    flushing: AtomicBool,
+    /// Sample at which playback resumes once flushed
+    seek_target: AtomicU64,
+    /// Number of samples pushed to the ring buffer so far
+    pushed: AtomicU64,
+    /// Number of samples read from the ring buffer so far
+    read: AtomicU64,
+    /// Value of `pushed` when the decoding thread moved on to a queued sound,
+    /// `u64::MAX` if it has not
+    boundary: AtomicU64,
+    /// Number of queued sounds whose playback started
+    sounds_started: AtomicU64,
+    /// Number of samples of the current sound read from the stream so far
+    position: AtomicU64
+}
+impl StreamState {
+    fn new() -> Self {
+        StreamState {
+            stopped: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            flushing: AtomicBool::new(false),
+            seek_target: AtomicU64::new(0),
+            pushed: AtomicU64::new(0),
+            read: AtomicU64::new(0),
+            boundary: AtomicU64::new(u64::MAX),
+            sounds_started: AtomicU64::new(0),
+            position: AtomicU64::new(0)
+        }
+    }
+}
+
+/// Interleaved samples of one or several `Sound`s, decoded ahead of time by a
+/// background thread into a lock-free ring buffer.
+///
+/// Reading from the stream never blocks, which makes it safe to use from
+/// within an audio callback. Dropping it stops the decoding thread.
+pub struct SoundStream {
+    consumer: Consumer<f32>,
+    sample_rate: u32,
+    channels: usize,
+
+    state: Arc<StreamState>,
+    commands: Sender<StreamRequest>
+}
+impl SoundStream {
+    fn decode(
+        mut source: Source,
+        mut producer: Producer<f32>,
+        state: &StreamState,
+        requests: Receiver<StreamRequest>
+    ) {
+        let mut queued: Option<Sound> = None;
+        let mut request = None;
+        loop {
+            if state.stopped.load(Ordering::Acquire) { return }
+
+            // Once the whole sound has been decoded, we simply wait for a request
+            // that would have us start over somewhere else
+            if request.is_none() {
+                request = if state.finished.load(Ordering::Acquire) {
+                    match requests.recv_timeout(IDLE_INTERVAL) {
+                        Ok(r) => Some(r),
+                        Err(RecvTimeoutError::Timeout) => continue,
+                        Err(RecvTimeoutError::Disconnected) => return
+                    }
+                } else {
+                    requests.try_recv().ok()
+                };
+            }
+
+            match request.take() {
+                Some(StreamRequest::Seek(time)) => {
+                    if let Some(target) = source.seek(time) {
+                        Self::flush(state, target);
+                    }
+                    continue
+                },
+                Some(StreamRequest::Play(sound)) => {
+                    if let Ok(s) = Source::open(&sound) {
+                        source = s;
+                        queued = None;
+                        Self::flush(state, 0);
+                    }
+                    continue
+                },
+                Some(StreamRequest::Queue(sound)) => {
+                    queued = Some(sound);
+                    // We may have already run out of samples, but the consumer
+                    // might not, in which case the transition can still be seamless
+                    state.finished.store(false, Ordering::Release);
+                    continue
+                },
+                None => ()
+            }
+
+            // Nothing we decode can be pushed until the consumer gets rid of
+            // the samples preceding the seek
+            if state.flushing.load(Ordering::Acquire) {
+                thread::sleep(REFILL_INTERVAL);
+                continue
+            }
+
+            let mut samples = match source.next_samples() {
+                Some(samples) => samples,
+                None => {
+                    // Chain the queued sound right after this one. This can only be
+                    // done if the output does not have to be reconfigured for it.
+                    let next = queued.take()
+                        .filter(|q| q.sample_rate == source.sound.sample_rate && q.channels == source.sound.channels)
+                        .and_then(|q| Source::open(&q).ok());
+                    match next {
+                        Some(next) => {
+                            source = next;
+                            state.boundary.store(state.pushed.load(Ordering::Acquire), Ordering::Release);
+                        },
+                        None => state.finished.store(true, Ordering::Release)
+                    }
+                    continue
                 }
+            };
 
-                if let Some(buffer) = &mut sample_buffer {
-                    buffer.copy_interleaved_ref(audio_buffer);
-                    let samples: Vec<f32> = buffer
-                        .samples()
-                        .to_vec();
-                    sound.samples.extend_from_slice(&samples);
+            // Wait for the consumer to make some room if the ring buffer is full
+            while !samples.is_empty() {
+                if state.stopped.load(Ordering::Acquire) { return }
+                let pushed = producer.push_slice(samples);
+                state.pushed.fetch_add(pushed as u64, Ordering::AcqRel);
+                samples = &samples[pushed..];
+                if !samples.is_empty() {
+                    // There is no point in finishing this packet if we are
+                    // going to move away from it
+                    if let Ok(r) = requests.try_recv() {
+                        request = Some(r);
+                        break
+                    }
+                    thread::sleep(REFILL_INTERVAL)
                 }
             }
         }
+    }
 
-        sound
+    /// Asks the consumer to throw away every sample already decoded, and to
+    /// resume playback at the given sample
+    fn flush(state: &StreamState, target: usize) {
+        state.seek_target.store(target as u64, Ordering::Release);
+        state.flushing.store(true, Ordering::Release);
+        state.finished.store(false, Ordering::Release);
     }
 
-    pub fn samples(&self) -> Vec<f32> { self.samples.clone() }
-    pub fn sample_rate(&self) -> u32 { self.sample_rate }
-    pub fn channel_count(&self) -> usize { self.channels }
-    pub fn duration(&self) -> Duration { self.duration }
-}
\ No newline at end of file
+    /// Fills the given buffer with as many samples as are available,
+    /// returning how many were written
+    pub fn read(&mut self, data: &mut [f32]) -> usize {
+        if self.state.flushing.load(Ordering::Acquire) {
+            let discarded = self.consumer.discard(self.consumer.len());
+            self.state.read.fetch_add(discarded as u64, Ordering::AcqRel);
+            self.state.boundary.store(u64::MAX, Ordering::Release);
+            self.state.position.store(self.state.seek_target.load(Ordering::Acquire), Ordering::Release);
+            self.state.flushing.store(false, Ordering::Release);
+        }
+
+        let read = self.consumer.pop_slice(data) as u64;
+        let total = self.state.read.fetch_add(read, Ordering::AcqRel) + read;
+        let boundary = self.state.boundary.load(Ordering::Acquire);
+        if total >= boundary {
+            // We just crossed over to a queued sound
+            self.state.position.store(total - boundary, Ordering::Release);
+            self.state.boundary.store(u64::MAX, Ordering::Release);
+            self.state.sounds_started.fetch_add(1, Ordering::AcqRel);
+        } else {
+            self.state.position.fetch_add(read, Ordering::AcqRel);
+        }
+        read as usize
+    }
+
+    /// Returns a handle that can control the stream from another thread
+    pub fn handle(&self) -> SoundStreamHandle {
+        SoundStreamHandle {
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            state: self.state.clone(),
+            commands: self.commands.clone()
+        }
+    }
+}
+impl Drop for SoundStream {
+    fn drop(&mut self) {
+        self.state.stopped.store(true, Ordering::Release);
+    }
+}
+
+/// A handle to a `SoundStream`, used to control it while it is being read by
+/// an audio callback
+#[derive(Clone)]
+pub struct SoundStreamHandle {
+    sample_rate: u32,
+    channels: usize,
+    state: Arc<StreamState>,
+    commands: Sender<StreamRequest>
+}
+impl SoundStreamHandle {
+    /// Moves the stream to the given time.
+    ///
+    /// Samples already decoded are thrown away by the next read, after which
+    /// the stream continues from the requested position.
+    pub fn seek(&self, time: Duration) {
+        let frame = (time.as_secs_f64() * self.sample_rate as f64) as u64;
+        self.state.position.store(frame * self.channels as u64, Ordering::Release);
+        self.commands.send(StreamRequest::Seek(time)).ok();
+    }
+
+    /// Whether a sound can be played by this stream without reconfiguring
+    /// the output
+    pub fn accepts(&self, sound: &Sound) -> bool {
+        sound.sample_rate == self.sample_rate && sound.channels == self.channels
+    }
+
+    /// Replaces the sound being streamed right away
+    pub fn play(&self, sound: &Sound) {
+        self.state.position.store(0, Ordering::Release);
+        self.commands.send(StreamRequest::Play(sound.clone())).ok();
+    }
+
+    /// Sets the sound to stream once the current one ends, without any gap
+    /// between the two if they share the same sample rate and channel count.
+    ///
+    /// Each call replaces the previously queued sound.
+    pub fn queue(&self, sound: &Sound) {
+        self.commands.send(StreamRequest::Queue(sound.clone())).ok();
+    }
+
+    /// Number of queued sounds that started playing since the stream was
+    /// created
+    pub fn sounds_started(&self) -> u64 {
+        self.state.sounds_started.load(Ordering::Acquire)
+    }
+
+    /// Whether every sample the stream will ever produce has been read
+    pub fn finished(&self) -> bool {
+        self.state.finished.load(Ordering::Acquire)
+            && self.state.read.load(Ordering::Acquire) == self.state.pushed.load(Ordering::Acquire)
+    }
+
+    /// Time of the last sample read from the stream
+    pub fn position(&self) -> Duration {
+        let frames = self.state.position.load(Ordering::Acquire) / self.channels as u64;
+        Duration::from_secs_f64(frames as f64 / self.sample_rate as f64)
+    }
+}
@@ -0,0 +1,330 @@
+use std::fs;
+use std::sync::Arc;
+use glm::{identity, rotate_z, scale, TMat3, TMat4, translate, TVec2, TVec3, vec2, vec3, vec4};
+use lyon::math::{point, Point};
+use lyon::path::Path;
+use lyon::tessellation::{
+    BuffersBuilder, FillOptions, FillRule, FillTessellator, FillVertex, LineCap, LineJoin,
+    StrokeOptions, StrokeTessellator, StrokeVertex, VertexBuffers
+};
+use usvg::{NodeExt, NodeKind, PathSegment, Transform, Tree, Units, Visibility};
+use crate::graphics::{MaterialParameters, OverlayVertex};
+use crate::graphics::canvas::{add_painted, Paint, Spread, Stop};
+use crate::graphics::mesh::Mesh;
+use crate::graphics::rendering_system::Render;
+use crate::resource_pool::ResourcePool;
+
+/// Largest distance between curves and the segments approximating them, as a
+/// share of the height of the drawing
+const TOLERANCE: f32 = 0.001;
+
+/// Copies of a drawing share its mesh, which is only uploaded once
+impl ResourcePool<Svg> {
+    pub fn load(&mut self, resource_id: &str, file_path: &str) -> Result<(), String> {
+        let svg = Svg::load(file_path)?;
+        self.0.insert(String::from(resource_id), svg);
+        Ok(())
+    }
+}
+
+/// The fills and strokes of an SVG file, drawn over the frame
+///
+/// The view box of the file is centered on the origin and one unit high, so
+/// that drawings can be scaled regardless of the size they were made at.
+/// Gradients are evaluated like those of the canvas, while patterns, images
+/// and text are left out.
+#[derive(Clone)]
+pub struct Svg {
+    mesh: Arc<Mesh<OverlayVertex>>,
+    translation: TMat4<f32>,
+    rotation: TMat4<f32>,
+    scale: TMat4<f32>,
+    parameters: MaterialParameters
+}
+
+impl Svg {
+    pub fn load(file_path: &str) -> Result<Self, String> {
+        let data = fs::read(file_path).map_err(|e| format!("{}: {}", file_path, e))?;
+        let tree = Tree::from_data(&data, &usvg::Options::default().to_ref())
+            .map_err(|e| format!("{}: {}", file_path, e))?;
+        let view_box = tree.svg_node().view_box.rect;
+        let height = view_box.height() as f32;
+
+        let mut tessellator = Tessellator {
+            tree: &tree,
+            tolerance: TOLERANCE * height,
+            geometry: VertexBuffers::new()
+        };
+        tessellator.add_node(&tree.root());
+
+        let center = vec2(view_box.x() as f32, view_box.y() as f32) + vec2(view_box.width() as f32, height) / 2.0;
+        let mut vertices = tessellator.geometry.vertices;
+        for vertex in &mut vertices {
+            vertex.position = ((TVec2::from(vertex.position) - center) / height).into();
+        }
+
+        Ok(Svg {
+            mesh: Arc::new(Mesh::indexed(vertices, tessellator.geometry.indices)),
+            translation: identity(),
+            rotation: identity(),
+            scale: identity(),
+            parameters: Default::default()
+        })
+    }
+
+    pub fn rotate(&mut self, radians: f32) -> &mut Self {
+        self.rotation = rotate_z(&self.rotation, radians);
+        self
+    }
+
+    pub fn translate(&mut self, v: TVec2<f32>) -> &mut Self {
+        self.translation = translate(&self.translation, &vec3(v.x, v.y, 0.0));
+        self
+    }
+
+    pub fn set_position(&mut self, v: TVec2<f32>) -> &mut Self {
+        self.translation = translate(&identity(), &vec3(v.x, v.y, 0.0));
+        self
+    }
+
+    /// Return the drawing's rotation to 0
+    pub fn zero_rotation(&mut self) -> &mut Self {
+        self.rotation = identity();
+        self
+    }
+
+    pub fn scale(&mut self, v: TVec2<f32>) -> &mut Self {
+        self.scale = scale(&self.scale, &vec3(v.x, v.y, 1.0));
+        self
+    }
+
+    pub fn reset_scaling(&mut self) -> &mut Self {
+        self.scale = identity();
+        self
+    }
+
+    /// Multiplies the colors of the drawing
+    pub fn set_color(&mut self, color: TVec3<f32>) -> &mut Self {
+        self.parameters.color = color.into();
+        self
+    }
+
+    /// Sets how opaque the drawing is, in [0, 1]
+    pub fn set_opacity(&mut self, opacity: f32) -> &mut Self {
+        self.parameters.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn get_scale(&self) -> TVec2<f32> {
+        vec2(self.scale.m11, self.scale.m22)
+    }
+    pub fn get_position(&self) -> TVec2<f32> { vec2(self.translation.m14, self.translation.m24) }
+}
+impl Render<OverlayVertex> for Svg {
+    fn mesh(&self) -> &Mesh<OverlayVertex> {
+        &self.mesh
+    }
+
+    fn model_matrices(&self) -> (TMat4<f32>, TMat4<f32>) {
+        (self.translation * self.rotation * self.scale, identity())
+    }
+
+    fn parameters(&self) -> MaterialParameters {
+        self.parameters
+    }
+}
+
+/// Turns the paths of an SVG tree into triangles, in the units of its view box
+struct Tessellator<'a> {
+    tree: &'a Tree,
+    tolerance: f32,
+    geometry: VertexBuffers<OverlayVertex, u32>
+}
+impl Tessellator<'_> {
+    /// Adds the paths under a node, in the order they are painted. Definitions
+    /// are skipped, as they are only painted where they are used
+    fn add_node(&mut self, node: &usvg::Node) {
+        match *node.borrow() {
+            NodeKind::Path(ref path) if path.visibility == Visibility::Visible => self.add_path(path, node.abs_transform()),
+            NodeKind::Svg(_) | NodeKind::Group(_) => {
+                for child in node.children() {
+                    self.add_node(&child);
+                }
+            },
+            _ => ()
+        }
+    }
+
+    fn add_path(&mut self, path: &usvg::Path, transform: Transform) {
+        let lyon_path = to_lyon(&path.data, &transform);
+
+        if let Some(fill) = &path.fill {
+            if let Some((paint, to_paint)) = self.paint(&fill.paint, path, &transform) {
+                let rule = match fill.rule {
+                    usvg::FillRule::NonZero => FillRule::NonZero,
+                    usvg::FillRule::EvenOdd => FillRule::EvenOdd
+                };
+                let options = FillOptions::tolerance(self.tolerance).with_fill_rule(rule);
+                let mut shape = VertexBuffers::new();
+                // Paths that cannot be tessellated are left out
+                let _ = FillTessellator::new().tessellate_path(
+                    &lyon_path,
+                    &options,
+                    &mut BuffersBuilder::new(&mut shape, |v: FillVertex| v.position())
+                );
+                add_painted(&mut self.geometry, &shape, &paint, fill.opacity.value() as f32, to_paint);
+            }
+        }
+
+        if let Some(stroke) = &path.stroke {
+            if let Some((paint, to_paint)) = self.paint(&stroke.paint, path, &transform) {
+                // Strokes are widened as much as the transform scales areas
+                let scale = (transform.a * transform.d - transform.b * transform.c).abs().sqrt();
+                let options = StrokeOptions::tolerance(self.tolerance)
+                    .with_line_width((stroke.width.value() * scale) as f32)
+                    .with_miter_limit(stroke.miterlimit.value() as f32)
+                    .with_line_cap(match stroke.linecap {
+                        usvg::LineCap::Butt => LineCap::Butt,
+                        usvg::LineCap::Round => LineCap::Round,
+                        usvg::LineCap::Square => LineCap::Square
+                    })
+                    .with_line_join(match stroke.linejoin {
+                        usvg::LineJoin::Miter => LineJoin::Miter,
+                        usvg::LineJoin::Round => LineJoin::Round,
+                        usvg::LineJoin::Bevel => LineJoin::Bevel
+                    });
+                let mut shape = VertexBuffers::new();
+                let _ = StrokeTessellator::new().tessellate_path(
+                    &lyon_path,
+                    &options,
+                    &mut BuffersBuilder::new(&mut shape, |v: StrokeVertex| v.position())
+                );
+                add_painted(&mut self.geometry, &shape, &paint, stroke.opacity.value() as f32, to_paint);
+            }
+        }
+    }
+
+    /// Returns a paint in linear colors if it can be drawn, along with what
+    /// takes points of the view box to where the paint is evaluated
+    fn paint(
+        &self,
+        paint: &usvg::Paint,
+        path: &usvg::Path,
+        transform: &Transform
+    ) -> Option<(Paint, impl Fn(Point) -> TVec2<f32>)> {
+        let (paint, paint_transform) = match paint {
+            usvg::Paint::Color(color) => {
+                let [r, g, b] = [color.red, color.green, color.blue].map(linear);
+                (Paint::Solid(vec4(r, g, b, 1.0)), Transform::default())
+            },
+            usvg::Paint::Link(id) => {
+                let node = self.tree.defs_by_id(id)?;
+                let kind = node.borrow();
+                let (paint, base) = match &*kind {
+                    NodeKind::LinearGradient(gradient) => (Paint::Linear {
+                        start: vec2(gradient.x1 as f32, gradient.y1 as f32),
+                        end: vec2(gradient.x2 as f32, gradient.y2 as f32),
+                        stops: stops(&gradient.stops),
+                        spread: spread(gradient.spread_method)
+                    }, &gradient.base),
+                    NodeKind::RadialGradient(gradient) => (Paint::Radial {
+                        center: vec2(gradient.cx as f32, gradient.cy as f32),
+                        focal: vec2(gradient.fx as f32, gradient.fy as f32),
+                        radius: gradient.r.value() as f32,
+                        stops: stops(&gradient.stops),
+                        spread: spread(gradient.spread_method)
+                    }, &gradient.base),
+                    _ => return None
+                };
+                if base.stops.is_empty() {
+                    return None
+                }
+
+                // Gradients are placed in the space of the path, relative to
+                // its bounding box unless said otherwise
+                let mut paint_transform = *transform;
+                if base.units == Units::ObjectBoundingBox {
+                    let bbox = path.data.bbox()?;
+                    if bbox.width() <= 0.0 || bbox.height() <= 0.0 {
+                        return None
+                    }
+                    paint_transform.append(&Transform::new(bbox.width(), 0.0, 0.0, bbox.height(), bbox.x(), bbox.y()));
+                }
+                paint_transform.append(&base.transform);
+                (paint, paint_transform)
+            }
+        };
+        let Transform { a, b, c, d, e, f } = paint_transform;
+        let to_paint = TMat3::new(
+            a as f32, c as f32, e as f32,
+            b as f32, d as f32, f as f32,
+            0.0, 0.0, 1.0
+        ).try_inverse()?;
+        Some((paint, move |p: Point| (to_paint * vec3(p.x, p.y, 1.0)).xy()))
+    }
+}
+
+/// Converts the stops of a gradient to linear colors
+fn stops(stops: &[usvg::Stop]) -> Vec<Stop> {
+    stops.iter().map(|s| {
+        let [r, g, b] = [s.color.red, s.color.green, s.color.blue].map(linear);
+        Stop { offset: s.offset.value() as f32, color: vec4(r, g, b, s.opacity.value() as f32) }
+    }).collect()
+}
+
+fn spread(spread: usvg::SpreadMethod) -> Spread {
+    match spread {
+        usvg::SpreadMethod::Pad => Spread::Pad,
+        usvg::SpreadMethod::Reflect => Spread::Reflect,
+        usvg::SpreadMethod::Repeat => Spread::Repeat
+    }
+}
+
+/// Converts the segments of a path to lyon, transformed into the coordinates
+/// of the view box
+fn to_lyon(data: &usvg::PathData, transform: &Transform) -> Path {
+    let at = |x: f64, y: f64| {
+        let (x, y) = transform.apply(x, y);
+        point(x as f32, y as f32)
+    };
+    let mut builder = Path::builder();
+    let mut open = false;
+    // Subpaths drawn on after being closed start again where they started
+    let mut start = point(0.0, 0.0);
+    for segment in data.0.iter() {
+        if !open && !matches!(segment, PathSegment::MoveTo { .. } | PathSegment::ClosePath) {
+            builder.begin(start);
+            open = true;
+        }
+        match *segment {
+            PathSegment::MoveTo { x, y } => {
+                if open {
+                    builder.end(false);
+                }
+                start = at(x, y);
+                builder.begin(start);
+                open = true;
+            },
+            PathSegment::LineTo { x, y } => { builder.line_to(at(x, y)); },
+            PathSegment::CurveTo { x1, y1, x2, y2, x, y } => {
+                builder.cubic_bezier_to(at(x1, y1), at(x2, y2), at(x, y));
+            },
+            PathSegment::ClosePath => {
+                if open {
+                    builder.end(true);
+                }
+                open = false;
+            }
+        }
+    }
+    if open {
+        builder.end(false);
+    }
+    builder.build()
+}
+
+/// Converts an sRGB component to linear light
+fn linear(component: u8) -> f32 {
+    let c = component as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
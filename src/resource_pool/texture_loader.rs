@@ -0,0 +1,46 @@
+use std::fs;
+use std::sync::Arc;
+use image::{imageops, ImageBuffer, Rgba};
+use image::imageops::FilterType;
+use crate::resource_pool::ResourcePool;
+
+/// Copies of a texture share its pixels, which are only uploaded once
+impl ResourcePool<Arc<Texture>> {
+    pub fn load(&mut self, resource_id: &str, file_path: &str) -> Result<(), String> {
+        let texture = Texture::load(file_path)?;
+        self.0.insert(String::from(resource_id), Arc::new(texture));
+        Ok(())
+    }
+}
+
+/// An image in 8 bit RGBA, its rows going from top to bottom
+#[derive(Clone)]
+pub struct Texture {
+    pub dimensions: [u32; 2],
+    pub pixels: Vec<u8>
+}
+impl Texture {
+    /// Loads a PNG or JPEG file
+    pub fn load(file_path: &str) -> Result<Self, String> {
+        let data = fs::read(file_path).map_err(|e| format!("{}: {}", file_path, e))?;
+        Self::decode(&data).map_err(|e| format!("{}: {}", file_path, e))
+    }
+
+    /// Decodes a PNG or JPEG image
+    pub fn decode(data: &[u8]) -> Result<Self, String> {
+        let image = image::load_from_memory(data).map_err(|e| e.to_string())?.into_rgba8();
+        Ok(Texture { dimensions: [image.width(), image.height()], pixels: image.into_raw() })
+    }
+
+    /// Returns a copy of the texture scaled down to fit in a square of the
+    /// given size, keeping its aspect ratio
+    pub fn fitted(&self, max_dimension: u32) -> Self {
+        let [width, height] = self.dimensions;
+        let scale = max_dimension as f32 / width.max(height) as f32;
+        let dimensions = [width, height].map(|d| ((d as f32 * scale).round() as u32).clamp(1, max_dimension));
+        let image = ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, &self.pixels[..])
+            .expect("pixels should match the dimensions");
+        let scaled = imageops::resize(&image, dimensions[0], dimensions[1], FilterType::Triangle);
+        Texture { dimensions, pixels: scaled.into_raw() }
+    }
+}
@@ -0,0 +1,496 @@
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Duration;
+use glm::{TVec2, TVec3, vec2, vec3};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, FLOAT, INT};
+use crate::audio::{GeneralAttributes, RealtimeAttributes};
+use crate::audio::analysis::TrackAnalysis;
+use crate::audio::signal_processing::{Aggregation, BandMapper, BandScale, Spectrum};
+use crate::graphics::{AmbientLight, DirectionalLight};
+use crate::graphics::camera::{Camera, Projection};
+use crate::graphics::canvas::Canvas;
+use crate::graphics::text::{Align, Text};
+use crate::resource_pool::font_loader::Font;
+use crate::resource_pool::instanced_model::{Instance, InstancedModel};
+use crate::resource_pool::model_loader::Model;
+use crate::resource_pool::svg_loader::Svg;
+use crate::resource_pool::texture_loader::Texture;
+
+type Vec2 = TVec2<f32>;
+type Vec3 = TVec3<f32>;
+
+/// Files a script used while being run
+#[derive(Default)]
+pub struct Loaded {
+    /// Every file, models and shaders included
+    pub files: Vec<PathBuf>,
+    pub materials: Vec<PathBuf>,
+    pub effects: Vec<PathBuf>
+}
+
+/// Creates an engine exposing everything a scene needs, files being loaded
+/// relative to the given directory and kept track of in `loaded`
+pub fn engine(directory: PathBuf, loaded: Rc<RefCell<Loaded>>) -> Engine {
+    let mut engine = Engine::new();
+    register_vectors(&mut engine);
+    register_models(&mut engine, directory.clone(), loaded.clone());
+    register_instances(&mut engine, directory.clone(), loaded.clone());
+    register_textures(&mut engine, directory.clone(), loaded.clone());
+    register_svgs(&mut engine, directory.clone(), loaded.clone());
+    register_canvas(&mut engine);
+    register_texts(&mut engine, directory.clone(), loaded.clone());
+    register_shaders(&mut engine, directory, loaded);
+    register_camera(&mut engine);
+    register_lights(&mut engine);
+    register_attributes(&mut engine);
+    register_bands(&mut engine);
+    engine
+}
+
+fn to_array<T: Into<Dynamic> + Copy>(values: &[T]) -> Array {
+    values.iter().map(|&v| v.into()).collect()
+}
+
+/// Times before the start of tracks are clamped to it
+fn seconds(seconds: FLOAT) -> Result<Duration, Box<EvalAltResult>> {
+    Duration::try_from_secs_f32(seconds.max(0.0))
+        .map_err(|_| format!("{} is not a valid time", seconds).into())
+}
+
+fn register_vectors(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<Vec2>("Vec2")
+        .register_fn("vec2", |x: FLOAT, y: FLOAT| vec2(x, y))
+        .register_get_set("x", |v: &mut Vec2| v.x, |v: &mut Vec2, x: FLOAT| v.x = x)
+        .register_get_set("y", |v: &mut Vec2| v.y, |v: &mut Vec2, y: FLOAT| v.y = y)
+        .register_fn("+", |a: Vec2, b: Vec2| a + b)
+        .register_fn("-", |a: Vec2, b: Vec2| a - b)
+        .register_fn("*", |v: Vec2, k: FLOAT| v * k)
+        .register_fn("*", |k: FLOAT, v: Vec2| v * k)
+        .register_fn("length", |v: &mut Vec2| v.norm())
+        .register_fn("normalize", |v: &mut Vec2| v.normalize())
+        .register_fn("to_string", |v: &mut Vec2| format!("vec2({}, {})", v.x, v.y))
+        .register_fn("to_debug", |v: &mut Vec2| format!("vec2({}, {})", v.x, v.y));
+    engine
+        .register_type_with_name::<Vec3>("Vec3")
+        .register_fn("vec3", |x: FLOAT, y: FLOAT, z: FLOAT| vec3(x, y, z))
+        .register_get_set("x", |v: &mut Vec3| v.x, |v: &mut Vec3, x: FLOAT| v.x = x)
+        .register_get_set("y", |v: &mut Vec3| v.y, |v: &mut Vec3, y: FLOAT| v.y = y)
+        .register_get_set("z", |v: &mut Vec3| v.z, |v: &mut Vec3, z: FLOAT| v.z = z)
+        .register_fn("+", |a: Vec3, b: Vec3| a + b)
+        .register_fn("-", |a: Vec3, b: Vec3| a - b)
+        .register_fn("*", |v: Vec3, k: FLOAT| v * k)
+        .register_fn("*", |k: FLOAT, v: Vec3| v * k)
+        .register_fn("length", |v: &mut Vec3| v.norm())
+        .register_fn("normalize", |v: &mut Vec3| v.normalize())
+        .register_fn("to_string", |v: &mut Vec3| format!("vec3({}, {}, {})", v.x, v.y, v.z))
+        .register_fn("to_debug", |v: &mut Vec3| format!("vec3({}, {}, {})", v.x, v.y, v.z));
+}
+
+fn register_models(engine: &mut Engine, directory: PathBuf, loaded: Rc<RefCell<Loaded>>) {
+    engine
+        .register_type_with_name::<Model>("Model")
+        .register_fn("model", move |path: &str| -> Result<Model, Box<EvalAltResult>> {
+            let path = directory.join(path);
+            let model = super::load_model(&path)?;
+            loaded.borrow_mut().files.push(path);
+            Ok(model)
+        })
+        .register_fn("rotate", |m: &mut Model, radians: FLOAT, axis: Vec3| { m.rotate(radians, axis.normalize()); })
+        .register_fn("translate", |m: &mut Model, v: Vec3| { m.translate(v); })
+        .register_fn("set_position", |m: &mut Model, v: Vec3| { m.set_position(v); })
+        .register_fn("zero_rotation", |m: &mut Model| { m.zero_rotation(); })
+        .register_fn("scale", |m: &mut Model, v: Vec3| { m.scale(v); })
+        .register_fn("reset_scaling", |m: &mut Model| { m.reset_scaling(); })
+        .register_fn("set_color", |m: &mut Model, color: Vec3| { m.set_color(color); })
+        .register_fn("set_emissive", |m: &mut Model, color: Vec3| { m.set_emissive(color); })
+        .register_fn("set_opacity", |m: &mut Model, opacity: FLOAT| { m.set_opacity(opacity); })
+        .register_fn("set_material", |m: &mut Model, material: &str| { m.set_material(Some(material.into())); })
+        .register_fn("reset_material", |m: &mut Model| { m.set_material(None); })
+        .register_fn("set_texture", |m: &mut Model, texture: Arc<Texture>| { m.set_texture(Some(texture)); })
+        .register_fn("reset_texture", |m: &mut Model| { m.set_texture(None); })
+        .register_fn("get_position", |m: &mut Model| m.get_position())
+        .register_fn("get_scale", |m: &mut Model| m.get_scale());
+}
+
+/// Textures come from PNG or JPEG files or the cover art of the track, and
+/// models are drawn with them through `set_texture`
+fn register_textures(engine: &mut Engine, directory: PathBuf, loaded: Rc<RefCell<Loaded>>) {
+    engine
+        .register_type_with_name::<Arc<Texture>>("Texture")
+        .register_fn("texture", move |path: &str| -> Result<Arc<Texture>, Box<EvalAltResult>> {
+            let path = directory.join(path);
+            let texture = super::load_texture(&path)?;
+            loaded.borrow_mut().files.push(path);
+            Ok(Arc::new(texture))
+        })
+        .register_get("width", |t: &mut Arc<Texture>| t.dimensions[0] as INT)
+        .register_get("height", |t: &mut Arc<Texture>| t.dimensions[1] as INT);
+}
+
+/// Drawings are placed over the frame, which spans [-1, 1] vertically
+fn register_svgs(engine: &mut Engine, directory: PathBuf, loaded: Rc<RefCell<Loaded>>) {
+    engine
+        .register_type_with_name::<Svg>("Svg")
+        .register_fn("svg", move |path: &str| -> Result<Svg, Box<EvalAltResult>> {
+            let path = directory.join(path);
+            let svg = super::load_svg(&path)?;
+            loaded.borrow_mut().files.push(path);
+            Ok(svg)
+        })
+        .register_fn("rotate", |s: &mut Svg, radians: FLOAT| { s.rotate(radians); })
+        .register_fn("translate", |s: &mut Svg, v: Vec2| { s.translate(v); })
+        .register_fn("set_position", |s: &mut Svg, v: Vec2| { s.set_position(v); })
+        .register_fn("zero_rotation", |s: &mut Svg| { s.zero_rotation(); })
+        .register_fn("scale", |s: &mut Svg, v: Vec2| { s.scale(v); })
+        .register_fn("scale", |s: &mut Svg, k: FLOAT| { s.scale(vec2(k, k)); })
+        .register_fn("reset_scaling", |s: &mut Svg| { s.reset_scaling(); })
+        .register_fn("set_color", |s: &mut Svg, color: Vec3| { s.set_color(color); })
+        .register_fn("set_opacity", |s: &mut Svg, opacity: FLOAT| { s.set_opacity(opacity); })
+        .register_fn("get_position", |s: &mut Svg| s.get_position())
+        .register_fn("get_scale", |s: &mut Svg| s.get_scale());
+}
+
+/// Shapes are drawn on `this.canvas`, whose methods change it in place
+fn register_canvas(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<Canvas>("Canvas")
+        .register_fn("clear", |c: &mut Canvas| c.clear())
+        .register_fn("set_color", |c: &mut Canvas, color: Vec3| { c.set_color(color); })
+        .register_fn("set_opacity", |c: &mut Canvas, opacity: FLOAT| { c.set_opacity(opacity); })
+        .register_fn("linear_gradient", |c: &mut Canvas, start: Vec2, end: Vec2, start_color: Vec3, end_color: Vec3| {
+            c.linear_gradient(start, end, start_color, end_color);
+        })
+        .register_fn("radial_gradient", |c: &mut Canvas, center: Vec2, radius: FLOAT, inner_color: Vec3, outer_color: Vec3| {
+            c.radial_gradient(center, radius, inner_color, outer_color);
+        })
+        .register_fn("set_stroke_width", |c: &mut Canvas, width: FLOAT| { c.set_stroke_width(width); })
+        .register_fn("begin_path", |c: &mut Canvas| { c.begin_path(); })
+        .register_fn("move_to", |c: &mut Canvas, to: Vec2| { c.move_to(to); })
+        .register_fn("line_to", |c: &mut Canvas, to: Vec2| { c.line_to(to); })
+        .register_fn("quadratic_to", |c: &mut Canvas, control: Vec2, to: Vec2| { c.quadratic_to(control, to); })
+        .register_fn("cubic_to", |c: &mut Canvas, control1: Vec2, control2: Vec2, to: Vec2| {
+            c.cubic_to(control1, control2, to);
+        })
+        .register_fn("arc", |c: &mut Canvas, center: Vec2, radius: FLOAT, start: FLOAT, end: FLOAT| {
+            c.arc(center, radius, start, end);
+        })
+        .register_fn("close_path", |c: &mut Canvas| { c.close_path(); })
+        .register_fn("fill", |c: &mut Canvas| { c.fill(); })
+        .register_fn("stroke", |c: &mut Canvas| { c.stroke(); })
+        .register_fn("polyline", |c: &mut Canvas, points: Array| -> Result<(), Box<EvalAltResult>> {
+            c.polyline(&to_points(points)?);
+            Ok(())
+        })
+        .register_fn("polygon", |c: &mut Canvas, points: Array| -> Result<(), Box<EvalAltResult>> {
+            c.polygon(&to_points(points)?);
+            Ok(())
+        });
+}
+
+/// Fonts are files relative to the script, or the name of a font installed on
+/// the system. Texts are placed like drawings, from the top of their first line
+fn register_texts(engine: &mut Engine, directory: PathBuf, loaded: Rc<RefCell<Loaded>>) {
+    engine
+        .register_type_with_name::<Font>("Font")
+        .register_fn("font", move |name: &str| -> Result<Font, Box<EvalAltResult>> {
+            let path = directory.join(name);
+            let (font, is_file) = super::load_font(&path, name)?;
+            if is_file {
+                loaded.borrow_mut().files.push(path);
+            }
+            Ok(font)
+        });
+    engine
+        .register_type_with_name::<Text>("Text")
+        .register_fn("text", |font: Font, content: &str| Text::new(font, content))
+        .register_get("content", |t: &mut Text| t.content().to_string())
+        .register_fn("set_content", |t: &mut Text, content: &str| { t.set_content(content); })
+        .register_fn("set_font", |t: &mut Text, font: Font| { t.set_font(font); })
+        .register_fn("translate", |t: &mut Text, v: Vec2| { t.translate(v); })
+        .register_fn("set_position", |t: &mut Text, v: Vec2| { t.set_position(v); })
+        .register_fn("set_size", |t: &mut Text, size: FLOAT| { t.set_size(size); })
+        .register_fn("set_color", |t: &mut Text, color: Vec3| { t.set_color(color); })
+        .register_fn("set_opacity", |t: &mut Text, opacity: FLOAT| { t.set_opacity(opacity); })
+        .register_fn("set_align", |t: &mut Text, align: &str| -> Result<(), Box<EvalAltResult>> {
+            t.set_align(match align {
+                "left" => Align::Left,
+                "center" => Align::Center,
+                "right" => Align::Right,
+                _ => return Err(format!("unknown alignment {}", align).into())
+            });
+            Ok(())
+        })
+        .register_fn("set_max_width", |t: &mut Text, width: FLOAT| { t.set_max_width(Some(width)); })
+        .register_fn("reset_max_width", |t: &mut Text| { t.set_max_width(None); })
+        .register_fn("get_position", |t: &mut Text| t.get_position());
+}
+
+fn to_points(points: Array) -> Result<Vec<Vec2>, Box<EvalAltResult>> {
+    points.into_iter()
+        .map(|p| p.try_cast::<Vec2>().ok_or_else(|| "points should be vec2".into()))
+        .collect()
+}
+
+/// Instances are copied when indexed, and written back when changed through
+/// the index, so `this.instanced[0][i].set_color(...)` works as expected
+fn register_instances(engine: &mut Engine, directory: PathBuf, loaded: Rc<RefCell<Loaded>>) {
+    engine
+        .register_type_with_name::<InstancedModel>("InstancedModel")
+        .register_fn("instanced", move |path: &str, count: INT| -> Result<InstancedModel, Box<EvalAltResult>> {
+            let path = directory.join(path);
+            let model = super::load_instanced_model(&path, count.max(0) as usize)?;
+            loaded.borrow_mut().files.push(path);
+            Ok(model)
+        })
+        .register_fn("len", |m: &mut InstancedModel| m.instances().len() as INT)
+        .register_fn("resize", |m: &mut InstancedModel, count: INT| {
+            m.instances_mut().resize(count.max(0) as usize, Instance::default());
+        })
+        .register_indexer_get_set(
+            |m: &mut InstancedModel, i: INT| -> Result<Instance, Box<EvalAltResult>> {
+                usize::try_from(i).ok()
+                    .and_then(|i| m.instances().get(i).cloned())
+                    .ok_or_else(|| format!("no instance {}", i).into())
+            },
+            |m: &mut InstancedModel, i: INT, instance: Instance| -> Result<(), Box<EvalAltResult>> {
+                let slot = usize::try_from(i).ok()
+                    .and_then(|i| m.instances_mut().get_mut(i))
+                    .ok_or_else(|| format!("no instance {}", i))?;
+                *slot = instance;
+                Ok(())
+            }
+        )
+        .register_fn("set_material", |m: &mut InstancedModel, material: &str| { m.set_material(Some(material.into())); })
+        .register_fn("reset_material", |m: &mut InstancedModel| { m.set_material(None); })
+        .register_fn("set_texture", |m: &mut InstancedModel, texture: Arc<Texture>| { m.set_texture(Some(texture)); })
+        .register_fn("reset_texture", |m: &mut InstancedModel| { m.set_texture(None); });
+
+    engine
+        .register_type_with_name::<Instance>("Instance")
+        .register_fn("rotate", |m: &mut Instance, radians: FLOAT, axis: Vec3| { m.rotate(radians, axis.normalize()); })
+        .register_fn("translate", |m: &mut Instance, v: Vec3| { m.translate(v); })
+        .register_fn("set_position", |m: &mut Instance, v: Vec3| { m.set_position(v); })
+        .register_fn("zero_rotation", |m: &mut Instance| { m.zero_rotation(); })
+        .register_fn("scale", |m: &mut Instance, v: Vec3| { m.scale(v); })
+        .register_fn("reset_scaling", |m: &mut Instance| { m.reset_scaling(); })
+        .register_fn("set_color", |m: &mut Instance, color: Vec3| { m.set_color(color); })
+        .register_fn("set_emissive", |m: &mut Instance, color: Vec3| { m.set_emissive(color); })
+        .register_fn("set_opacity", |m: &mut Instance, opacity: FLOAT| { m.set_opacity(opacity); })
+        .register_fn("get_position", |m: &mut Instance| m.get_position())
+        .register_fn("get_scale", |m: &mut Instance| m.get_scale());
+}
+
+/// Materials and effects are compiled once the script has been run, so they
+/// should be declared when loading rather than in `update`
+fn register_shaders(engine: &mut Engine, directory: PathBuf, loaded: Rc<RefCell<Loaded>>) {
+    let shader_path = move |path: &str| -> Result<PathBuf, Box<EvalAltResult>> {
+        let path = directory.join(path);
+        if !path.is_file() {
+            return Err(format!("{} does not exist", path.display()).into())
+        }
+        Ok(path)
+    };
+    let effect_path = shader_path.clone();
+    let effect_loaded = loaded.clone();
+    engine
+        // Returns the path models are given to be drawn with the material
+        .register_fn("material", move |path: &str| -> Result<String, Box<EvalAltResult>> {
+            let path = shader_path(path)?;
+            let mut loaded = loaded.borrow_mut();
+            loaded.files.push(path.clone());
+            loaded.materials.push(path.clone());
+            Ok(path.to_string_lossy().into_owned())
+        })
+        .register_fn("effect", move |path: &str| -> Result<(), Box<EvalAltResult>> {
+            let path = effect_path(path)?;
+            let mut loaded = effect_loaded.borrow_mut();
+            loaded.files.push(path.clone());
+            loaded.effects.push(path);
+            Ok(())
+        });
+}
+
+/// Fields of view are in degrees, like in RON scenes, while orbiting is in
+/// radians like rotations
+fn register_camera(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<Camera>("Camera")
+        .register_fn("perspective_camera", |fov: FLOAT| Camera::perspective(fov))
+        .register_fn("orthographic_camera", |height: FLOAT| Camera::orthographic(height))
+        .register_fn("set_perspective", |c: &mut Camera, fov: FLOAT| c.projection = Projection::Perspective(fov))
+        .register_fn("set_orthographic", |c: &mut Camera, height: FLOAT| c.projection = Projection::Orthographic(height))
+        .register_fn("look_at", |c: &mut Camera, position: Vec3, target: Vec3| { c.look_at(position, target); })
+        .register_fn("orbit", |c: &mut Camera, yaw: FLOAT, pitch: FLOAT| { c.orbit(yaw, pitch); })
+        .register_get_set(
+            "position",
+            |c: &mut Camera| Vec3::from(c.position),
+            |c: &mut Camera, position: Vec3| c.position = position.into()
+        )
+        .register_get_set(
+            "target",
+            |c: &mut Camera| Vec3::from(c.target),
+            |c: &mut Camera, target: Vec3| c.target = target.into()
+        )
+        .register_get_set(
+            "up",
+            |c: &mut Camera| Vec3::from(c.up),
+            |c: &mut Camera, up: Vec3| c.up = up.into()
+        )
+        .register_get_set(
+            "distance",
+            |c: &mut Camera| c.distance(),
+            |c: &mut Camera, distance: FLOAT| { c.set_distance(distance); }
+        )
+        .register_get_set("near", |c: &mut Camera| c.near, |c: &mut Camera, near: FLOAT| c.near = near)
+        .register_get_set("far", |c: &mut Camera| c.far, |c: &mut Camera, far: FLOAT| c.far = far);
+}
+
+fn register_lights(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<AmbientLight>("AmbientLight")
+        .register_fn("ambient_light", |color: Vec3, intensity: FLOAT| {
+            AmbientLight { color: color.into(), intensity }
+        })
+        .register_get_set(
+            "color",
+            |l: &mut AmbientLight| Vec3::from(l.color),
+            |l: &mut AmbientLight, color: Vec3| l.color = color.into()
+        )
+        .register_get_set(
+            "intensity",
+            |l: &mut AmbientLight| l.intensity,
+            |l: &mut AmbientLight, intensity: FLOAT| l.intensity = intensity
+        );
+
+    engine
+        .register_type_with_name::<DirectionalLight>("DirectionalLight")
+        .register_fn("directional_light", |position: Vec3, color: Vec3, intensity: FLOAT| {
+            DirectionalLight { position: position.into(), color: color.into(), intensity }
+        })
+        .register_get_set(
+            "position",
+            |l: &mut DirectionalLight| Vec3::from(l.position),
+            |l: &mut DirectionalLight, position: Vec3| l.position = position.into()
+        )
+        .register_get_set(
+            "color",
+            |l: &mut DirectionalLight| Vec3::from(l.color),
+            |l: &mut DirectionalLight, color: Vec3| l.color = color.into()
+        )
+        .register_get_set(
+            "intensity",
+            |l: &mut DirectionalLight| l.intensity,
+            |l: &mut DirectionalLight, intensity: FLOAT| l.intensity = intensity
+        );
+}
+
+fn register_attributes(engine: &mut Engine) {
+    // Spectrums are copied into arrays whenever they are accessed, so scripts
+    // should keep them in a variable rather than access them repeatedly
+    engine
+        .register_type_with_name::<Spectrum>("Spectrum")
+        .register_fn("len", |s: &mut Spectrum| s.decibels.len() as INT)
+        .register_get("decibels", |s: &mut Spectrum| to_array(&s.decibels))
+        .register_get("magnitudes", |s: &mut Spectrum| to_array(&s.magnitudes))
+        .register_get("frequencies", |s: &mut Spectrum| to_array(&s.frequencies));
+
+    engine
+        .register_type_with_name::<RealtimeAttributes>("RealtimeAttributes")
+        .register_get("spectrum", |a: &mut RealtimeAttributes| a.spectrum.clone())
+        .register_get("waveform", |a: &mut RealtimeAttributes| to_array(&a.waveform))
+        .register_get("timestamp", |a: &mut RealtimeAttributes| a.timestamp.as_secs_f32())
+        .register_get("beat", |a: &mut RealtimeAttributes| a.beat)
+        .register_get("onset_strength", |a: &mut RealtimeAttributes| a.onset_strength)
+        .register_get("bpm", |a: &mut RealtimeAttributes| a.bpm)
+        .register_get("beat_phase", |a: &mut RealtimeAttributes| a.beat_phase)
+        .register_get("momentary_loudness", |a: &mut RealtimeAttributes| a.momentary_loudness)
+        .register_get("short_term_loudness", |a: &mut RealtimeAttributes| a.short_term_loudness)
+        .register_get("integrated_loudness", |a: &mut RealtimeAttributes| a.integrated_loudness)
+        .register_get("true_peak", |a: &mut RealtimeAttributes| a.true_peak)
+        .register_get("gain", |a: &mut RealtimeAttributes| a.gain);
+
+    // The analysis is unit until it is available, and so are the tags and the
+    // cover art the track does not have
+    engine
+        .register_type_with_name::<GeneralAttributes>("GeneralAttributes")
+        .register_get("duration", |a: &mut GeneralAttributes| a.duration.as_secs_f32())
+        .register_get("track_index", |a: &mut GeneralAttributes| a.track_index as INT)
+        .register_get("track_changed", |a: &mut GeneralAttributes| a.track_changed)
+        .register_get("title", |a: &mut GeneralAttributes| a.title.clone())
+        .register_get("artist", |a: &mut GeneralAttributes| {
+            a.metadata.artist.clone().map(Dynamic::from).unwrap_or(Dynamic::UNIT)
+        })
+        .register_get("album", |a: &mut GeneralAttributes| {
+            a.metadata.album.clone().map(Dynamic::from).unwrap_or(Dynamic::UNIT)
+        })
+        .register_get("track_number", |a: &mut GeneralAttributes| {
+            a.metadata.track_number.map(|n| Dynamic::from(n as INT)).unwrap_or(Dynamic::UNIT)
+        })
+        .register_get("track_gain", |a: &mut GeneralAttributes| {
+            a.metadata.track_gain.map(Dynamic::from).unwrap_or(Dynamic::UNIT)
+        })
+        .register_get("track_peak", |a: &mut GeneralAttributes| {
+            a.metadata.track_peak.map(Dynamic::from).unwrap_or(Dynamic::UNIT)
+        })
+        .register_get("album_gain", |a: &mut GeneralAttributes| {
+            a.metadata.album_gain.map(Dynamic::from).unwrap_or(Dynamic::UNIT)
+        })
+        .register_get("album_peak", |a: &mut GeneralAttributes| {
+            a.metadata.album_peak.map(Dynamic::from).unwrap_or(Dynamic::UNIT)
+        })
+        .register_get("cover", |a: &mut GeneralAttributes| {
+            a.metadata.cover.clone().map(Dynamic::from).unwrap_or(Dynamic::UNIT)
+        })
+        .register_get("analysis", |a: &mut GeneralAttributes| {
+            a.analysis.clone().map(Dynamic::from).unwrap_or(Dynamic::UNIT)
+        });
+
+    engine
+        .register_type_with_name::<Arc<TrackAnalysis>>("TrackAnalysis")
+        .register_get("peak", |a: &mut Arc<TrackAnalysis>| a.peak)
+        .register_get("rms", |a: &mut Arc<TrackAnalysis>| a.rms)
+        .register_get("integrated_loudness", |a: &mut Arc<TrackAnalysis>| a.integrated_loudness)
+        .register_get("bpm", |a: &mut Arc<TrackAnalysis>| a.bpm)
+        .register_get("key", |a: &mut Arc<TrackAnalysis>| {
+            a.key.as_ref().map(|k| Dynamic::from(k.to_string())).unwrap_or(Dynamic::UNIT)
+        })
+        .register_get("sections", |a: &mut Arc<TrackAnalysis>| {
+            a.sections.iter().map(|s| Dynamic::from(s.as_secs_f32())).collect::<Array>()
+        })
+        .register_fn("loudness_at", |a: &mut Arc<TrackAnalysis>, t: FLOAT| -> Result<FLOAT, Box<EvalAltResult>> {
+            Ok(a.loudness_at(seconds(t)?))
+        })
+        .register_fn("section_at", |a: &mut Arc<TrackAnalysis>, t: FLOAT| -> Result<INT, Box<EvalAltResult>> {
+            Ok(a.section_at(seconds(t)?) as INT)
+        })
+        .register_fn("next_section", |a: &mut Arc<TrackAnalysis>, t: FLOAT| -> Result<Dynamic, Box<EvalAltResult>> {
+            Ok(a.next_section(seconds(t)?).map(|s| Dynamic::from(s.as_secs_f32())).unwrap_or(Dynamic::UNIT))
+        });
+}
+
+fn register_bands(engine: &mut Engine) {
+    engine
+        .register_type_with_name::<BandMapper>("BandMapper")
+        .register_fn(
+            "band_mapper",
+            |scale: &str, count: INT, min: FLOAT, max: FLOAT| -> Result<BandMapper, Box<EvalAltResult>> {
+                let count = count.max(1) as usize;
+                let scale = match scale {
+                    "logarithmic" => BandScale::Logarithmic(count),
+                    "mel" => BandScale::Mel(count),
+                    "bark" => BandScale::Bark(count),
+                    "third_octave" => BandScale::ThirdOctave,
+                    _ => return Err(format!("unknown band scale {}", scale).into())
+                };
+                Ok(BandMapper::new(scale).frequency_range(min, max))
+            }
+        )
+        .register_fn("smoothing", |b: BandMapper, attack: FLOAT, release: FLOAT| b.smoothing(attack, release))
+        .register_fn("rms", |b: BandMapper| b.aggregation(Aggregation::Rms))
+        .register_fn("map", |b: &mut BandMapper, s: Spectrum| to_array(b.map(&s)))
+        .register_fn("levels", |b: &mut BandMapper, floor: FLOAT| to_array(&b.levels(floor)))
+        .register_get("edges", |b: &mut BandMapper| to_array(b.edges()));
+}
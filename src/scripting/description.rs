@@ -0,0 +1,539 @@
+//! Scenes described in [RON](https://github.com/ron-rs/ron) files, for
+//! visualisers simple enough not to need any code.
+//!
+//! Models are repeated `count` times, each instance being moved by `spacing`
+//! from the previous one, and all of them drawn in a single call. Bindings then
+//! set a component of their transform or color to `offset + factor * source`
+//! every frame, `Band` being the band of the same index as the instance. Models
+//! can also be drawn with a `texture` and a `material`, and `effects` drawn
+//! over the frame, materials and effects being GLSL fragment shaders. The
+//! `camera` can be bound the same way with `camera_bindings`, `Band` being the
+//! first band. `svgs` are drawn over everything else, where they are placed,
+//! and `texts` over them. The font of a text is a file relative to the scene or
+//! the name of a font installed on the system, and `{title}`, `{artist}`,
+//! `{album}`, `{elapsed}` and `{duration}` in its content are replaced by those
+//! of the track being played:
+//!
+//! ```ron
+//! SceneDescription(
+//!     bands: (scale: Logarithmic(64)),
+//!     models: [(
+//!         file: "cube.obj",
+//!         count: 64,
+//!         position: (-1.0, 0.0, -0.5),
+//!         spacing: (0.03, 0.0, 0.0),
+//!         scale: (0.015, 0.0, 0.001),
+//!         bindings: [(target: ScaleY, source: Band, factor: 2.0, smoothing: 0.6)]
+//!     )],
+//!     ambient: (color: (1.0, 1.0, 1.0), intensity: 0.5),
+//!     camera: (projection: Perspective(60.0), position: (0.0, 0.5, 1.5)),
+//!     camera_bindings: [(target: Yaw, source: Time, factor: 0.1)],
+//!     svgs: [(file: "logo.svg", position: (-1.2, -0.8), scale: (0.2, 0.2))],
+//!     texts: [(font: "DejaVu Sans", content: "{title}\n{elapsed} / {duration}", position: (-1.7, 0.7))]
+//! )
+//! ```
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use glm::vec3;
+use serde::Deserialize;
+use crate::audio::{GeneralAttributes, RealtimeAttributes};
+use crate::audio::signal_processing::{Aggregation, BandMapper, BandScale};
+use crate::graphics::{AmbientLight, DirectionalLight};
+use crate::graphics::camera::{Camera, Projection};
+use crate::graphics::text::{Align, Text};
+use crate::resource_pool::font_loader::Font;
+use crate::resource_pool::instanced_model::{Instance, InstancedModel};
+use crate::resource_pool::model_loader::Model;
+use crate::resource_pool::ResourcePool;
+use crate::resource_pool::svg_loader::Svg;
+use crate::resource_pool::texture_loader::Texture;
+use crate::Scene;
+
+#[derive(Deserialize)]
+struct SceneDescription {
+    #[serde(default)]
+    bands: BandsDescription,
+    models: Vec<ModelDescription>,
+    #[serde(default)]
+    ambient: AmbientLight,
+    #[serde(default)]
+    directionals: Vec<DirectionalLight>,
+    #[serde(default)]
+    camera: Camera,
+    #[serde(default)]
+    camera_bindings: Vec<Binding<CameraTarget>>,
+    /// Paths of effects drawn over the frame, in order
+    #[serde(default)]
+    effects: Vec<String>,
+    #[serde(default)]
+    svgs: Vec<SvgDescription>,
+    #[serde(default)]
+    texts: Vec<TextDescription>
+}
+
+/// How the spectrum is split into the bands used by `Band` bindings
+#[derive(Deserialize)]
+#[serde(default)]
+struct BandsDescription {
+    scale: BandScale,
+    aggregation: Aggregation,
+    min_frequency: f32,
+    max_frequency: f32,
+    /// Decibels mapped to 0, as well as for `Intensity` and `Loudness`
+    floor: f32
+}
+impl Default for BandsDescription {
+    fn default() -> Self {
+        BandsDescription {
+            scale: BandScale::Logarithmic(32),
+            aggregation: Aggregation::Peak,
+            min_frequency: 30.0,
+            max_frequency: 16000.0,
+            floor: -60.0
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ModelDescription {
+    /// Path of an OBJ or glTF file, relative to the scene file
+    file: String,
+    #[serde(default = "one")]
+    count: usize,
+    #[serde(default)]
+    position: [f32; 3],
+    /// Added to the position of each instance relative to the previous one
+    #[serde(default)]
+    spacing: [f32; 3],
+    /// Angles around the x, y and z axis, in radians
+    #[serde(default)]
+    rotation: [f32; 3],
+    #[serde(default = "ones")]
+    scale: [f32; 3],
+    #[serde(default = "ones")]
+    color: [f32; 3],
+    #[serde(default)]
+    emissive: [f32; 3],
+    #[serde(default = "opaque")]
+    opacity: f32,
+    /// Path of a fragment shader to draw the model with
+    #[serde(default)]
+    material: Option<String>,
+    /// Path of a PNG or JPEG file the colors of the model are multiplied by
+    #[serde(default)]
+    texture: Option<String>,
+    #[serde(default)]
+    bindings: Vec<Binding<Target>>
+}
+
+/// A drawing placed over the frame, which spans [-1, 1] vertically
+#[derive(Deserialize)]
+struct SvgDescription {
+    /// Path of an SVG file, relative to the scene file
+    file: String,
+    #[serde(default)]
+    position: [f32; 2],
+    /// Angle in radians
+    #[serde(default)]
+    rotation: f32,
+    #[serde(default = "ones")]
+    scale: [f32; 2],
+    #[serde(default = "ones")]
+    color: [f32; 3],
+    #[serde(default = "opaque")]
+    opacity: f32
+}
+
+/// Text placed over the frame like drawings, from the top of its first line
+#[derive(Deserialize)]
+struct TextDescription {
+    /// Path of a TrueType or OpenType file relative to the scene file, or name
+    /// of a font installed on the system
+    font: String,
+    /// Drawn with its placeholders replaced every frame
+    content: String,
+    #[serde(default)]
+    position: [f32; 2],
+    /// Height of the em square
+    #[serde(default = "text_size")]
+    size: f32,
+    #[serde(default = "ones")]
+    color: [f32; 3],
+    #[serde(default = "opaque")]
+    opacity: f32,
+    #[serde(default = "left")]
+    align: Align,
+    /// Width lines are wrapped at
+    #[serde(default)]
+    max_width: Option<f32>
+}
+
+fn text_size() -> f32 { 0.1 }
+fn left() -> Align { Align::Left }
+
+fn one() -> usize { 1 }
+fn ones<const N: usize>() -> [f32; N] { [1.0; N] }
+fn opaque() -> f32 { 1.0 }
+
+#[derive(Deserialize)]
+struct Binding<T> {
+    target: T,
+    source: Source,
+    #[serde(default = "factor")]
+    factor: f32,
+    #[serde(default)]
+    offset: f32,
+    /// Share of the previous value kept every frame, in [0, 1)
+    #[serde(default)]
+    smoothing: f32
+}
+
+fn factor() -> f32 { 1.0 }
+
+impl<T> Binding<T> {
+    /// Moves the smoothed value of the binding towards its source, for the
+    /// instance of the given index, and returns it
+    fn follow(&self, value: &mut Option<f32>, sources: &Sources, instance: usize) -> f32 {
+        let target = self.offset + self.factor * sources.get(self.source, instance);
+        let smoothed = match *value {
+            Some(previous) => previous * self.smoothing + target * (1.0 - self.smoothing),
+            None => target
+        };
+        *value = Some(smoothed);
+        smoothed
+    }
+}
+
+/// Components of a model bindings can set
+#[derive(Clone, Copy, Deserialize)]
+enum Target {
+    PositionX, PositionY, PositionZ,
+    RotationX, RotationY, RotationZ,
+    /// Scales all three axis at once
+    Scale,
+    ScaleX, ScaleY, ScaleZ,
+    ColorR, ColorG, ColorB,
+    EmissiveR, EmissiveG, EmissiveB,
+    Opacity
+}
+
+/// Components of the camera bindings can set
+#[derive(Clone, Copy, Deserialize)]
+enum CameraTarget {
+    /// Radians the camera orbits around its target, horizontally
+    Yaw,
+    /// Radians the camera orbits around its target, towards the up vector
+    Pitch,
+    /// Distance between the camera and its target
+    Distance,
+    /// Vertical field of view in degrees, making the camera a perspective one
+    Fov
+}
+
+/// Values bindings follow
+#[derive(Clone, Copy, Deserialize)]
+enum Source {
+    /// Level of the band of the same index as the instance, in [0, 1]
+    Band,
+    /// Average level of the whole spectrum, in [0, 1]
+    Intensity,
+    /// Momentary loudness, in [0, 1]
+    Loudness,
+    OnsetStrength,
+    /// 1 on the frame a beat happens, 0 otherwise
+    Beat,
+    BeatPhase,
+    /// How much of the track was played, in [0, 1]
+    Progress,
+    /// Index of the section of the track being played
+    Section,
+    /// Seconds since the track started
+    Time
+}
+
+/// Transform and color of an instance, before being applied to it
+struct Appearance {
+    position: [f32; 3],
+    rotation: [f32; 3],
+    scale: [f32; 3],
+    color: [f32; 3],
+    emissive: [f32; 3],
+    opacity: f32
+}
+impl Appearance {
+    fn set(&mut self, target: Target, value: f32) {
+        match target {
+            Target::PositionX => self.position[0] = value,
+            Target::PositionY => self.position[1] = value,
+            Target::PositionZ => self.position[2] = value,
+            Target::RotationX => self.rotation[0] = value,
+            Target::RotationY => self.rotation[1] = value,
+            Target::RotationZ => self.rotation[2] = value,
+            Target::Scale => self.scale = [value; 3],
+            Target::ScaleX => self.scale[0] = value,
+            Target::ScaleY => self.scale[1] = value,
+            Target::ScaleZ => self.scale[2] = value,
+            Target::ColorR => self.color[0] = value,
+            Target::ColorG => self.color[1] = value,
+            Target::ColorB => self.color[2] = value,
+            Target::EmissiveR => self.emissive[0] = value,
+            Target::EmissiveG => self.emissive[1] = value,
+            Target::EmissiveB => self.emissive[2] = value,
+            Target::Opacity => self.opacity = value
+        }
+    }
+
+    fn apply(&self, instance: &mut Instance) {
+        instance.set_position(vec3(self.position[0], self.position[1], self.position[2]));
+        instance.zero_rotation();
+        for (axis, &angle) in self.rotation.iter().enumerate() {
+            if angle != 0.0 {
+                let mut v = vec3(0.0, 0.0, 0.0);
+                v[axis] = 1.0;
+                instance.rotate(angle, v);
+            }
+        }
+        instance.reset_scaling();
+        instance.scale(vec3(self.scale[0], self.scale[1], self.scale[2]));
+        instance.set_color(vec3(self.color[0], self.color[1], self.color[2]));
+        instance.set_emissive(vec3(self.emissive[0], self.emissive[1], self.emissive[2]));
+        instance.set_opacity(self.opacity);
+    }
+}
+
+/// Loads a scene from a RON file, paths being relative to it
+pub fn load(path: &Path) -> Result<Scene, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let description: SceneDescription = ron::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let existing = |file: &str| {
+        let file = directory.join(file);
+        if file.is_file() { Ok(file) } else { Err(format!("{} does not exist", file.display())) }
+    };
+
+    let mut files = vec![path.to_path_buf()];
+    let mut pool: ResourcePool<Model> = Default::default();
+    let mut texture_pool: ResourcePool<Arc<Texture>> = Default::default();
+    let mut instanced = vec![];
+    let mut materials = vec![];
+    for group in &description.models {
+        if pool.get(&group.file).is_none() {
+            let file = existing(&group.file)?;
+            pool.load(&group.file, file.to_str().ok_or("invalid model path")?)?;
+            files.push(file);
+        }
+        let material = group.material.as_deref().map(existing).transpose()?;
+        if let Some(material) = &material {
+            if !materials.contains(material) {
+                materials.push(material.clone());
+                files.push(material.clone());
+            }
+        }
+        let mut model = InstancedModel::new(pool.get(&group.file).unwrap().mesh().clone(), group.count);
+        model.set_material(material);
+        // Models keep the texture their file came with unless given another
+        match &group.texture {
+            Some(texture) => {
+                if texture_pool.get(texture).is_none() {
+                    let file = existing(texture)?;
+                    texture_pool.load(texture, file.to_str().ok_or("invalid texture path")?)?;
+                    files.push(file);
+                }
+                model.set_texture(texture_pool.get_copy(texture));
+            },
+            None => { model.set_texture(pool.get(&group.file).unwrap().texture().cloned()); }
+        }
+        instanced.push(model);
+    }
+    let effects = description.effects.iter().map(|e| existing(e)).collect::<Result<Vec<_>, _>>()?;
+    files.extend(effects.iter().cloned());
+
+    let mut svg_pool: ResourcePool<Svg> = Default::default();
+    let mut svgs = vec![];
+    for description in &description.svgs {
+        if svg_pool.get(&description.file).is_none() {
+            let file = existing(&description.file)?;
+            svg_pool.load(&description.file, file.to_str().ok_or("invalid SVG path")?)?;
+            files.push(file);
+        }
+        let mut svg = svg_pool.get_copy(&description.file).unwrap();
+        svg.set_position(description.position.into())
+            .rotate(description.rotation)
+            .scale(description.scale.into())
+            .set_color(description.color.into())
+            .set_opacity(description.opacity);
+        svgs.push(svg);
+    }
+
+    let mut font_pool: ResourcePool<Font> = Default::default();
+    let mut system_fonts = HashMap::new();
+    let mut texts = vec![];
+    for description in &description.texts {
+        let file = directory.join(&description.font);
+        let font = if file.is_file() {
+            if font_pool.get(&description.font).is_none() {
+                font_pool.load(&description.font, file.to_str().ok_or("invalid font path")?)?;
+                files.push(file);
+            }
+            font_pool.get_copy(&description.font).unwrap()
+        } else {
+            if !system_fonts.contains_key(&description.font) {
+                system_fonts.insert(description.font.clone(), Font::system(&description.font)?);
+            }
+            system_fonts[&description.font].clone()
+        };
+        let mut text = Text::new(font, &description.content);
+        text.set_position(description.position.into())
+            .set_size(description.size)
+            .set_color(description.color.into())
+            .set_opacity(description.opacity)
+            .set_align(description.align)
+            .set_max_width(description.max_width);
+        texts.push(text);
+    }
+    let contents: Vec<String> = description.texts.iter().map(|t| t.content.clone()).collect();
+
+    let bands = &description.bands;
+    let mapper = RefCell::new(
+        BandMapper::new(bands.scale)
+            .frequency_range(bands.min_frequency, bands.max_frequency)
+            .aggregation(bands.aggregation)
+    );
+    let floor = bands.floor;
+    let groups = description.models;
+    let camera = description.camera.clone();
+    let camera_bindings = description.camera_bindings;
+    let camera_values = RefCell::new(vec![None; camera_bindings.len()]);
+    // Smoothed value of each binding of each instance, once there is one
+    let values = RefCell::new(
+        groups.iter()
+            .flat_map(|g| (0..g.count).map(move |_| vec![None; g.bindings.len()]))
+            .collect::<Vec<Vec<Option<f32>>>>()
+    );
+
+    Ok(Scene {
+        main: Arc::new(move |scene, _, rta, ga| {
+            let levels = {
+                let mut mapper = mapper.borrow_mut();
+                mapper.map(&rta.spectrum);
+                mapper.levels(floor)
+            };
+            let sources = Sources::new(&rta, &ga, levels, floor);
+            let mut values = values.borrow_mut();
+
+            let mut values = values.iter_mut();
+            for (group, model) in groups.iter().zip(scene.instanced.iter_mut()) {
+                let instances = model.instances_mut().iter_mut().zip(values.by_ref().take(group.count));
+                for (i, (instance, values)) in instances.enumerate() {
+                    let mut appearance = Appearance {
+                        position: [0, 1, 2].map(|a| group.position[a] + group.spacing[a] * i as f32),
+                        rotation: group.rotation,
+                        scale: group.scale,
+                        color: group.color,
+                        emissive: group.emissive,
+                        opacity: group.opacity
+                    };
+                    for (binding, value) in group.bindings.iter().zip(values.iter_mut()) {
+                        appearance.set(binding.target, binding.follow(value, &sources, i));
+                    }
+                    appearance.apply(instance);
+                }
+            }
+
+            // Bindings move the camera from where it is described every frame
+            scene.camera = camera.clone();
+            let mut camera_values = camera_values.borrow_mut();
+            for (binding, value) in camera_bindings.iter().zip(camera_values.iter_mut()) {
+                let value = binding.follow(value, &sources, 0);
+                match binding.target {
+                    CameraTarget::Yaw => { scene.camera.orbit(value, 0.0); },
+                    CameraTarget::Pitch => { scene.camera.orbit(0.0, value); },
+                    CameraTarget::Distance => { scene.camera.set_distance(value); },
+                    CameraTarget::Fov => scene.camera.projection = Projection::Perspective(value)
+                }
+            }
+
+            for (text, content) in scene.texts.iter_mut().zip(&contents) {
+                let content = content
+                    .replace("{title}", &ga.title)
+                    .replace("{artist}", ga.metadata.artist.as_deref().unwrap_or_default())
+                    .replace("{album}", ga.metadata.album.as_deref().unwrap_or_default())
+                    .replace("{elapsed}", &clock(rta.timestamp))
+                    .replace("{duration}", &clock(ga.duration));
+                text.set_content(&content);
+            }
+        }),
+        camera: description.camera,
+        models: vec![],
+        instanced,
+        ambient: description.ambient,
+        directionals: description.directionals,
+        materials,
+        effects,
+        svgs,
+        canvas: Default::default(),
+        texts,
+        shadertoy: None,
+        files
+    })
+}
+
+/// Formats a duration as minutes and seconds
+fn clock(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Values of the sources for a frame
+struct Sources {
+    levels: Vec<f32>,
+    intensity: f32,
+    loudness: f32,
+    onset_strength: f32,
+    beat: f32,
+    beat_phase: f32,
+    progress: f32,
+    section: f32,
+    time: f32
+}
+impl Sources {
+    fn new(rta: &RealtimeAttributes, ga: &GeneralAttributes, levels: Vec<f32>, floor: f32) -> Self {
+        let level = |db: f32| (1.0 - db / floor).clamp(0.0, 1.0);
+        let decibels = &rta.spectrum.decibels;
+        let time = rta.timestamp.as_secs_f32();
+        Sources {
+            levels,
+            intensity: decibels.iter().map(|&db| level(db)).sum::<f32>() / decibels.len().max(1) as f32,
+            loudness: level(rta.momentary_loudness),
+            onset_strength: rta.onset_strength,
+            beat: if rta.beat { 1.0 } else { 0.0 },
+            beat_phase: rta.beat_phase,
+            progress: if ga.duration.is_zero() { 0.0 } else { time / ga.duration.as_secs_f32() },
+            section: ga.analysis.as_ref().map(|a| a.section_at(rta.timestamp)).unwrap_or_default() as f32,
+            time
+        }
+    }
+
+    /// Returns the value of a source for the instance of the given index
+    fn get(&self, source: Source, instance: usize) -> f32 {
+        match source {
+            Source::Band => {
+                let last = self.levels.len().saturating_sub(1);
+                self.levels.get(instance.min(last)).copied().unwrap_or_default()
+            },
+            Source::Intensity => self.intensity,
+            Source::Loudness => self.loudness,
+            Source::OnsetStrength => self.onset_strength,
+            Source::Beat => self.beat,
+            Source::BeatPhase => self.beat_phase,
+            Source::Progress => self.progress,
+            Source::Section => self.section,
+            Source::Time => self.time
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Scenes written in [Rhai](https://rhai.rs), described in RON files (see
+//! `description`) or made of a Shadertoy shader (see `shadertoy`).
+//!
+//! A scene script is run once when loaded, with a `scene` object map in scope
+//! whose `camera`, `models`, `instanced`, `ambient`, `directionals`, `svgs`,
+//! `canvas` and `texts` are what gets drawn. Anything else stored in `scene` is
+//! kept around for the script to use. Models repeated many times are best drawn
+//! instanced, every instance of `instanced(path, count)` being drawn in a
+//! single call.
+//!
+//! The script must also define an `update(dt, rta, ga)` function, which is
+//! called every frame with `this` bound to `scene`, its canvas being cleared
+//! beforehand so that shapes can be drawn on it anew.
+//!
+//! Materials and effects are GLSL fragment shaders (see
+//! `graphics::shader`), declared with `material` and `effect` when loading:
+//!
+//! ```rhai
+//! let glow = material("glow.frag");
+//! scene.models.push(model("cube.obj"));
+//! scene.models[0].set_material(glow);
+//! scene.ambient = ambient_light(vec3(1.0, 1.0, 1.0), 0.5);
+//! effect("vignette.frag");
+//!
+//! fn update(dt, rta, ga) {
+//!     this.models[0].rotate(dt, vec3(0.0, 1.0, 0.0));
+//!     this.camera.orbit(dt * 0.1, 0.0);
+//! }
+//! ```
+use std::cell::RefCell;
+use std::mem;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+use rhai::{Array, CallFnOptions, Dynamic, Map, Scope};
+use crate::graphics::{AmbientLight, DirectionalLight};
+use crate::graphics::camera::Camera;
+use crate::graphics::canvas::Canvas;
+use crate::graphics::text::Text;
+use crate::resource_pool::font_loader::Font;
+use crate::resource_pool::instanced_model::InstancedModel;
+use crate::resource_pool::model_loader::Model;
+use crate::resource_pool::svg_loader::Svg;
+use crate::resource_pool::texture_loader::Texture;
+use crate::Scene;
+
+mod bindings;
+mod description;
+mod shadertoy;
+
+/// Name of the function called every frame
+const UPDATE_FUNCTION: &str = "update";
+
+/// Loads a scene from a script, a `.ron` description or a `.glsl` Shadertoy
+/// shader, paths used by the scene being relative to it
+pub fn load(script_path: &str) -> Result<Scene, String> {
+    let script_path = Path::new(script_path);
+    match script_path.extension().and_then(|e| e.to_str()) {
+        Some("ron") => return description::load(script_path),
+        Some("glsl") => return shadertoy::load(script_path),
+        _ => ()
+    }
+    let directory = script_path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+    let loaded = Rc::new(RefCell::new(bindings::Loaded::default()));
+    let engine = bindings::engine(directory, loaded.clone());
+
+    let ast = engine.compile_file(script_path.into()).map_err(|e| e.to_string())?;
+    if !ast.iter_functions().any(|f| f.name == UPDATE_FUNCTION && f.params.len() == 3) {
+        return Err(format!("{} does not define {}(dt, rta, ga)", script_path.display(), UPDATE_FUNCTION))
+    }
+
+    let mut state = Map::new();
+    state.insert("camera".into(), Dynamic::from(Camera::default()));
+    state.insert("models".into(), Dynamic::from_array(vec![]));
+    state.insert("instanced".into(), Dynamic::from_array(vec![]));
+    state.insert("ambient".into(), Dynamic::from(AmbientLight::default()));
+    state.insert("directionals".into(), Dynamic::from_array(vec![]));
+    state.insert("svgs".into(), Dynamic::from_array(vec![]));
+    state.insert("canvas".into(), Dynamic::from(Canvas::new()));
+    state.insert("texts".into(), Dynamic::from_array(vec![]));
+    let mut scope = Scope::new();
+    scope.push("scene", state);
+    engine.run_ast_with_scope(&mut scope, &ast).map_err(|e| e.to_string())?;
+
+    let mut state = scope.remove::<Dynamic>("scene").unwrap();
+    let mut scene = Scene {
+        main: Arc::new(|_, _, _, _| ()),
+        camera: Default::default(),
+        models: vec![],
+        instanced: vec![],
+        ambient: Default::default(),
+        directionals: vec![],
+        materials: vec![],
+        effects: vec![],
+        svgs: vec![],
+        canvas: Default::default(),
+        texts: vec![],
+        shadertoy: None,
+        files: vec![script_path.to_path_buf()]
+    };
+    {
+        let mut loaded = loaded.borrow_mut();
+        scene.files.append(&mut loaded.files);
+        scene.materials.append(&mut loaded.materials);
+        scene.effects.append(&mut loaded.effects);
+    }
+    take_back(&mut state, &mut scene)?;
+
+    let state = RefCell::new(state);
+    let scope = RefCell::new(scope);
+    let last_error = RefCell::new(None);
+    scene.main = Arc::new(move |scene, dt, rta, ga| {
+        let mut state = state.borrow_mut();
+        lend(scene, &mut state);
+        let update = engine.call_fn_with_options::<Dynamic>(
+            CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut state),
+            &mut scope.borrow_mut(),
+            &ast,
+            UPDATE_FUNCTION,
+            (dt, rta, ga)
+        );
+        // Whatever the update did before failing is kept
+        let result = take_back(&mut state, scene).and(update.map(|_| ()).map_err(|e| e.to_string()));
+
+        // Errors are likely to happen every frame, so we only report new ones
+        let mut last_error = last_error.borrow_mut();
+        if let Err(e) = &result {
+            if last_error.as_ref() != Some(e) {
+                eprintln!("{}: {}", UPDATE_FUNCTION, e);
+            }
+        }
+        *last_error = result.err();
+    });
+
+    Ok(scene)
+}
+
+/// Copies the camera, models, lights and drawings of a scene into the script
+/// state, the scene keeping them until they are taken back
+fn lend(scene: &mut Scene, state: &mut Dynamic) {
+    let mut state = state.write_lock::<Map>().unwrap();
+    state.insert("camera".into(), Dynamic::from(scene.camera.clone()));
+    state.insert("models".into(), lend_array(&scene.models));
+    state.insert("instanced".into(), lend_array(&scene.instanced));
+    state.insert("ambient".into(), Dynamic::from(scene.ambient.clone()));
+    state.insert("directionals".into(), lend_array(&scene.directionals));
+    state.insert("svgs".into(), lend_array(&scene.svgs));
+    scene.canvas.clear();
+    state.insert("canvas".into(), Dynamic::from(mem::take(&mut scene.canvas)));
+    state.insert("texts".into(), lend_array(&scene.texts));
+}
+
+fn lend_array<T: Clone + Send + Sync + 'static>(elements: &[T]) -> Dynamic {
+    elements.iter().cloned().map(Dynamic::from).collect::<Array>().into()
+}
+
+/// Moves the camera, models, lights and drawings of the script state back into
+/// a scene, which is left as it was if any of them is invalid
+fn take_back(state: &mut Dynamic, scene: &mut Scene) -> Result<(), String> {
+    let mut state = state.write_lock::<Map>().ok_or("scene is not an object map anymore")?;
+    check::<Camera>(&state, "camera", "a camera")?;
+    check_array::<Model>(&state, "models", "models")?;
+    check_array::<InstancedModel>(&state, "instanced", "instanced models")?;
+    check::<AmbientLight>(&state, "ambient", "an ambient light")?;
+    check_array::<DirectionalLight>(&state, "directionals", "directional lights")?;
+    check_array::<Svg>(&state, "svgs", "SVG drawings")?;
+    check::<Canvas>(&state, "canvas", "a canvas")?;
+    check_array::<Text>(&state, "texts", "texts")?;
+
+    // Checked above, so none of these casts can fail
+    scene.camera = state["camera"].clone().cast::<Camera>();
+    scene.models = take_array(&mut state, "models");
+    scene.instanced = take_array(&mut state, "instanced");
+    scene.ambient = state["ambient"].clone().cast::<AmbientLight>();
+    scene.directionals = take_array(&mut state, "directionals");
+    scene.svgs = take_array(&mut state, "svgs");
+    scene.canvas = state.get_mut("canvas")
+        .and_then(|c| c.write_lock::<Canvas>().map(|mut c| mem::take(&mut *c)))
+        .unwrap_or_default();
+    scene.texts = take_array(&mut state, "texts");
+    Ok(())
+}
+
+fn check<T: Clone + 'static>(state: &Map, name: &str, kind: &str) -> Result<(), String> {
+    match state.get(name) {
+        Some(value) if value.is::<T>() => Ok(()),
+        _ => Err(format!("scene.{} should be {}", name, kind))
+    }
+}
+
+fn check_array<T: Clone + 'static>(state: &Map, name: &str, kind: &str) -> Result<(), String> {
+    let array = state.get(name)
+        .and_then(|a| a.read_lock::<Array>())
+        .ok_or(format!("scene.{} should be an array", name))?;
+    match array.iter().all(|d| d.is::<T>()) {
+        true => Ok(()),
+        false => Err(format!("scene.{} should only contain {}", name, kind))
+    }
+}
+
+/// Moves the elements of an array of the script state out of it, once checked
+fn take_array<T: Clone + 'static>(state: &mut Map, name: &str) -> Vec<T> {
+    let array = state.get_mut(name)
+        .and_then(|a| a.write_lock::<Array>().map(|mut a| mem::take(&mut *a)))
+        .unwrap_or_default();
+    array.into_iter().filter_map(|d| d.try_cast::<T>()).collect()
+}
+
+fn load_model(path: &Path) -> Result<Model, String> {
+    if !path.is_file() {
+        return Err(format!("{} does not exist", path.display()))
+    }
+    Model::new(path.to_str().ok_or("invalid model path")?).build()
+}
+
+fn load_svg(path: &Path) -> Result<Svg, String> {
+    if !path.is_file() {
+        return Err(format!("{} does not exist", path.display()))
+    }
+    Svg::load(path.to_str().ok_or("invalid SVG path")?)
+}
+
+/// Loads a font file if there is one at `path`, and otherwise the font
+/// installed on the system under `name`, returning whether it is a file
+fn load_font(path: &Path, name: &str) -> Result<(Font, bool), String> {
+    if path.is_file() {
+        Ok((Font::load(path.to_str().ok_or("invalid font path")?)?, true))
+    } else {
+        Ok((Font::system(name)?, false))
+    }
+}
+
+fn load_texture(path: &Path) -> Result<Texture, String> {
+    if !path.is_file() {
+        return Err(format!("{} does not exist", path.display()))
+    }
+    Texture::load(path.to_str().ok_or("invalid texture path")?)
+}
+
+fn load_instanced_model(path: &Path, count: usize) -> Result<InstancedModel, String> {
+    if !path.is_file() {
+        return Err(format!("{} does not exist", path.display()))
+    }
+    Model::new(path.to_str().ok_or("invalid model path")?).build_instanced(count)
+}
@@ -0,0 +1,95 @@
+//! Scenes made of a single [Shadertoy](https://www.shadertoy.com) shader, so
+//! that audio visualisers written there can be used as they are.
+//!
+//! Like on Shadertoy, `iChannel0` is a 512x2 texture whose first row is the
+//! spectrum up to 11kHz, and whose second row is the waveform.
+use std::cell::RefCell;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::audio::RealtimeAttributes;
+use crate::audio::signal_processing::DECIBEL_FLOOR;
+use crate::graphics::shader::{CHANNEL_DIMENSIONS, Shadertoy, ShadertoyUniforms};
+use crate::Scene;
+
+/// Frequency of the last texel of the spectrum, in Hz
+const SPECTRUM_MAX_FREQUENCY: f32 = 11025.0;
+/// Decibels mapped to 0 and 1 in the spectrum, as by the Web Audio analysers
+/// Shadertoy uses
+const SPECTRUM_RANGE: (f32, f32) = (-100.0, -30.0);
+/// Share of the previous magnitudes kept every frame, as on Shadertoy
+const SPECTRUM_SMOOTHING: f32 = 0.8;
+
+/// Loads a scene drawing the Shadertoy shader of the given file
+pub fn load(path: &Path) -> Result<Scene, String> {
+    if !path.is_file() {
+        return Err(format!("{} does not exist", path.display()))
+    }
+    let width = CHANNEL_DIMENSIONS[0] as usize;
+    let magnitudes = RefCell::new(vec![0.0; width]);
+
+    Ok(Scene {
+        main: Arc::new(move |scene, dt, rta, _| {
+            let shadertoy = scene.shadertoy.as_mut().unwrap();
+            let uniforms = &mut shadertoy.uniforms;
+            uniforms.time += dt;
+            uniforms.time_delta = dt;
+            uniforms.frame += 1;
+            uniforms.frame_rate = if dt > 0.0 { 1.0 / dt } else { 0.0 };
+            // The last bin of the spectrum is at the Nyquist frequency
+            uniforms.sample_rate = rta.spectrum.frequencies.last().map(|f| f * 2.0).unwrap_or_default();
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            uniforms.date[3] = (now.as_secs_f64() % 86400.0) as f32;
+
+            let mut magnitudes = magnitudes.borrow_mut();
+            fill_spectrum(&mut shadertoy.channel[..width], &mut magnitudes, &rta);
+            fill_waveform(&mut shadertoy.channel[width..], &rta.waveform);
+        }),
+        camera: Default::default(),
+        models: vec![],
+        instanced: vec![],
+        ambient: Default::default(),
+        directionals: vec![],
+        materials: vec![],
+        effects: vec![],
+        svgs: vec![],
+        canvas: Default::default(),
+        texts: vec![],
+        shadertoy: Some(Shadertoy {
+            path: path.to_path_buf(),
+            // The frame is counted up before the first one is drawn
+            uniforms: ShadertoyUniforms { frame: -1, ..Default::default() },
+            channel: vec![0; width * CHANNEL_DIMENSIONS[1] as usize]
+        }),
+        files: vec![path.to_path_buf()]
+    })
+}
+
+/// Smooths the magnitudes of the spectrum up to `SPECTRUM_MAX_FREQUENCY`, and
+/// writes them as bytes spanning `SPECTRUM_RANGE`
+fn fill_spectrum(texels: &mut [u8], magnitudes: &mut [f32], rta: &RealtimeAttributes) {
+    let spectrum = &rta.spectrum;
+    let resolution = spectrum.frequencies.get(1).copied().unwrap_or(1.0);
+    let (min, max) = SPECTRUM_RANGE;
+    let step = SPECTRUM_MAX_FREQUENCY / texels.len() as f32;
+    for (i, (texel, magnitude)) in texels.iter_mut().zip(magnitudes.iter_mut()).enumerate() {
+        let frequency = i as f32 * step;
+        let bin = (frequency / resolution).round() as usize;
+        let current = spectrum.magnitudes.get(bin).copied().unwrap_or_default();
+        *magnitude = SPECTRUM_SMOOTHING * *magnitude + (1.0 - SPECTRUM_SMOOTHING) * current;
+
+        let decibels = (20.0 * magnitude.log10()).max(DECIBEL_FLOOR);
+        *texel = ((decibels - min) / (max - min) * 255.0).clamp(0.0, 255.0) as u8;
+    }
+}
+
+/// Writes the last samples as bytes, silence being 128. Missing samples are
+/// considered silent
+fn fill_waveform(texels: &mut [u8], waveform: &[f32]) {
+    let samples = &waveform[waveform.len().saturating_sub(texels.len())..];
+    let (silent, heard) = texels.split_at_mut(texels.len() - samples.len());
+    silent.fill(128);
+    for (texel, sample) in heard.iter_mut().zip(samples) {
+        *texel = (128.0 + sample * 128.0).clamp(0.0, 255.0) as u8;
+    }
+}